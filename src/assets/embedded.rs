@@ -0,0 +1,34 @@
+/// Assets compiled into the binary, keyed by their path relative to `src/assets`.
+/// Anything the renderer needs to start must be listed here, so that the
+/// executable does not depend on the working directory it is launched from.
+pub static EMBEDDED_ASSETS: &[(&str, &[u8])] = &[
+    ("shaders/player.wgsl", include_bytes!("shaders/player.wgsl")),
+    ("shaders/gbuffer.wgsl", include_bytes!("shaders/gbuffer.wgsl")),
+    ("shaders/deferred_lighting.wgsl", include_bytes!("shaders/deferred_lighting.wgsl")),
+    ("shaders/screen_quad.wgsl", include_bytes!("shaders/screen_quad.wgsl")),
+    ("shaders/gpu_cull.wgsl", include_bytes!("shaders/gpu_cull.wgsl")),
+    ("shaders/debug_draw.wgsl", include_bytes!("shaders/debug_draw.wgsl")),
+    ("shaders/grid.wgsl", include_bytes!("shaders/grid.wgsl")),
+    ("shaders/outline_mask.wgsl", include_bytes!("shaders/outline_mask.wgsl")),
+    ("shaders/outline_jfa.wgsl", include_bytes!("shaders/outline_jfa.wgsl")),
+    ("shaders/outline_composite.wgsl", include_bytes!("shaders/outline_composite.wgsl")),
+    ("shaders/debug_view.wgsl", include_bytes!("shaders/debug_view.wgsl")),
+    ("shaders/debug_overdraw.wgsl", include_bytes!("shaders/debug_overdraw.wgsl")),
+    ("shaders/oit_composite.wgsl", include_bytes!("shaders/oit_composite.wgsl")),
+    ("shaders/gpu_driven.wgsl", include_bytes!("shaders/gpu_driven.wgsl")),
+    ("shaders/common/vertex_input.wgsl", include_bytes!("shaders/common/vertex_input.wgsl")),
+    ("shaders/common/camera.wgsl", include_bytes!("shaders/common/camera.wgsl")),
+    ("shaders/common/material.wgsl", include_bytes!("shaders/common/material.wgsl")),
+    ("shaders/common/lighting.wgsl", include_bytes!("shaders/common/lighting.wgsl")),
+    ("shaders/common/gpu_object.wgsl", include_bytes!("shaders/common/gpu_object.wgsl")),
+    ("shaders/common/skinning.wgsl", include_bytes!("shaders/common/skinning.wgsl")),
+    ("shaders/common/morph.wgsl", include_bytes!("shaders/common/morph.wgsl")),
+    ("textures/checker.png", include_bytes!("textures/checker.png")),
+];
+
+pub fn embedded_asset(path: &str) -> Option<&'static [u8]> {
+    EMBEDDED_ASSETS
+        .iter()
+        .find(|(embedded_path, _)| *embedded_path == path)
+        .map(|(_, data)| *data)
+}
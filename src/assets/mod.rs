@@ -0,0 +1,4 @@
+pub mod source;
+pub mod embedded;
+pub mod server;
+pub mod watcher;
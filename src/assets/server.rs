@@ -0,0 +1,561 @@
+use std::{collections::{HashMap, HashSet}, fmt, path::PathBuf, marker::PhantomData, rc::{Rc, Weak}, sync::{mpsc::{channel, Receiver, Sender}, Arc, Mutex}};
+
+use crate::{graphics::{context::{Context, Shader, Texture, TextureView}, lod::MAX_LOD_LEVELS, preprocessor::{Defines, PreprocessedShader}, reflection::ShaderReflection}, world::{animation::{AnimationClip, Skeleton}, import::ImportedMaterial, material::Material, model::PreparedModel, obj, world::Mesh}};
+
+use super::{source::AssetResolver, watcher::{any_changed, FileWatcher}};
+
+
+/// Most threads importing assets at once.
+const MAX_WORKERS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AssetId(u64);
+
+/// Refers to an asset of the `AssetServer`, which keeps it loaded for as long
+/// as any handle to it exists.
+pub struct Handle<T> {
+    id: Rc<AssetId>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> AssetId {
+        *self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.id.0).finish()
+    }
+}
+
+/// The meshes and material of a model file, shared by every `Model` built
+/// from it.
+pub struct ModelAsset {
+    pub meshes: Vec<Mesh>,
+    pub material: Option<Rc<Material>>,
+    pub skeleton: Option<Rc<Skeleton>>,
+    pub animations: Rc<Vec<AnimationClip>>,
+    pub dependencies: Vec<String>,
+}
+
+/// An sRGB image with a single mip level.
+pub struct TextureAsset {
+    pub texture: Rc<Texture>,
+    pub view: Rc<TextureView>,
+}
+
+struct AssetEntry<T> {
+    path: String,
+    state: LoadState,
+    asset: Option<Rc<T>>,
+    /// Bumped every time the asset finishes loading.
+    version: u32,
+    handle: Weak<AssetId>,
+}
+
+/// The assets of one type, by id and by path.
+pub struct AssetStore<T> {
+    entries: HashMap<AssetId, AssetEntry<T>>,
+    paths: HashMap<String, AssetId>,
+    /// Files of the assets loaded since `AssetServer::update` last started
+    /// watching them.
+    unwatched: Vec<String>,
+}
+
+impl<T> Default for AssetStore<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            paths: HashMap::new(),
+            unwatched: Vec::new(),
+        }
+    }
+}
+
+impl<T: Asset> AssetStore<T> {
+    /// The ids and paths of the assets depending on a changed file.
+    fn changed(&self, assets: &AssetResolver, changed_paths: &HashSet<PathBuf>) -> Vec<(AssetId, String)> {
+        self.entries.iter().filter(|(_, entry)| {
+            let dependencies = entry.asset.as_deref().map_or(&[][..], Asset::dependencies);
+
+            any_changed(assets, std::iter::once(entry.path.as_str()).chain(dependencies.iter().map(String::as_str)), changed_paths)
+        }).map(|(id, entry)| (*id, entry.path.clone())).collect()
+    }
+
+    /// The live handle to the asset at `path`, if it is loaded or loading.
+    fn find(&self, path: &str) -> Option<Handle<T>> {
+        let id = self.paths.get(path)?;
+
+        self.entries[id].handle.upgrade().map(|id| Handle {
+            id,
+            marker: PhantomData,
+        })
+    }
+
+    fn insert(&mut self, id: AssetId, path: &str) -> Handle<T> {
+        let handle = Handle {
+            id: Rc::new(id),
+            marker: PhantomData,
+        };
+
+        if let Some(previous) = self.paths.insert(path.to_string(), id) {
+            self.entries.remove(&previous);
+        }
+
+        self.entries.insert(id, AssetEntry {
+            path: path.to_string(),
+            state: LoadState::Loading,
+            asset: None,
+            version: 0,
+            handle: Rc::downgrade(&handle.id),
+        });
+
+        handle
+    }
+
+    fn path(&self, id: AssetId) -> Option<String> {
+        self.entries.get(&id).map(|entry| entry.path.clone())
+    }
+
+    /// Stores the result of a load. Failures, e.g. of a reload, keep whatever
+    /// was loaded before.
+    fn finish(&mut self, id: AssetId, result: Result<impl Into<Rc<T>>, String>) {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return;
+        };
+
+        match result {
+            Ok(asset) => {
+                let asset = asset.into();
+
+                // Reloads may read files the previous version didn't.
+                self.unwatched.push(entry.path.clone());
+                self.unwatched.extend(asset.dependencies().iter().cloned());
+
+                entry.asset = Some(asset);
+                entry.version += 1;
+                entry.state = LoadState::Loaded;
+            },
+            Err(error) => {
+                log::error!("Failed to load {}: {}", entry.path, error);
+                entry.state = LoadState::Failed(error);
+            },
+        }
+    }
+
+    /// Drops the assets no handle refers to anymore.
+    fn free_unused(&mut self) {
+        self.entries.retain(|_, entry| entry.handle.strong_count() > 0);
+        self.paths.retain(|_, id| self.entries.contains_key(id));
+    }
+}
+
+pub trait Asset: Sized {
+    fn store(server: &AssetServer) -> &AssetStore<Self>;
+
+    /// Files other than its own that the asset was read from.
+    fn dependencies(&self) -> &[String] {
+        &[]
+    }
+}
+
+impl Asset for ModelAsset {
+    fn store(server: &AssetServer) -> &AssetStore<Self> {
+        &server.models
+    }
+
+    fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+}
+
+impl Asset for TextureAsset {
+    fn store(server: &AssetServer) -> &AssetStore<Self> {
+        &server.textures
+    }
+}
+
+impl Asset for Material {
+    fn store(server: &AssetServer) -> &AssetStore<Self> {
+        &server.materials
+    }
+}
+
+impl Asset for Shader {
+    fn store(server: &AssetServer) -> &AssetStore<Self> {
+        &server.shaders
+    }
+
+    fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+}
+
+enum Job {
+    Model(AssetId, String),
+    Texture(AssetId, String),
+    Material(AssetId, String),
+    Shader(AssetId, String),
+}
+
+/// The CPU half of a load, done by a worker. The GPU half happens in
+/// `AssetServer::update`, since the context can't leave the main thread.
+enum Prepared {
+    Model(Result<PreparedModel, String>),
+    Texture(Result<image::RgbaImage, String>),
+    Material(Result<ImportedMaterial, String>),
+    Shader(Result<Box<(PreprocessedShader, ShaderReflection)>, String>),
+}
+
+impl Job {
+    fn path(&self) -> &str {
+        match self {
+            Job::Model(_, path) | Job::Texture(_, path) | Job::Material(_, path) | Job::Shader(_, path) => path,
+        }
+    }
+
+    fn run(self, assets: &AssetResolver) -> (AssetId, Prepared) {
+        match self {
+            Job::Model(id, path) => (id, Prepared::Model(PreparedModel::load(assets, &path, MAX_LOD_LEVELS))),
+            Job::Texture(id, path) => {
+                let image = assets.read(&path).and_then(|bytes| {
+                    image::load_from_memory(&bytes)
+                        .map(|image| image.to_rgba8())
+                        .map_err(|error| format!("{}: {}", path, error))
+                });
+
+                (id, Prepared::Texture(image))
+            },
+            Job::Material(id, path) => (id, Prepared::Material(obj::import_material(assets, &path))),
+            Job::Shader(id, path) => (id, Prepared::Shader(Shader::load(assets, &path, &Defines::new()).map(Box::new))),
+        }
+    }
+}
+
+/// Loads models, textures, materials and shaders by asset path on background
+/// threads. Materials are read from MTL files, see `obj::import_material`.
+///
+/// `load_*` returns a handle straight away, and the same handle for a path
+/// that is already loaded or loading. Reading, decoding and mesh processing
+/// happen on the workers, and `update` uploads whatever finished to the GPU.
+/// Until then `get` returns `None` and `state` says why.
+///
+/// Assets read from files on disk are reloaded when the files change. The
+/// new version replaces the old one once it has loaded, bumping `version`,
+/// and if it fails to load the old one is kept.
+pub struct AssetServer {
+    jobs: Sender<Job>,
+    results: Receiver<(AssetId, Prepared)>,
+    next_id: u64,
+    assets: AssetResolver,
+    watcher: FileWatcher,
+    /// Files that changed during the last `update`.
+    changed_paths: HashSet<PathBuf>,
+    models: AssetStore<ModelAsset>,
+    textures: AssetStore<TextureAsset>,
+    materials: AssetStore<Material>,
+    shaders: AssetStore<Shader>,
+}
+
+impl AssetServer {
+    pub fn new(assets: AssetResolver) -> Self {
+        let (jobs, job_receiver) = channel::<Job>();
+        let (result_sender, results) = channel();
+
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = std::thread::available_parallelism().map_or(1, |count| count.get()).clamp(1, MAX_WORKERS);
+
+        (0..workers).for_each(|index| {
+            let jobs = job_receiver.clone();
+            let results = result_sender.clone();
+            let assets = assets.clone();
+
+            let spawned = std::thread::Builder::new()
+                .name(format!("asset_worker_{}", index))
+                .spawn(move || loop {
+                    // The lock is only held while waiting, not while loading.
+                    let job = jobs.lock().expect("An asset worker panicked").recv();
+
+                    // The server was dropped.
+                    let Ok(job) = job else {
+                        break;
+                    };
+
+                    if results.send(job.run(&assets)).is_err() {
+                        break;
+                    }
+                });
+
+            if let Err(error) = spawned {
+                panic!("Failed to start asset worker {}: {}", index, error);
+            }
+        });
+
+        Self {
+            jobs,
+            results,
+            next_id: 0,
+            assets,
+            watcher: FileWatcher::new(),
+            changed_paths: HashSet::new(),
+            models: AssetStore::default(),
+            textures: AssetStore::default(),
+            materials: AssetStore::default(),
+            shaders: AssetStore::default(),
+        }
+    }
+
+    pub fn load_model(&mut self, path: &str) -> Handle<ModelAsset> {
+        if let Some(handle) = self.models.find(path) {
+            return handle;
+        }
+
+        let id = self.next_id();
+        self.send(Job::Model(id, path.to_string()));
+        self.models.insert(id, path)
+    }
+
+    pub fn load_texture(&mut self, path: &str) -> Handle<TextureAsset> {
+        if let Some(handle) = self.textures.find(path) {
+            return handle;
+        }
+
+        let id = self.next_id();
+        self.send(Job::Texture(id, path.to_string()));
+        self.textures.insert(id, path)
+    }
+
+    /// Loads the first material of an MTL file. Its base color texture is
+    /// loaded once a model draws with it, see `Material::bind_texture`.
+    pub fn load_material(&mut self, path: &str) -> Handle<Material> {
+        if let Some(handle) = self.materials.find(path) {
+            return handle;
+        }
+
+        let id = self.next_id();
+        self.send(Job::Material(id, path.to_string()));
+        self.materials.insert(id, path)
+    }
+
+    /// Loads the shader without defines. It is not added to the context's
+    /// shaders, so pipelines using it aren't rebuilt when it reloads.
+    pub fn load_shader(&mut self, path: &str) -> Handle<Shader> {
+        if let Some(handle) = self.shaders.find(path) {
+            return handle;
+        }
+
+        let id = self.next_id();
+        self.send(Job::Shader(id, path.to_string()));
+        self.shaders.insert(id, path)
+    }
+
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<Rc<T>> {
+        T::store(self).entries.get(&handle.id()).and_then(|entry| entry.asset.clone())
+    }
+
+    pub fn state<T: Asset>(&self, handle: &Handle<T>) -> LoadState {
+        T::store(self).entries.get(&handle.id())
+            .map_or(LoadState::Failed("The asset was freed".to_string()), |entry| entry.state.clone())
+    }
+
+    /// How many times the asset has finished loading, so users of it can tell
+    /// when to pick up a new version.
+    pub fn version<T: Asset>(&self, handle: &Handle<T>) -> u32 {
+        T::store(self).entries.get(&handle.id()).map_or(0, |entry| entry.version)
+    }
+
+    /// How many handles to the asset exist.
+    pub fn ref_count<T>(&self, handle: &Handle<T>) -> usize {
+        Rc::strong_count(&handle.id)
+    }
+
+    /// Starts watching files that aren't loaded through the server, for
+    /// `changed`. Each file only needs watching once.
+    pub fn watch(&mut self, paths: &[String]) {
+        self.watcher.watch(&self.assets, paths.iter().map(String::as_str));
+    }
+
+    /// Whether any of the files changed during the last `update`.
+    pub fn changed(&self, paths: &[String]) -> bool {
+        any_changed(&self.assets, paths.iter().map(String::as_str), &self.changed_paths)
+    }
+
+    /// Reloads assets whose files changed, uploads the assets that finished
+    /// loading and frees those without handles. Call once a frame.
+    pub fn update(&mut self, ctx: &mut Context) {
+        self.changed_paths = self.watcher.poll();
+
+        if !self.changed_paths.is_empty() {
+            let jobs: Vec<Job> = self.models.changed(&self.assets, &self.changed_paths).into_iter().map(|(id, path)| Job::Model(id, path))
+                .chain(self.textures.changed(&self.assets, &self.changed_paths).into_iter().map(|(id, path)| Job::Texture(id, path)))
+                .chain(self.materials.changed(&self.assets, &self.changed_paths).into_iter().map(|(id, path)| Job::Material(id, path)))
+                .chain(self.shaders.changed(&self.assets, &self.changed_paths).into_iter().map(|(id, path)| Job::Shader(id, path)))
+                .collect();
+
+            jobs.into_iter().for_each(|job| {
+                log::info!("Reloading {}", job.path());
+                self.send(job);
+            });
+        }
+
+        while let Ok((id, prepared)) = self.results.try_recv() {
+            match prepared {
+                Prepared::Model(result) => {
+                    let Some(path) = self.models.path(id) else {
+                        continue;
+                    };
+
+                    self.models.finish(id, result.map(|model| ModelAsset {
+                        meshes: model.meshes.into_iter().map(|mesh| Mesh::from_prepared(ctx, mesh)).collect(),
+                        material: model.material.map(|material| material.build(ctx, &path)),
+                        skeleton: model.skeleton.map(Rc::new),
+                        animations: Rc::new(model.animations),
+                        dependencies: model.dependencies,
+                    }));
+                },
+                Prepared::Texture(result) => {
+                    let Some(path) = self.textures.path(id) else {
+                        continue;
+                    };
+
+                    self.textures.finish(id, result.map(|image| upload_texture(ctx, &path, &image)));
+                },
+                Prepared::Material(result) => {
+                    let Some(path) = self.materials.path(id) else {
+                        continue;
+                    };
+
+                    self.materials.finish(id, result.map(|material| material.build(ctx, &path)));
+                },
+                Prepared::Shader(result) => {
+                    let Some(path) = self.shaders.path(id) else {
+                        continue;
+                    };
+
+                    self.shaders.finish(id, result.map(|loaded| {
+                        let (source, reflection) = *loaded;
+
+                        Shader::from_source(&ctx.device.logical_device, &path, &path, &Defines::new(), source, reflection)
+                    }));
+                },
+            }
+        }
+
+        self.models.free_unused();
+        self.textures.free_unused();
+        self.materials.free_unused();
+        self.shaders.free_unused();
+
+        // Only the files of assets that just loaded, so the watcher isn't
+        // asked about every file every frame.
+        let unwatched: Vec<String> = [&mut self.models.unwatched, &mut self.textures.unwatched, &mut self.materials.unwatched, &mut self.shaders.unwatched]
+            .into_iter()
+            .flat_map(std::mem::take)
+            .collect();
+
+        if !unwatched.is_empty() {
+            self.watcher.watch(&self.assets, unwatched.iter().map(String::as_str));
+        }
+    }
+
+    fn next_id(&mut self) -> AssetId {
+        self.next_id += 1;
+        AssetId(self.next_id)
+    }
+
+    fn send(&self, job: Job) {
+        self.jobs.send(job).expect("The asset workers stopped");
+    }
+}
+
+pub fn upload_texture(ctx: &Context, label: &str, image: &image::RgbaImage) -> TextureAsset {
+    let size = wgpu::Extent3d {
+        width: image.width(),
+        height: image.height(),
+        depth_or_array_layers: 1,
+    };
+
+    let texture = Texture::new(
+        &ctx.device.logical_device,
+        label,
+        size,
+        1,
+        1,
+        wgpu::TextureDimension::D2,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    );
+
+    ctx.device.queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture.gpu_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        image,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * image.width()),
+            rows_per_image: Some(image.height()),
+        },
+        size,
+    );
+
+    let view = TextureView::new(&texture.gpu_texture, label);
+
+    TextureAsset {
+        texture: Rc::new(texture),
+        view: Rc::new(view),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_are_deduplicated_by_path() {
+        let mut server = AssetServer::new(AssetResolver::embedded());
+
+        let first = server.load_material("materials/a.mtl");
+        let second = server.load_material("materials/a.mtl");
+        let other = server.load_material("materials/b.mtl");
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(server.ref_count(&first), 2);
+        assert_eq!(server.state(&first), LoadState::Loading);
+        assert_eq!(server.version(&first), 0);
+
+        // Materials and textures are stored apart, even under the same path.
+        let texture = server.load_texture("materials/a.mtl");
+        assert_ne!(texture.id(), first.id());
+    }
+}
@@ -0,0 +1,3 @@
+struct CameraUniform {
+    view_projection: mat4x4<f32>
+}
@@ -0,0 +1,14 @@
+// Per-object data of the GPU-driven path, one entry per mesh instance.
+
+struct GpuObject {
+    model: mat4x4<f32>,
+    base_color: vec4<f32>,
+    bounds_center: vec3<f32>,
+    metallic: f32,
+    bounds_extents: vec3<f32>,
+    roughness: f32,
+    index_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    pick_id: u32
+}
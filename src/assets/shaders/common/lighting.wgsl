@@ -0,0 +1,61 @@
+// Scene lights and the shading model shared by the forward and deferred paths.
+// Define LIGHTS_GROUP as the bind group index of "lights_bind_group" before
+// including this file.
+
+#ifndef LIGHTS_GROUP
+#define LIGHTS_GROUP 3
+#endif
+
+struct PointLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32
+}
+
+struct SceneLighting {
+    inverse_view_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    ambient: vec4<f32>,
+    clear_color: vec4<f32>,
+    light_count: u32,
+    debug_view: u32
+}
+
+@group(LIGHTS_GROUP) @binding(0)
+var<uniform> u_lighting: SceneLighting;
+
+@group(LIGHTS_GROUP) @binding(1)
+var<storage, read> s_lights: array<PointLight>;
+
+fn shade(albedo: vec3<f32>, normal: vec3<f32>, metallic: f32, roughness: f32, world_position: vec3<f32>) -> vec3<f32> {
+    let n = normalize(normal);
+    let v = normalize(u_lighting.camera_position.xyz - world_position);
+    let specular_color = mix(vec3<f32>(0.04), albedo, metallic);
+    let shininess = exp2(10.0 * (1.0 - roughness) + 1.0);
+
+    var color = u_lighting.ambient.rgb * albedo;
+
+    let light_count = min(u_lighting.light_count, arrayLength(&s_lights));
+    for (var i = 0u; i < light_count; i++) {
+        let light = s_lights[i];
+        let to_light = light.position - world_position;
+        let distance = length(to_light);
+        if (distance > light.range) {
+            continue;
+        }
+
+        let l = to_light / distance;
+        let h = normalize(l + v);
+        let n_dot_l = max(dot(n, l), 0.0);
+        let falloff = saturate(1.0 - pow(distance / light.range, 4.0));
+        let attenuation = falloff * falloff / (distance * distance + 1.0);
+
+        let diffuse = albedo * (1.0 - metallic) * n_dot_l;
+        let specular = specular_color * pow(max(dot(n, h), 0.0), shininess) * n_dot_l;
+
+        color += (diffuse + specular) * light.color * light.intensity * attenuation;
+    }
+
+    return color;
+}
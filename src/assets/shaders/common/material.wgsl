@@ -0,0 +1,17 @@
+// Matches AlphaMode in world/material.rs.
+const ALPHA_MODE_OPAQUE: u32 = 0u;
+const ALPHA_MODE_MASK: u32 = 1u;
+const ALPHA_MODE_BLEND: u32 = 2u;
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    alpha_cutoff: f32,
+    alpha_mode: u32
+}
+
+// True for fragments an alpha-masked material discards.
+fn alpha_masked(material: MaterialUniform, alpha: f32) -> bool {
+    return material.alpha_mode == ALPHA_MODE_MASK && alpha < material.alpha_cutoff;
+}
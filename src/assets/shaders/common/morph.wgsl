@@ -0,0 +1,49 @@
+// Morph targets. Shaders built with MORPHED bind the mesh's target deltas and
+// the model's weights for them next to the model transform.
+
+#ifdef MORPHED
+struct MorphDelta {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+    tangent: vec4<f32>
+}
+
+struct MorphWeights {
+    // Four weights to an element, MAX_MORPH_TARGETS in all.
+    weights: array<vec4<f32>, 16>,
+    target_count: u32,
+    vertex_count: u32
+}
+
+// Every vertex's delta for the first target, then for the second, and so on.
+@group(1) @binding(2)
+var<storage, read> u_morph_deltas: array<MorphDelta>;
+
+@group(1) @binding(3)
+var<uniform> u_morph: MorphWeights;
+#endif
+
+// The vertex with the targets added in by their weights, before skinning.
+fn morphed_vertex(vertices: VertexInput) -> VertexInput {
+#ifdef MORPHED
+    var morphed = vertices;
+
+    for (var index = 0u; index < u_morph.target_count; index++) {
+        let weight = u_morph.weights[index / 4u][index % 4u];
+
+        if (weight == 0.0) {
+            continue;
+        }
+
+        let delta = u_morph_deltas[index * u_morph.vertex_count + vertices.vertex_index];
+
+        morphed.a_position += delta.position.xyz * weight;
+        morphed.a_normal += delta.normal.xyz * weight;
+        morphed.a_tangent += vec4<f32>(delta.tangent.xyz * weight, 0.0);
+    }
+
+    return morphed;
+#else
+    return vertices;
+#endif
+}
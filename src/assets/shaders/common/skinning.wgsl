@@ -0,0 +1,28 @@
+// Linear blend skinning. Shaders built with SKINNED bind the joint matrices
+// next to the model transform, and read the vertex joints and weights.
+
+#ifdef SKINNED
+@group(1) @binding(1)
+var<storage, read> u_joints: array<mat4x4<f32>>;
+#endif
+
+// The model matrix, moved by the vertex's joints in skinned shaders.
+fn skinned_model_matrix(model: mat4x4<f32>, vertices: VertexInput) -> mat4x4<f32> {
+#ifdef SKINNED
+    let weights = vertices.a_weights;
+
+    // Meshes without joints in a skinned model stay where they are.
+    if (weights.x + weights.y + weights.z + weights.w <= 0.0) {
+        return model;
+    }
+
+    let skin = u_joints[vertices.a_joints.x] * weights.x
+        + u_joints[vertices.a_joints.y] * weights.y
+        + u_joints[vertices.a_joints.z] * weights.z
+        + u_joints[vertices.a_joints.w] * weights.w;
+
+    return model * skin;
+#else
+    return model;
+#endif
+}
@@ -0,0 +1,13 @@
+struct VertexInput {
+    @location(0) a_position: vec3<f32>,
+    @location(1) a_normal: vec3<f32>,
+    @location(2) a_uv: vec2<f32>,
+    @location(3) a_tangent: vec4<f32>,
+#ifdef SKINNED
+    @location(4) a_joints: vec4<u32>,
+    @location(5) a_weights: vec4<f32>,
+#endif
+#ifdef MORPHED
+    @builtin(vertex_index) vertex_index: u32,
+#endif
+}
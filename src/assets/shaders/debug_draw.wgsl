@@ -0,0 +1,29 @@
+#include "common/camera.wgsl"
+
+// Immediate-mode debug lines submitted through `DebugDraw`.
+
+struct DebugDrawInput {
+    @location(0) a_position: vec3<f32>,
+    @location(1) a_color: vec4<f32>
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>
+}
+
+@group(0) @binding(0)
+var<uniform> u_camera: CameraUniform;
+
+@vertex
+fn vs_main(vertices: DebugDrawInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = u_camera.view_projection * vec4<f32>(vertices.a_position, 1.0);
+    out.color = vertices.a_color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
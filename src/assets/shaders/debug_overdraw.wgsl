@@ -0,0 +1,54 @@
+// Maps the per-pixel layer count written by debug_view.wgsl's fs_overdraw to
+// a heat-map: black for nothing, then blue, green, yellow and red at max_overdraw.
+
+struct DebugViewUniform {
+    wireframe_color: vec4<f32>,
+    camera_position: vec3<f32>,
+    far: f32,
+    mode: u32,
+    max_overdraw: f32
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var t_overdraw: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> u_debug: DebugViewUniform;
+
+// Full-screen triangle, no vertex buffer required.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+fn heat(t: f32) -> vec3<f32> {
+    let blue = vec3<f32>(0.0, 0.1, 0.8);
+    let green = vec3<f32>(0.0, 0.8, 0.2);
+    let yellow = vec3<f32>(1.0, 0.9, 0.0);
+    let red = vec3<f32>(1.0, 0.0, 0.0);
+
+    if (t < 1.0 / 3.0) {
+        return mix(blue, green, t * 3.0);
+    } else if (t < 2.0 / 3.0) {
+        return mix(green, yellow, t * 3.0 - 1.0);
+    }
+    return mix(yellow, red, t * 3.0 - 2.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let layers = textureLoad(t_overdraw, vec2<i32>(in.clip_position.xy), 0).r;
+    if (layers < 0.5) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let t = saturate((layers - 1.0) / max(u_debug.max_overdraw - 1.0, 1.0));
+    return vec4<f32>(heat(t), 1.0);
+}
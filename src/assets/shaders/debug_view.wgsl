@@ -0,0 +1,154 @@
+#include "common/vertex_input.wgsl"
+#include "common/morph.wgsl"
+#include "common/skinning.wgsl"
+#include "common/camera.wgsl"
+
+// Debug visualisations drawn instead of, or over, the shaded scene. The mode
+// values match `DebugView` on the Rust side.
+
+const DEBUG_VIEW_NORMALS: u32 = 2u;
+const DEBUG_VIEW_UVS: u32 = 3u;
+const DEBUG_VIEW_DEPTH: u32 = 4u;
+const DEBUG_VIEW_MESH_COLORS: u32 = 6u;
+
+struct DebugViewUniform {
+    wireframe_color: vec4<f32>,
+    camera_position: vec3<f32>,
+    far: f32,
+    mode: u32,
+    max_overdraw: f32
+}
+
+struct TransformUniform {
+    model: mat4x4<f32>
+}
+
+@group(0) @binding(0)
+var<uniform> u_camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> u_model: TransformUniform;
+
+@group(2) @binding(0)
+var<uniform> u_debug: DebugViewUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) @interpolate(flat) mesh_id: u32
+}
+
+@vertex
+fn vs_main(rest: VertexInput, @builtin(instance_index) mesh_id: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let vertices = morphed_vertex(rest);
+    let model = skinned_model_matrix(u_model.model, vertices);
+    let world_position = model * vec4<f32>(vertices.a_position, 1.0);
+    out.clip_position = u_camera.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = (model * vec4<f32>(vertices.a_normal, 0.0)).xyz;
+    out.uv = vertices.a_uv;
+    out.mesh_id = mesh_id;
+    return out;
+}
+
+// Integer hash (PCG) spread over a hue, so neighbouring ids differ clearly.
+fn mesh_color(id: u32) -> vec3<f32> {
+    var state = id * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    let hue = f32((word >> 22u) ^ word) / 4294967295.0;
+    let rgb = clamp(abs(fract(hue + vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+    return mix(vec3<f32>(0.25), rgb, 0.8);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    switch u_debug.mode {
+        case DEBUG_VIEW_NORMALS: {
+            return vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+        }
+        case DEBUG_VIEW_UVS: {
+            return vec4<f32>(fract(in.uv), 0.0, 1.0);
+        }
+        case DEBUG_VIEW_DEPTH: {
+            let depth = distance(in.world_position, u_debug.camera_position) / u_debug.far;
+            return vec4<f32>(vec3<f32>(1.0 - sqrt(saturate(depth))), 1.0);
+        }
+        case DEBUG_VIEW_MESH_COLORS: {
+            return vec4<f32>(mesh_color(in.mesh_id), 1.0);
+        }
+        default: {
+            return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+        }
+    }
+}
+
+// Each fragment adds one layer; debug_overdraw.wgsl turns the count into a heat-map.
+@fragment
+fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 0.0, 0.0);
+}
+
+// Used with `PolygonMode::Line`.
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    return u_debug.wireframe_color;
+}
+
+// Fallback wireframe for devices without `POLYGON_MODE_LINE`. Meshes are
+// expanded so each triangle corner carries one of the unit barycentrics, and
+// fragments close to an edge are kept. Corners keep the index of the vertex
+// they were copied from, for the morph target deltas.
+
+struct WireframeInput {
+    @location(0) a_position: vec3<f32>,
+    @location(1) a_normal: vec3<f32>,
+    @location(2) a_uv: vec2<f32>,
+    @location(3) a_tangent: vec4<f32>,
+    @location(4) a_joints: vec4<u32>,
+    @location(5) a_weights: vec4<f32>,
+    @location(6) a_barycentric: vec3<f32>,
+    @location(7) a_vertex_index: u32
+}
+
+struct WireframeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) barycentric: vec3<f32>
+}
+
+@vertex
+fn vs_barycentric(corner: WireframeInput) -> WireframeOutput {
+    var rest: VertexInput;
+    rest.a_position = corner.a_position;
+    rest.a_normal = corner.a_normal;
+    rest.a_uv = corner.a_uv;
+    rest.a_tangent = corner.a_tangent;
+#ifdef SKINNED
+    rest.a_joints = corner.a_joints;
+    rest.a_weights = corner.a_weights;
+#endif
+#ifdef MORPHED
+    rest.vertex_index = corner.a_vertex_index;
+#endif
+
+    var out: WireframeOutput;
+    let vertices = morphed_vertex(rest);
+    let model = skinned_model_matrix(u_model.model, vertices);
+    out.clip_position = u_camera.view_projection * model * vec4<f32>(vertices.a_position, 1.0);
+    out.barycentric = corner.a_barycentric;
+    return out;
+}
+
+@fragment
+fn fs_barycentric(in: WireframeOutput) -> @location(0) vec4<f32> {
+    let width = fwidth(in.barycentric);
+    let edge = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+    let coverage = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if (coverage <= 0.0) {
+        discard;
+    }
+
+    return vec4<f32>(u_debug.wireframe_color.rgb, u_debug.wireframe_color.a * coverage);
+}
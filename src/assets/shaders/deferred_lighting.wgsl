@@ -0,0 +1,73 @@
+#define LIGHTS_GROUP 1
+
+#include "common/lighting.wgsl"
+
+// Vertex shader
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var t_albedo: texture_2d<f32>;
+
+@group(0) @binding(1)
+var t_normal: texture_2d<f32>;
+
+@group(0) @binding(2)
+var t_material: texture_2d<f32>;
+
+@group(0) @binding(3)
+var t_depth: texture_depth_2d;
+
+
+
+// Full-screen triangle, no vertex buffer required.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(t_depth, pixel, 0);
+
+    if (depth >= 1.0) {
+        return u_lighting.clear_color;
+    }
+
+    let albedo = textureLoad(t_albedo, pixel, 0).rgb;
+    let normal = textureLoad(t_normal, pixel, 0).xyz;
+    let material = textureLoad(t_material, pixel, 0);
+
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let uv = in.clip_position.xy / size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = u_lighting.inverse_view_projection * ndc;
+    let world_position = world.xyz / world.w;
+
+    switch u_lighting.debug_view {
+        case 1u: {
+            return vec4<f32>(albedo, 1.0);
+        }
+        case 2u: {
+            return vec4<f32>(normal * 0.5 + 0.5, 1.0);
+        }
+        case 3u: {
+            return vec4<f32>(material.r, material.g, 0.0, 1.0);
+        }
+        case 4u: {
+            let distance = length(world_position - u_lighting.camera_position.xyz);
+            return vec4<f32>(vec3<f32>(1.0 - exp(-distance * 0.05)), 1.0);
+        }
+        default: {
+            return vec4<f32>(shade(albedo, normal, material.r, material.g, world_position), 1.0);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+#include "common/vertex_input.wgsl"
+#include "common/morph.wgsl"
+#include "common/skinning.wgsl"
+#include "common/camera.wgsl"
+#include "common/material.wgsl"
+
+// Vertex shader
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) @interpolate(flat) pick_id: u32
+}
+
+struct ModelUniform {
+    model: mat4x4<f32>
+}
+
+@group(0) @binding(0)
+var<uniform> u_camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> u_model: ModelUniform;
+
+@group(2) @binding(0)
+var<uniform> u_material: MaterialUniform;
+
+@group(2) @binding(1)
+var t_base_color: texture_2d<f32>;
+
+@group(2) @binding(2)
+var s_base_color: sampler;
+
+
+
+@vertex
+fn vs_main(rest: VertexInput, @builtin(instance_index) pick_id: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let vertices = morphed_vertex(rest);
+    let model = skinned_model_matrix(u_model.model, vertices);
+    out.clip_position = u_camera.view_projection * model * vec4<f32>(vertices.a_position, 1.0);
+    out.world_normal = (model * vec4<f32>(vertices.a_normal, 0.0)).xyz;
+    out.uv = vertices.a_uv;
+    out.pick_id = pick_id;
+    return out;
+}
+
+// Fragment shader
+
+struct GBufferOutput {
+    @location(0) albedo: vec4<f32>,
+    @location(1) normal: vec4<f32>,
+    @location(2) material: vec4<f32>,
+    @location(3) pick_id: u32
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> GBufferOutput {
+    let base_color = u_material.base_color * textureSample(t_base_color, s_base_color, in.uv);
+
+    if (alpha_masked(u_material, base_color.a)) {
+        discard;
+    }
+
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(base_color.rgb, 1.0);
+    out.normal = vec4<f32>(normalize(in.world_normal), 1.0);
+    out.material = vec4<f32>(u_material.metallic, u_material.roughness, 0.0, 1.0);
+    out.pick_id = in.pick_id;
+    return out;
+}
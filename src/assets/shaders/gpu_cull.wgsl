@@ -0,0 +1,67 @@
+#include "common/gpu_object.wgsl"
+
+// Frustum culls every object and writes one indirect draw per object. Culled
+// objects get an instance count of zero so the draw list stays in object order.
+
+#ifndef WORKGROUP_SIZE
+#define WORKGROUP_SIZE 64
+#endif
+
+struct CullUniform {
+    planes: array<vec4<f32>, 6>,
+    object_count: u32,
+    culling_enabled: u32
+}
+
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32
+}
+
+@group(0) @binding(0)
+var<uniform> u_cull: CullUniform;
+
+@group(0) @binding(1)
+var<storage, read> s_objects: array<GpuObject>;
+
+@group(0) @binding(2)
+var<storage, read_write> s_draws: array<DrawIndexedIndirect>;
+
+@group(0) @binding(3)
+var<storage, read_write> s_visible_count: atomic<u32>;
+
+fn is_visible(object: GpuObject) -> bool {
+    let center = (object.model * vec4<f32>(object.bounds_center, 1.0)).xyz;
+    let abs_model = mat3x3<f32>(abs(object.model[0].xyz), abs(object.model[1].xyz), abs(object.model[2].xyz));
+    let extents = abs_model * object.bounds_extents;
+
+    for (var i = 0u; i < 6u; i++) {
+        let plane = u_cull.planes[i];
+        let radius = dot(extents, abs(plane.xyz));
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= u_cull.object_count) {
+        return;
+    }
+
+    let object = s_objects[index];
+    let visible = u_cull.culling_enabled == 0u || is_visible(object);
+
+    if (visible) {
+        atomicAdd(&s_visible_count, 1u);
+    }
+
+    s_draws[index] = DrawIndexedIndirect(object.index_count, select(0u, 1u, visible), object.first_index, object.base_vertex, index);
+}
@@ -0,0 +1,51 @@
+#define LIGHTS_GROUP 2
+
+#include "common/vertex_input.wgsl"
+#include "common/camera.wgsl"
+#include "common/gpu_object.wgsl"
+#include "common/lighting.wgsl"
+
+// Forward shading for the GPU-driven path. Each draw's first instance is the
+// index of its object, so per-object data comes from a single storage buffer.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) @interpolate(flat) object_index: u32
+}
+
+@group(0) @binding(0)
+var<uniform> u_camera: CameraUniform;
+
+@group(1) @binding(0)
+var<storage, read> s_objects: array<GpuObject>;
+
+@vertex
+fn vs_main(vertices: VertexInput, @builtin(instance_index) object_index: u32) -> VertexOutput {
+    let object = s_objects[object_index];
+
+    var out: VertexOutput;
+    let world_position = object.model * vec4<f32>(vertices.a_position, 1.0);
+    out.clip_position = u_camera.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = (object.model * vec4<f32>(vertices.a_normal, 0.0)).xyz;
+    out.object_index = object_index;
+    return out;
+}
+
+struct ForwardOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) pick_id: u32
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> ForwardOutput {
+    let object = s_objects[in.object_index];
+    let color = shade(object.base_color.rgb, in.world_normal, object.metallic, object.roughness, in.world_position);
+
+    var out: ForwardOutput;
+    out.color = vec4<f32>(color, object.base_color.a);
+    out.pick_id = object.pick_id;
+    return out;
+}
@@ -0,0 +1,101 @@
+// Infinite ground grid on the y = 0 plane, drawn as a full-screen pass. Each
+// pixel unprojects a ray through the near and far planes and intersects it
+// with the ground; the hit point's depth is written so the scene occludes it.
+// The camera matrices are part of the grid uniform, since the camera bind
+// group is only visible to vertex shaders.
+
+struct GridUniform {
+    view_projection: mat4x4<f32>,
+    inverse_view_projection: mat4x4<f32>,
+    camera_position: vec3<f32>,
+    // Size of the finest visible cell.
+    cell_size: f32,
+    minor_color: vec4<f32>,
+    major_color: vec4<f32>,
+    x_axis_color: vec4<f32>,
+    z_axis_color: vec4<f32>,
+    // Cells per major line.
+    major_every: f32,
+    fade_distance: f32,
+    // How far the camera is towards the next, coarser spacing, in 0..1.
+    lod_fraction: f32
+}
+
+@group(0) @binding(0)
+var<uniform> u_grid: GridUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32
+}
+
+// Full-screen triangle, no vertex buffer required.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.ndc = out.clip_position.xy;
+    return out;
+}
+
+fn unproject(ndc: vec2<f32>, z: f32) -> vec3<f32> {
+    let position = u_grid.inverse_view_projection * vec4<f32>(ndc, z, 1.0);
+    return position.xyz / position.w;
+}
+
+// Coverage of lines every `spacing` units, about one pixel wide.
+fn grid_lines(position: vec2<f32>, spacing: f32) -> f32 {
+    let coord = position / spacing;
+    let derivative = max(fwidth(coord), vec2<f32>(1e-6));
+    let distance_to_line = abs(fract(coord - 0.5) - 0.5) / derivative;
+    return 1.0 - min(min(distance_to_line.x, distance_to_line.y), 1.0);
+}
+
+// Coverage of the line where `value` is zero.
+fn axis_line(value: f32) -> f32 {
+    return 1.0 - min(abs(value) / max(fwidth(value), 1e-6), 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // The camera matrices use OpenGL-style clip space, so the near plane is at -1.
+    let near = unproject(in.ndc, -1.0);
+    let far = unproject(in.ndc, 1.0);
+    let t = -near.y / (far.y - near.y);
+    if (t <= 0.0 || t > 1.0) {
+        discard;
+    }
+
+    let hit = near + (far - near) * t;
+    let position = hit.xz;
+
+    let minor = grid_lines(position, u_grid.cell_size) * (1.0 - u_grid.lod_fraction);
+    let major = grid_lines(position, u_grid.cell_size * u_grid.major_every);
+
+    var color = vec4<f32>(u_grid.minor_color.rgb, u_grid.minor_color.a * minor);
+    color = mix(color, u_grid.major_color, major);
+
+    let x_axis = axis_line(position.y);
+    let z_axis = axis_line(position.x);
+    color = mix(color, u_grid.x_axis_color, x_axis);
+    color = mix(color, u_grid.z_axis_color, z_axis);
+
+    let fade = 1.0 - smoothstep(u_grid.fade_distance * 0.25, u_grid.fade_distance, distance(hit, u_grid.camera_position));
+    color.a *= fade;
+    if (color.a <= 0.001) {
+        discard;
+    }
+
+    let clip = u_grid.view_projection * vec4<f32>(hit, 1.0);
+
+    var out: FragmentOutput;
+    out.color = color;
+    out.depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    return out;
+}
@@ -0,0 +1,34 @@
+// Resolves the weighted blended transparency targets written by player.wgsl
+// with OIT defined. Blended over the lit scene with straight alpha.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var t_accum: texture_2d<f32>;
+
+@group(0) @binding(1)
+var t_revealage: texture_2d<f32>;
+
+// Full-screen triangle, no vertex buffer required.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let revealage = textureLoad(t_revealage, pixel, 0).r;
+    if (revealage >= 1.0) {
+        discard;
+    }
+
+    let accum = textureLoad(t_accum, pixel, 0);
+    let average_color = accum.rgb / max(accum.a, 1e-5);
+    return vec4<f32>(average_color, 1.0 - revealage);
+}
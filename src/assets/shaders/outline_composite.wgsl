@@ -0,0 +1,62 @@
+// Draws the selection outline over the presented image from the jump flood
+// result. Pixels outside the selection within `width` of it are coloured,
+// and pixels whose nearest selected pixel is hidden by the scene are drawn
+// with `occluded_opacity`.
+
+struct OutlineUniform {
+    color: vec4<f32>,
+    width: f32,
+    occluded_opacity: f32
+}
+
+@group(0) @binding(0)
+var t_mask: texture_2d<f32>;
+
+@group(0) @binding(1)
+var t_seeds: texture_2d<f32>;
+
+@group(0) @binding(2)
+var<uniform> u_outline: OutlineUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>
+}
+
+// Full-screen triangle, no vertex buffer required.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The outline is computed at the resolution of the world target, which
+    // the screen quad stretches over the surface.
+    let size = vec2<f32>(textureDimensions(t_mask));
+    let pixel = vec2<i32>(clamp(in.uv * size, vec2<f32>(0.0), size - 1.0));
+
+    if (textureLoad(t_mask, pixel, 0).r > 0.0) {
+        discard;
+    }
+
+    let seed = textureLoad(t_seeds, pixel, 0).xy;
+    if (seed.x < 0.0) {
+        discard;
+    }
+
+    let seed_distance = distance(seed, vec2<f32>(pixel));
+    let coverage = saturate(u_outline.width - seed_distance + 0.5);
+    if (coverage <= 0.0) {
+        discard;
+    }
+
+    let visible = textureLoad(t_mask, vec2<i32>(seed), 0).g > 0.0;
+    let opacity = select(u_outline.occluded_opacity, 1.0, visible);
+
+    return vec4<f32>(u_outline.color.rgb, u_outline.color.a * opacity * coverage);
+}
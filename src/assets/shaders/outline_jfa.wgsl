@@ -0,0 +1,67 @@
+// Jump flooding (Rong and Tan 2006) over the outline mask. Every pixel ends
+// up holding the coordinates of the nearest covered pixel, or -1 if there is
+// none within reach, in a number of passes logarithmic in the outline width.
+
+@group(0) @binding(0)
+var t_mask: texture_2d<f32>;
+
+@group(0) @binding(1)
+var t_seeds: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Distance in pixels to the neighbours sampled by `fs_step`.
+    @location(0) @interpolate(flat) step: u32
+}
+
+// Full-screen triangle, no vertex buffer required. The step size is passed as
+// the instance index so that passes need no uniform buffer of their own.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) step: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.step = step;
+    return out;
+}
+
+@fragment
+fn fs_init(in: VertexOutput) -> @location(0) vec2<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    if (textureLoad(t_mask, pixel, 0).r > 0.0) {
+        return vec2<f32>(pixel);
+    }
+    return vec2<f32>(-1.0);
+}
+
+@fragment
+fn fs_step(in: VertexOutput) -> @location(0) vec2<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let size = vec2<i32>(textureDimensions(t_seeds));
+    let step = i32(in.step);
+
+    var nearest = vec2<f32>(-1.0);
+    var nearest_distance = 1e20;
+
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let neighbour = pixel + vec2<i32>(x, y) * step;
+            if (any(neighbour < vec2<i32>(0)) || any(neighbour >= size)) {
+                continue;
+            }
+
+            let seed = textureLoad(t_seeds, neighbour, 0).xy;
+            if (seed.x < 0.0) {
+                continue;
+            }
+
+            let seed_distance = distance(seed, vec2<f32>(pixel));
+            if (seed_distance < nearest_distance) {
+                nearest = seed;
+                nearest_distance = seed_distance;
+            }
+        }
+    }
+
+    return nearest;
+}
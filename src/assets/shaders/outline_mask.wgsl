@@ -0,0 +1,41 @@
+#include "common/vertex_input.wgsl"
+#include "common/morph.wgsl"
+#include "common/skinning.wgsl"
+#include "common/camera.wgsl"
+
+// Marks the pixels covered by selected models for the outline. Red is set for
+// every covered pixel and green only where the model is in front of the
+// scene, so the composite pass can draw occluded parts of the outline dimmer.
+
+struct TransformUniform {
+    model: mat4x4<f32>
+}
+
+@group(0) @binding(0)
+var<uniform> u_camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> u_model: TransformUniform;
+
+@group(2) @binding(0)
+var t_depth: texture_depth_2d;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>
+}
+
+@vertex
+fn vs_main(rest: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let vertices = morphed_vertex(rest);
+    let model = skinned_model_matrix(u_model.model, vertices);
+    out.clip_position = u_camera.view_projection * model * vec4<f32>(vertices.a_position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene_depth = textureLoad(t_depth, vec2<i32>(in.clip_position.xy), 0);
+    let visible = select(0.0, 1.0, in.clip_position.z <= scene_depth + 1e-5);
+    return vec4<f32>(1.0, visible, 0.0, 0.0);
+}
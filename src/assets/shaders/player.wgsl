@@ -1,18 +1,20 @@
-// Vertex shader
+#define LIGHTS_GROUP 3
 
-struct VertexInput {
-    @location(0) a_position: vec3<f32>,
-    @location(1) a_normal: vec3<f32>,
-    @location(2) a_uv: vec2<f32>
-}
+#include "common/vertex_input.wgsl"
+#include "common/morph.wgsl"
+#include "common/skinning.wgsl"
+#include "common/camera.wgsl"
+#include "common/material.wgsl"
+#include "common/lighting.wgsl"
+
+// Vertex shader
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(1) vertex_color: vec3<f32>
-}
-
-struct CameraUniform {
-    view_projection: mat4x4<f32>
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) @interpolate(flat) pick_id: u32,
+    @location(3) uv: vec2<f32>
 }
 
 struct CubeTransformUniform {
@@ -25,19 +27,74 @@ var<uniform> u_camera: CameraUniform;
 @group(1) @binding(0)
 var<uniform> u_model: CubeTransformUniform;
 
+@group(2) @binding(0)
+var<uniform> u_material: MaterialUniform;
+
+@group(2) @binding(1)
+var t_base_color: texture_2d<f32>;
+
+@group(2) @binding(2)
+var s_base_color: sampler;
+
 
 
 @vertex
-fn vs_main(vertices: VertexInput) -> VertexOutput {
+fn vs_main(rest: VertexInput, @builtin(instance_index) pick_id: u32) -> VertexOutput {
     var out: VertexOutput;
-    out.clip_position = u_camera.view_projection * vec4<f32>(vertices.a_position, 1.0);
-    out.vertex_color = vertices.a_normal;
+    let vertices = morphed_vertex(rest);
+    let model = skinned_model_matrix(u_model.model, vertices);
+    let world_position = model * vec4<f32>(vertices.a_position, 1.0);
+    out.clip_position = u_camera.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = (model * vec4<f32>(vertices.a_normal, 0.0)).xyz;
+    out.pick_id = pick_id;
+    out.uv = vertices.a_uv;
     return out;
 } 
 
 // Fragment shader
 
+#ifdef OIT
+// Weighted blended order-independent transparency (McGuire and Bavoil 2013).
+// Accumulates weighted premultiplied colour and the product of (1 - alpha);
+// oit_composite.wgsl resolves both onto the lit scene.
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: f32
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> OitOutput {
+    let base_color = u_material.base_color * textureSample(t_base_color, s_base_color, in.uv);
+    let color = shade(base_color.rgb, in.world_normal, u_material.metallic, u_material.roughness, in.world_position);
+    let alpha = base_color.a;
+    let depth = in.clip_position.z;
+    let weight = clamp(pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - depth * 0.9, 3.0), 1e-2, 3e3);
+
+    var out: OitOutput;
+    out.accum = vec4<f32>(color * alpha, alpha) * weight;
+    out.revealage = alpha;
+    return out;
+}
+#else
+// The id is only kept by the opaque pipeline, which has a target for it.
+struct ForwardOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) pick_id: u32
+}
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.vertex_color, 1.0);
+fn fs_main(in: VertexOutput) -> ForwardOutput {
+    let base_color = u_material.base_color * textureSample(t_base_color, s_base_color, in.uv);
+
+    if (alpha_masked(u_material, base_color.a)) {
+        discard;
+    }
+
+    var out: ForwardOutput;
+    let color = shade(base_color.rgb, in.world_normal, u_material.metallic, u_material.roughness, in.world_position);
+    out.color = vec4<f32>(color, base_color.a);
+    out.pick_id = in.pick_id;
+    return out;
 }
+#endif
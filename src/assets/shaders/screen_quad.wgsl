@@ -1,10 +1,6 @@
-// Vertex shader
+#include "common/vertex_input.wgsl"
 
-struct VertexInput {
-    @location(0) a_position: vec3<f32>,
-    @location(1) a_normal: vec3<f32>,
-    @location(2) a_uv: vec2<f32>
-}
+// Vertex shader
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -0,0 +1,141 @@
+use std::{borrow::Cow, path::{Path, PathBuf}};
+
+use super::embedded::embedded_asset;
+
+
+/// Environment variable naming a directory whose files take precedence over
+/// every other source, e.g. to try out modified shaders in a release build.
+pub const ASSET_OVERRIDE_ENV: &str = "PHOENIX_ASSET_OVERRIDE";
+
+/// Environment variable naming the asset root directory.
+pub const ASSET_ROOT_ENV: &str = "PHOENIX_ASSET_ROOT";
+
+/// Somewhere an asset can be read from.
+#[derive(Debug, Clone)]
+pub enum AssetSource {
+    /// Files compiled into the binary, see `EMBEDDED_ASSETS`.
+    Embedded,
+    /// A directory that asset paths are resolved relative to.
+    Directory(PathBuf),
+}
+
+impl AssetSource {
+    fn read(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        match self {
+            AssetSource::Embedded => embedded_asset(path).map(Cow::Borrowed),
+            AssetSource::Directory(directory) => std::fs::read(directory.join(path)).ok().map(Cow::Owned),
+        }
+    }
+
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        match self {
+            AssetSource::Embedded => None,
+            AssetSource::Directory(directory) => Some(directory.join(path)).filter(|path| path.is_file()),
+        }
+    }
+}
+
+/// Resolves asset paths such as `"shaders/player.wgsl"` against an ordered list
+/// of sources, returning the first match. By default that is the override
+/// directory, then the asset root directory, then the embedded assets.
+#[derive(Debug, Clone)]
+pub struct AssetResolver {
+    sources: Vec<AssetSource>,
+}
+
+impl AssetResolver {
+    /// A resolver that only reads embedded assets.
+    pub fn embedded() -> Self {
+        Self {
+            sources: vec![AssetSource::Embedded],
+        }
+    }
+
+    /// Adds a directory that is searched before every existing source.
+    pub fn with_override_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.sources.insert(0, AssetSource::Directory(directory.into()));
+        self
+    }
+
+    /// Adds a directory that is searched after the override directories but
+    /// before the embedded assets.
+    pub fn with_root_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        let index = self.sources
+            .iter()
+            .position(|source| matches!(source, AssetSource::Embedded))
+            .unwrap_or(self.sources.len());
+
+        self.sources.insert(index, AssetSource::Directory(directory.into()));
+        self
+    }
+
+    pub fn sources(&self) -> &[AssetSource] {
+        &self.sources
+    }
+
+    pub fn read(&self, path: &str) -> Result<Cow<'static, [u8]>, String> {
+        let path = normalize(path);
+
+        self.sources
+            .iter()
+            .find_map(|source| source.read(&path))
+            .ok_or_else(|| format!("asset {} not found in {:?}", path, self.sources))
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String, String> {
+        let data = self.read(path)?;
+
+        String::from_utf8(data.into_owned())
+            .map_err(|error| format!("asset {} is not valid UTF-8: {}", path, error))
+    }
+
+    /// The file on disk that `path` currently resolves to, if it does not come
+    /// from the embedded assets. Used to decide what to watch for hot reload.
+    pub fn file_path(&self, path: &str) -> Option<PathBuf> {
+        let path = normalize(path);
+
+        for source in &self.sources {
+            if let Some(file_path) = source.file_path(&path) {
+                return Some(file_path);
+            }
+
+            if source.read(&path).is_some() {
+                return None;
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for AssetResolver {
+    /// Embedded assets, plus the directories named by `PHOENIX_ASSET_ROOT` and
+    /// `PHOENIX_ASSET_OVERRIDE`. Debug builds fall back to this crate's
+    /// `src/assets` as the root so that shader edits are picked up without a
+    /// rebuild.
+    fn default() -> Self {
+        let mut resolver = AssetResolver::embedded();
+
+        let root = std::env::var_os(ASSET_ROOT_ENV).map(PathBuf::from).or_else(|| {
+            cfg!(debug_assertions).then(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("src/assets"))
+        });
+
+        if let Some(root) = root {
+            resolver = resolver.with_root_directory(root);
+        }
+
+        if let Some(directory) = std::env::var_os(ASSET_OVERRIDE_ENV) {
+            resolver = resolver.with_override_directory(PathBuf::from(directory));
+        }
+
+        resolver
+    }
+}
+
+/// Asset paths are always relative, with forward slashes.
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/")
+        .trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
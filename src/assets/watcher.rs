@@ -0,0 +1,97 @@
+use std::{collections::HashSet, fs, path::{Path, PathBuf}, sync::mpsc::{channel, Receiver}};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::source::AssetResolver;
+
+
+/// Reports which asset files changed on disk. Assets that resolve to embedded
+/// files have nothing to watch.
+///
+/// Directories rather than files are watched, since many editors save by
+/// replacing the file, which would silently drop a watch on the file itself.
+pub struct FileWatcher {
+    watcher: Option<RecommendedWatcher>,
+    events: Receiver<notify::Result<notify::Event>>,
+    watched_directories: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        let (sender, events) = channel();
+
+        let watcher = notify::recommended_watcher(sender)
+            .map_err(|error| log::warn!("Hot reload disabled, failed to create file watcher: {}", error))
+            .ok();
+
+        Self {
+            watcher,
+            events,
+            watched_directories: HashSet::new(),
+        }
+    }
+
+    /// Starts watching the directories of the files the asset paths resolve to,
+    /// unless they are already watched.
+    pub fn watch<'a>(&mut self, assets: &AssetResolver, paths: impl IntoIterator<Item = &'a str>) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+
+        paths.into_iter().for_each(|path| {
+            let Some(directory) = assets.file_path(path)
+                .and_then(canonical_path)
+                .and_then(|path| path.parent().map(Path::to_path_buf)) else {
+                return;
+            };
+
+            if self.watched_directories.contains(&directory) {
+                return;
+            }
+
+            match watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    self.watched_directories.insert(directory);
+                },
+                Err(error) => log::warn!("Failed to watch {}: {}", directory.display(), error),
+            }
+        });
+    }
+
+    /// The canonical paths of the files modified or created since the last poll.
+    pub fn poll(&mut self) -> HashSet<PathBuf> {
+        let mut changed_paths = HashSet::new();
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+                    changed_paths.extend(event.paths.iter().filter_map(canonical_path));
+                },
+                Ok(_) => {},
+                Err(error) => log::warn!("File watcher error: {}", error),
+            }
+        }
+
+        changed_paths
+    }
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether any of the asset paths resolves to one of `changed_paths`.
+pub fn any_changed<'a>(assets: &AssetResolver, paths: impl IntoIterator<Item = &'a str>, changed_paths: &HashSet<PathBuf>) -> bool {
+    !changed_paths.is_empty() && paths
+        .into_iter()
+        .filter_map(|path| assets.file_path(path))
+        .filter_map(canonical_path)
+        .any(|path| changed_paths.contains(&path))
+}
+
+fn canonical_path(path: impl AsRef<Path>) -> Option<PathBuf> {
+    fs::canonicalize(path).ok()
+}
+
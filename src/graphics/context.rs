@@ -1,16 +1,30 @@
-use std::{fs, rc::Rc};
+use std::{rc::Rc, sync::{atomic::{AtomicU8, Ordering}, Arc}};
 use wgpu::util::DeviceExt;
 use winit::{dpi::PhysicalSize, window::Window};
 
+use crate::assets::source::AssetResolver;
+
+use super::{preprocessor::{preprocess, Defines, PreprocessedShader}, reflection::{ReflectionError, ShaderReflection}};
+
 pub struct Device {
     pub logical_device: wgpu::Device,
-    pub queue: wgpu::Queue
+    pub queue: wgpu::Queue,
+    /// The subset of `OPTIONAL_FEATURES` the adapter supports, all of which are enabled.
+    pub features: wgpu::Features,
 }
 
+/// Features that are enabled when the adapter has them. Code relying on one
+/// must check `Device::features` and provide a fallback.
+pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::MULTI_DRAW_INDIRECT
+    .union(wgpu::Features::INDIRECT_FIRST_INSTANCE)
+    .union(wgpu::Features::POLYGON_MODE_LINE);
+
 impl Device {
     pub async fn new(adapter: &wgpu::Adapter) -> Self {
+        let features = adapter.features() & OPTIONAL_FEATURES;
+
         let (logical_device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
-            features: wgpu::Features::default(),
+            features,
             limits: wgpu::Limits::default(),
             label: None
         }, 
@@ -18,7 +32,8 @@ impl Device {
 
         Self {
             logical_device,
-            queue
+            queue,
+            features,
         }
     }
 }
@@ -45,6 +60,117 @@ impl Buffer {
             needs_update: false,
         }
     }
+
+    /// Creates an uninitialised buffer, for storage and indirect buffers that
+    /// are only ever written by the GPU or by `Queue::write_buffer`.
+    pub fn with_size(device: &wgpu::Device, label: &str, size: wgpu::BufferAddress, usage: wgpu::BufferUsages) -> Self {
+        let gpu_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false
+        });
+
+        Self {
+            gpu_buffer,
+            needs_update: false,
+        }
+    }
+}
+
+const READBACK_IDLE: u8 = 0;
+const READBACK_COPIED: u8 = 1;
+const READBACK_MAPPING: u8 = 2;
+const READBACK_MAPPED: u8 = 3;
+
+/// A buffer the GPU copies results into and the CPU reads back asynchronously,
+/// usually a frame or two later. Only one copy is in flight at a time; copies
+/// requested while one is pending are skipped.
+pub struct ReadbackBuffer {
+    pub gpu_buffer: wgpu::Buffer,
+    state: Arc<AtomicU8>,
+}
+
+impl ReadbackBuffer {
+    pub fn new(device: &wgpu::Device, label: &str, size: wgpu::BufferAddress) -> Self {
+        let gpu_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        Self {
+            gpu_buffer,
+            state: Arc::new(AtomicU8::new(READBACK_IDLE)),
+        }
+    }
+
+    /// Records a copy of `source` at `offset` into this buffer unless a previous
+    /// readback is still in flight. Returns whether the copy was recorded.
+    pub fn copy_from_buffer(&self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer, offset: wgpu::BufferAddress) -> bool {
+        if self.state.load(Ordering::Acquire) != READBACK_IDLE {
+            return false;
+        }
+
+        encoder.copy_buffer_to_buffer(source, offset, &self.gpu_buffer, 0, self.gpu_buffer.size());
+
+        self.state.store(READBACK_COPIED, Ordering::Release);
+
+        true
+    }
+
+    /// Same as `copy_from_buffer`, for a texture region such as a single texel.
+    pub fn copy_from_texture(&self, encoder: &mut wgpu::CommandEncoder, source: wgpu::ImageCopyTexture, layout: wgpu::ImageDataLayout, size: wgpu::Extent3d) -> bool {
+        if self.state.load(Ordering::Acquire) != READBACK_IDLE {
+            return false;
+        }
+
+        encoder.copy_texture_to_buffer(
+            source,
+            wgpu::ImageCopyBuffer {
+                buffer: &self.gpu_buffer,
+                layout
+            },
+            size
+        );
+
+        self.state.store(READBACK_COPIED, Ordering::Release);
+
+        true
+    }
+
+    /// Starts mapping the buffer. Call once the encoder holding the copy has
+    /// been submitted.
+    pub fn map(&self) {
+        if self.state.load(Ordering::Acquire) != READBACK_COPIED {
+            return;
+        }
+
+        self.state.store(READBACK_MAPPING, Ordering::Release);
+
+        let state = self.state.clone();
+
+        self.gpu_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            state.store(if result.is_ok() { READBACK_MAPPED } else { READBACK_IDLE }, Ordering::Release);
+        });
+    }
+
+    /// Returns the copied bytes once mapping has finished, without blocking.
+    pub fn try_read(&self, device: &wgpu::Device) -> Option<Vec<u8>> {
+        device.poll(wgpu::Maintain::Poll);
+
+        if self.state.load(Ordering::Acquire) != READBACK_MAPPED {
+            return None;
+        }
+
+        let data = self.gpu_buffer.slice(..).get_mapped_range().to_vec();
+
+        self.gpu_buffer.unmap();
+        self.state.store(READBACK_IDLE, Ordering::Release);
+
+        Some(data)
+    }
 }
 
 impl State for Buffer {
@@ -143,6 +269,49 @@ pub struct BindGroupLayoutEntry {
     pub ty: wgpu::BindingType,
 }
 
+impl BindGroupLayoutEntry {
+    pub fn uniform_buffer(binding: u32, visibility: wgpu::ShaderStages) -> Self {
+        Self {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None
+            }
+        }
+    }
+
+    pub fn storage_buffer(binding: u32, visibility: wgpu::ShaderStages, read_only: bool) -> Self {
+        Self {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None
+            }
+        }
+    }
+
+    pub fn storage_texture(
+        binding: u32, 
+        visibility: wgpu::ShaderStages, 
+        format: wgpu::TextureFormat, 
+        access: wgpu::StorageTextureAccess
+    ) -> Self {
+        Self {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension: wgpu::TextureViewDimension::D2
+            }
+        }
+    }
+}
+
 pub struct BindGroupLayout {
     pub gpu_bind_group_layout: wgpu::BindGroupLayout,
 }
@@ -166,7 +335,7 @@ impl BindGroupLayout {
 
         let gpu_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &gpu_entries,
-            label: Some("cube_bind_group_layout")
+            label: Some(label)
         });
 
         Self {
@@ -213,76 +382,273 @@ impl BindGroup {
 }
 
 pub struct Shader {
-    pub shader: wgpu::ShaderModule
+    pub shader: wgpu::ShaderModule,
+    pub label: String,
+    pub code_path: String,
+    pub defines: Defines,
+    /// Every file the shader was assembled from, including `#include`s.
+    pub dependencies: Vec<String>,
+    pub reflection: ShaderReflection,
 }
 
 impl Shader {
-    pub fn new(logical_device: &wgpu::Device, label: &str, code_path: &str) -> Self {
-        let code = fs::read_to_string(code_path)
-            .expect("Failed to read shader file!");
-        
+    pub fn new(logical_device: &wgpu::Device, assets: &AssetResolver, label: &str, code_path: &str, defines: &Defines) -> Self {
+        let (source, reflection) = Shader::load(assets, code_path, defines)
+            .unwrap_or_else(|error| panic!("Failed to compile shader {}:\n{}", label, error));
+
+        Self::from_source(logical_device, label, code_path, defines, source, reflection)
+    }
+
+    /// Builds a shader module from WGSL that has already been through `Shader::load`.
+    pub fn from_source(
+        logical_device: &wgpu::Device, 
+        label: &str, 
+        code_path: &str, 
+        defines: &Defines, 
+        source: PreprocessedShader,
+        reflection: ShaderReflection
+    ) -> Self {
         let shader = logical_device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some(label),
-            source: wgpu::ShaderSource::Wgsl(code.into())
+            source: wgpu::ShaderSource::Wgsl(source.code.into())
         });
 
         Self {
-            shader
+            shader,
+            label: label.to_string(),
+            code_path: code_path.to_string(),
+            defines: defines.clone(),
+            dependencies: source.dependencies,
+            reflection,
         }
     }
+
+    /// Reads and preprocesses the shader at the asset path `code_path`, then parses and validates
+    /// the result with naga. wgpu panics on invalid shaders, so anything that may
+    /// be broken (such as a file being edited) should go through here first.
+    /// Errors are reported against the original file and line.
+    pub fn load(assets: &AssetResolver, code_path: &str, defines: &Defines) -> Result<(PreprocessedShader, ShaderReflection), String> {
+        let source = preprocess(code_path, defines, &|path| assets.read_to_string(path))
+            .map_err(|error| error.to_string())?;
+
+        let reflection = Shader::validate(&source, code_path)?;
+
+        Ok((source, reflection))
+    }
+
+    pub fn validate(source: &PreprocessedShader, code_path: &str) -> Result<ShaderReflection, String> {
+        let describe = |message: String, location: Option<naga::SourceLocation>, emitted: String| {
+            let original = location.and_then(|location| source.original_location(location.line_number as usize));
+
+            match original {
+                Some(original) => format!("{}: {}\n\n{}", original, message, emitted),
+                None => format!("{}: {}\n\n{}", code_path, message, emitted),
+            }
+        };
+
+        let preprocessed_path = format!("{} (preprocessed)", code_path);
+
+        let module = naga::front::wgsl::parse_str(&source.code)
+            .map_err(|error| describe(
+                error.message().to_string(), 
+                error.location(&source.code), 
+                error.emit_to_string_with_path(&source.code, &preprocessed_path)
+            ))?;
+
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .map_err(|error| describe(
+                error.as_inner().to_string(), 
+                error.location(&source.code), 
+                error.emit_to_string_with_path(&source.code, &preprocessed_path)
+            ))?;
+
+        Ok(ShaderReflection::new(module, info))
+    }
 }
 
 pub struct RenderPipeline {
     pub gpu_render_pipeline: wgpu::RenderPipeline,
+    pub label: String,
+    pub shader_label: String,
+    /// The layouts of the bind groups this pipeline expects, indexed by group.
+    pub bind_group_layouts: Vec<Rc<BindGroupLayout>>,
+    pub state: RenderPipelineState,
+    layout: Rc<wgpu::PipelineLayout>,
+}
+
+/// Everything besides the bind group layouts and shader that describes a
+/// render pipeline. Usually filled in by `RenderPipelineBuilder`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RenderPipelineState {
+    pub vertex_entry_point: String,
+    /// `None` builds a pipeline without a fragment stage, e.g. for depth-only passes.
+    pub fragment_entry_point: Option<String>,
+    pub buffers: Vec<wgpu::VertexBufferLayout<'static>>,
+    pub color_target_states: Vec<Option<wgpu::ColorTargetState>>,
+    pub depth_stencil_state: Option<wgpu::DepthStencilState>,
+    pub primitive: wgpu::PrimitiveState,
+    pub multisample: wgpu::MultisampleState,
+}
+
+/// Identifies a render pipeline in `Context::render_pipeline_cache`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RenderPipelineKey {
+    pub shader_label: String,
+    /// Addresses of the explicit bind group layouts, or `None` when they are
+    /// reflected from the shader.
+    pub bind_group_layouts: Option<Vec<usize>>,
+    pub state: RenderPipelineState,
 }
 
 impl RenderPipeline {
     pub fn new(
         device: &wgpu::Device, 
         label: &str,
-        layout: wgpu::PipelineLayout, 
-        shader: &wgpu::ShaderModule,
-        buffers: &[wgpu::VertexBufferLayout],
-        color_target_state: Option<wgpu::ColorTargetState>,
-        depth_target_state: Option<wgpu::DepthStencilState>,
-        topology: wgpu::PrimitiveTopology,
-        polygon_mode: wgpu::PolygonMode,
+        bind_group_layouts: Vec<Rc<BindGroupLayout>>,
+        shader: &Shader,
+        state: RenderPipelineState,
     ) -> Self {
-        let gpu_render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let gpu_bind_group_layouts: Vec<&wgpu::BindGroupLayout> = bind_group_layouts
+            .iter()
+            .map(|layout| &layout.gpu_bind_group_layout)
+            .collect();
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{}_layout", label)),
+            bind_group_layouts: &gpu_bind_group_layouts,
+            push_constant_ranges: &[]
+        });
+
+        let gpu_render_pipeline = Self::create_gpu_pipeline(device, label, &layout, &shader.shader, &state);
+
+        Self {
+            gpu_render_pipeline,
+            label: label.to_string(),
+            shader_label: shader.label.clone(),
+            bind_group_layouts,
+            state,
+            layout: Rc::new(layout),
+        }
+    }
+
+    /// Recreates this pipeline with the same state but a different shader
+    /// module, used when a shader is reloaded from disk.
+    pub fn rebuild(&self, device: &wgpu::Device, shader: &Shader) -> Self {
+        let gpu_render_pipeline = Self::create_gpu_pipeline(device, &self.label, &self.layout, &shader.shader, &self.state);
+
+        Self {
+            gpu_render_pipeline,
+            label: self.label.clone(),
+            shader_label: shader.label.clone(),
+            bind_group_layouts: self.bind_group_layouts.clone(),
+            state: self.state.clone(),
+            layout: self.layout.clone(),
+        }
+    }
+
+    fn create_gpu_pipeline(
+        device: &wgpu::Device, 
+        label: &str,
+        layout: &wgpu::PipelineLayout, 
+        shader: &wgpu::ShaderModule,
+        state: &RenderPipelineState,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some(label),
-            layout: Some(&layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: shader,
-                entry_point: "vs_main",
-                buffers,
+                entry_point: &state.vertex_entry_point,
+                buffers: &state.buffers,
             },
-            fragment: Some(wgpu::FragmentState {
+            fragment: state.fragment_entry_point.as_ref().map(|entry_point| wgpu::FragmentState {
                 module: shader,
-                entry_point: "fs_main",
-                targets: &[color_target_state],
+                entry_point,
+                targets: &state.color_target_states,
             }),
-            primitive: wgpu::PrimitiveState {
-                topology,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode,
-                unclipped_depth: false,
-                conservative: false
-            },
-            depth_stencil: depth_target_state,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false
-            },
+            primitive: state.primitive,
+            depth_stencil: state.depth_stencil_state.clone(),
+            multisample: state.multisample,
             multiview: None,
+        })
+    }
+}
+
+pub struct ComputePipeline {
+    pub gpu_compute_pipeline: wgpu::ComputePipeline,
+}
+
+impl ComputePipeline {
+    pub fn new(
+        device: &wgpu::Device, 
+        label: &str,
+        layout: wgpu::PipelineLayout, 
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+    ) -> Self {
+        let gpu_compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            module: shader,
+            entry_point,
         });
 
         Self {
-            gpu_render_pipeline
+            gpu_compute_pipeline
         }
     }
+
+    /// Number of workgroups needed to cover `size` invocations.
+    pub fn workgroup_count(size: u32, workgroup_size: u32) -> u32 {
+        size.div_ceil(workgroup_size)
+    }
+
+    /// Records a compute pass that binds `bind_groups` in order and dispatches
+    /// this pipeline once.
+    pub fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        bind_groups: &[&BindGroup],
+        workgroups: (u32, u32, u32),
+    ) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(label),
+            timestamp_writes: None,
+        });
+
+        compute_pass.set_pipeline(&self.gpu_compute_pipeline);
+
+        bind_groups.iter().enumerate().for_each(|(index, bind_group)| {
+            compute_pass.set_bind_group(index as u32, &bind_group.gpu_bind_group, &[]);
+        });
+
+        compute_pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+    }
+}
+
+/// A compute dispatch that is recorded every frame, before any render pass.
+/// Pipeline and bind groups are looked up by label so that the pass can be
+/// registered before the resources it names are recreated.
+pub struct ComputePass {
+    pub label: String,
+    pub pipeline: String,
+    pub bind_groups: Vec<String>,
+    pub workgroups: (u32, u32, u32),
+}
+
+impl ComputePass {
+    pub fn record(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder) {
+        let pipeline = ctx.get_compute_pipeline(&self.pipeline);
+
+        let bind_groups: Vec<&BindGroup> = self.bind_groups
+            .iter()
+            .map(|label| ctx.get_bind_group(label))
+            .collect();
+
+        pipeline.dispatch(encoder, &self.label, &bind_groups, self.workgroups);
+    }
 }
 
 
@@ -293,8 +659,9 @@ pub enum Renderables {
     Sampler(Sampler),
     BindGroupLayout(BindGroupLayout),
     BindGroup(BindGroup),
-    Shader(Shader),
-    RenderPipeline(RenderPipeline),
+    Shader(Box<Shader>),
+    RenderPipeline(Box<RenderPipeline>),
+    ComputePipeline(Box<ComputePipeline>),
 }
 
 
@@ -309,7 +676,13 @@ pub struct Context {
     pub bind_group_layouts: std::collections::HashMap<String, Rc<BindGroupLayout>>,
     pub bind_groups: std::collections::HashMap<String, Rc<BindGroup>>,
     pub shaders: std::collections::HashMap<String, Rc<Shader>>,
+    /// Labels of the shaders compiled since `ShaderHotReloader` last started
+    /// watching their files.
+    pub unwatched_shaders: Vec<String>,
     pub render_pipelines: std::collections::HashMap<String, Rc<RenderPipeline>>,
+    pub render_pipeline_cache: std::collections::HashMap<RenderPipelineKey, Rc<RenderPipeline>>,
+    pub compute_pipelines: std::collections::HashMap<String, Rc<ComputePipeline>>,
+    pub assets: AssetResolver,
 }
 
 
@@ -363,6 +736,8 @@ impl Context {
         let bind_groups = std::collections::HashMap::new();
         let shaders = std::collections::HashMap::new();
         let render_pipelines = std::collections::HashMap::new();
+        let render_pipeline_cache = std::collections::HashMap::new();
+        let compute_pipelines = std::collections::HashMap::new();
 
         Self {
             device,
@@ -375,7 +750,11 @@ impl Context {
             bind_group_layouts,
             bind_groups,
             shaders,
+            unwatched_shaders: Vec::new(),
             render_pipelines,
+            render_pipeline_cache,
+            compute_pipelines,
+            assets: AssetResolver::default(),
         }
     }
 
@@ -408,6 +787,49 @@ impl Context {
         buffer
     }
 
+    /// Creates a buffer of `size` bytes without initial contents.
+    pub fn create_buffer_with_size(
+        &mut self, 
+        label: &str, 
+        size: wgpu::BufferAddress, 
+        usage: wgpu::BufferUsages
+    ) -> Rc<Buffer> {
+        let buffer = Rc::new(Buffer::with_size(&self.device.logical_device, label, size, usage));
+        
+        self.buffers.insert(label.to_string(), buffer.clone());
+        
+        buffer
+    }
+
+    /// Storage buffers can always be copied to and from, so that CPU uploads
+    /// and readbacks don't need a second buffer.
+    pub fn create_storage_buffer(
+        &mut self, 
+        label: &str, 
+        data: &[u8], 
+        usage: wgpu::BufferUsages
+    ) -> Rc<Buffer> {
+        self.create_buffer(label, data, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC | usage)
+    }
+
+    /// A 2D texture that compute shaders can write and later passes can sample.
+    pub fn create_storage_texture(
+        &mut self, 
+        label: &str,         
+        size: wgpu::Extent3d, 
+        format: wgpu::TextureFormat, 
+    ) -> Rc<Texture> {
+        self.create_texture(
+            label, 
+            size, 
+            1, 
+            1, 
+            wgpu::TextureDimension::D2, 
+            format, 
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC
+        )
+    }
+
     ///
     pub fn create_texture(
         &mut self, 
@@ -432,9 +854,31 @@ impl Context {
         label: &str, 
         code_path: &str
     ) -> Rc<Shader> {
-        let shader = Rc::new(Shader::new(&self.device.logical_device, label, code_path));
+        self.create_shader_permutation(label, code_path, &Defines::new())
+    }
+
+    /// Compiles `code_path` with a set of preprocessor defines. Each distinct set
+    /// of defines is a separate permutation, stored under the label with the
+    /// defines appended (see `permutation_label`), and is only compiled once.
+    pub fn create_shader_permutation(
+        &mut self, 
+        label: &str, 
+        code_path: &str,
+        defines: &Defines
+    ) -> Rc<Shader> {
+        let label = permutation_label(label, defines);
+
+        if let Some(shader) = self.shaders.get(&label) {
+            if shader.code_path == code_path {
+                return shader.clone();
+            }
+        }
+
+        let shader = Rc::new(Shader::new(&self.device.logical_device, &self.assets, &label, code_path, defines));
         
-        self.shaders.insert(label.to_string(), shader.clone());
+        self.shaders.insert(label.clone(), shader.clone());
+
+        self.unwatched_shaders.push(label);
         
         shader
     }
@@ -482,6 +926,21 @@ impl Context {
         bind_group_layout
     }
 
+    /// Creates a bind group layout for every group `shader` binds, derived from
+    /// its reflection and stored as `{label}_group{n}_layout`.
+    pub fn create_reflected_bind_group_layouts(
+        &mut self,
+        label: &str,
+        shader: &Shader
+    ) -> Result<Vec<Rc<BindGroupLayout>>, ReflectionError> {
+        Ok(shader.reflection
+            .all_bind_group_layout_entries()?
+            .into_iter()
+            .enumerate()
+            .map(|(group, entries)| self.create_bind_group_layout(&format!("{}_group{}_layout", label, group), entries))
+            .collect())
+    }
+
     pub fn create_bind_group(
         &mut self,
         label: &str, 
@@ -495,24 +954,58 @@ impl Context {
         bind_group
     }
 
+    /// Creates a render pipeline, or returns the cached one if a pipeline with
+    /// the same shader, bind group layouts and state was created before. When
+    /// `bind_group_layouts` is `None` they are reflected from the shader.
+    /// Prefer `RenderPipelineBuilder` over calling this directly.
     pub fn create_render_pipeline(
         &mut self,
         label: &str,
-        layout: wgpu::PipelineLayout, 
-        shader: &wgpu::ShaderModule,
-        buffers: &[wgpu::VertexBufferLayout],
-        color_target_state: Option<wgpu::ColorTargetState>,
-        depth_target_state: Option<wgpu::DepthStencilState>,
-        topology: wgpu::PrimitiveTopology,
-        polygon_mode: wgpu::PolygonMode,
+        shader: &Shader,
+        bind_group_layouts: Option<Vec<Rc<BindGroupLayout>>>,
+        state: RenderPipelineState,
     ) -> Rc<RenderPipeline> {
-        let render_pipeline = Rc::new(RenderPipeline::new(&self.device.logical_device, label, layout, shader, buffers, color_target_state, depth_target_state, topology, polygon_mode));
+        let key = RenderPipelineKey {
+            shader_label: shader.label.clone(),
+            bind_group_layouts: bind_group_layouts
+                .as_ref()
+                .map(|layouts| layouts.iter().map(|layout| Rc::as_ptr(layout) as usize).collect()),
+            state,
+        };
+
+        if let Some(render_pipeline) = self.render_pipeline_cache.get(&key).cloned() {
+            self.render_pipelines.insert(label.to_string(), render_pipeline.clone());
+
+            return render_pipeline;
+        }
+
+        let bind_group_layouts = bind_group_layouts.unwrap_or_else(|| {
+            self.create_reflected_bind_group_layouts(label, shader)
+                .unwrap_or_else(|error| panic!("Failed to reflect bind group layouts of {}: {}", shader.code_path, error))
+        });
+
+        let render_pipeline = Rc::new(RenderPipeline::new(&self.device.logical_device, label, bind_group_layouts, shader, key.state.clone()));
         
+        self.render_pipeline_cache.insert(key, render_pipeline.clone());
         self.render_pipelines.insert(label.to_string(), render_pipeline.clone());
         
         render_pipeline
     }
 
+    pub fn create_compute_pipeline(
+        &mut self,
+        label: &str,
+        layout: wgpu::PipelineLayout, 
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+    ) -> Rc<ComputePipeline> {
+        let compute_pipeline = Rc::new(ComputePipeline::new(&self.device.logical_device, label, layout, shader, entry_point));
+        
+        self.compute_pipelines.insert(label.to_string(), compute_pipeline.clone());
+        
+        compute_pipeline
+    }
+
     pub fn get_buffer(&self, label: &str) -> &Buffer {
         &self.buffers
             .get(label)
@@ -568,4 +1061,25 @@ impl Context {
             .expect(&format!("Failed to get {} from ctx.render_pipelines!", label.to_string()))
             .as_ref()
     }
-}
\ No newline at end of file
+
+    pub fn get_compute_pipeline(&self, label: &str) -> &ComputePipeline {
+        self.compute_pipelines
+            .get(label)
+            .unwrap_or_else(|| panic!("Failed to get {} from ctx.compute_pipelines!", label))
+            .as_ref()
+    }
+}
+
+/// The label a shader permutation is stored under, e.g. `cube_shader[SKINNED,MAX_JOINTS=64]`.
+pub fn permutation_label(label: &str, defines: &Defines) -> String {
+    if defines.is_empty() {
+        return label.to_string();
+    }
+
+    let defines: Vec<String> = defines
+        .iter()
+        .map(|(name, value)| if value.is_empty() { name.clone() } else { format!("{}={}", name, value) })
+        .collect();
+
+    format!("{}[{}]", label, defines.join(","))
+}
@@ -0,0 +1,64 @@
+use crate::world::{bounds::Frustum, model::Model, world::{Mesh, World}};
+
+
+/// What the last frame drew, shown in the render settings window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub drawn: u32,
+    pub culled: u32,
+    pub pipeline_switches: u32,
+    pub bind_group_switches: u32,
+    pub draw_calls: u32,
+    /// Of indexed draws, i.e. of meshes at the level of detail drawn.
+    pub triangles: u32,
+}
+
+impl std::ops::AddAssign for RenderStats {
+    fn add_assign(&mut self, other: Self) {
+        self.drawn += other.drawn;
+        self.culled += other.culled;
+        self.pipeline_switches += other.pipeline_switches;
+        self.bind_group_switches += other.bind_group_switches;
+        self.draw_calls += other.draw_calls;
+        self.triangles += other.triangles;
+    }
+}
+
+/// Collects the meshes of `world` whose world-space bounds intersect `frustum`,
+/// together with the model they belong to, and counts what was culled. Passing
+/// `None` disables culling and returns every mesh.
+pub fn visible_meshes<'a>(world: &'a World, frustum: Option<&Frustum>, stats: &mut RenderStats) -> Vec<(&'a Model, &'a Mesh)> {
+    visible_model_meshes(world.models.iter(), frustum, stats)
+}
+
+/// `visible_meshes` of only some of the world's models.
+pub fn visible_model_meshes<'a>(models: impl IntoIterator<Item = &'a Model>, frustum: Option<&Frustum>, stats: &mut RenderStats) -> Vec<(&'a Model, &'a Mesh)> {
+    let mut visible = Vec::new();
+
+    models.into_iter().for_each(|model| {
+        let Some(frustum) = frustum else {
+            visible.extend(model.meshes.iter().map(|mesh| (model, mesh)));
+            return;
+        };
+
+        // Test the whole model first so fully hidden models skip their meshes.
+        if model.world_bounds().is_some_and(|bounds| !frustum.intersects_aabb(&bounds)) {
+            stats.culled += model.meshes.len() as u32;
+            return;
+        }
+
+        let model_matrix = model.model_matrix();
+
+        model.meshes.iter().for_each(|mesh| {
+            if frustum.intersects_aabb(&mesh.bounds.transformed(&model_matrix)) {
+                visible.push((model, mesh));
+            } else {
+                stats.culled += 1;
+            }
+        });
+    });
+
+    stats.drawn += visible.len() as u32;
+
+    visible
+}
@@ -0,0 +1,272 @@
+use std::rc::Rc;
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix};
+
+use crate::world::{bounds::Aabb, camera::PerspectiveCamera};
+
+use super::{context::{Buffer, Context}, graphics::{WORLD_COLOR_FORMAT, WORLD_DEPTH_FORMAT}, pipeline::{BlendPreset, RenderPipelineBuilder}};
+
+
+pub const RED: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+pub const GREEN: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+pub const BLUE: [f32; 4] = [0.2, 0.4, 1.0, 1.0];
+
+const SPHERE_SEGMENTS: u32 = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+impl DebugVertex {
+    pub fn buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ]
+        }
+    }
+}
+
+/// Collects debug lines during a frame. `Graphics` draws them over the world
+/// image at the end of `render` and then clears them, so shapes have to be
+/// submitted again every frame they should stay visible.
+///
+/// Lines are depth tested against the scene while `depth_test` is true, and
+/// drawn on top of everything otherwise. The flag applies to the calls made
+/// while it is set.
+pub struct DebugDraw {
+    pub depth_test: bool,
+    depth_tested: Vec<DebugVertex>,
+    overlay: Vec<DebugVertex>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            depth_test: true,
+            depth_tested: Vec::new(),
+            overlay: Vec::new(),
+        }
+    }
+
+    pub fn line(&mut self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, color: [f32; 4]) {
+        let vertices = if self.depth_test {
+            &mut self.depth_tested
+        } else {
+            &mut self.overlay
+        };
+
+        vertices.push(DebugVertex { position: a.into(), color });
+        vertices.push(DebugVertex { position: b.into(), color });
+    }
+
+    pub fn ray(&mut self, origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>, length: f32, color: [f32; 4]) {
+        self.line(origin, origin + direction.normalize() * length, color);
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4]) {
+        let corners = aabb_corners(aabb);
+
+        self.box_edges(&corners, color);
+    }
+
+    /// Three great circles, one around each axis.
+    pub fn sphere(&mut self, center: cgmath::Point3<f32>, radius: f32, color: [f32; 4]) {
+        let point = |axis: usize, angle: f32| {
+            let (sin, cos) = angle.sin_cos();
+            let offset = match axis {
+                0 => cgmath::Vector3::new(0.0, cos, sin),
+                1 => cgmath::Vector3::new(cos, 0.0, sin),
+                _ => cgmath::Vector3::new(cos, sin, 0.0),
+            };
+
+            center + offset * radius
+        };
+
+        (0..3).for_each(|axis| {
+            (0..SPHERE_SEGMENTS).for_each(|segment| {
+                let step = std::f32::consts::TAU / SPHERE_SEGMENTS as f32;
+
+                self.line(point(axis, segment as f32 * step), point(axis, (segment + 1) as f32 * step), color);
+            });
+        });
+    }
+
+    /// The volume a view-projection matrix sees, e.g. another camera's.
+    pub fn frustum(&mut self, view_projection: &cgmath::Matrix4<f32>, color: [f32; 4]) {
+        let Some(inverse) = view_projection.invert() else {
+            return;
+        };
+
+        // Clip-space corners in the same order as `aabb_corners`, with the
+        // OpenGL-style depth range the camera matrices use.
+        let corners = aabb_corners(&Aabb::new(cgmath::Point3::new(-1.0, -1.0, -1.0), cgmath::Point3::new(1.0, 1.0, 1.0)))
+            .map(|corner| cgmath::Point3::from_homogeneous(inverse * corner.to_homogeneous()));
+
+        self.box_edges(&corners, color);
+    }
+
+    /// The X, Y and Z axes of `transform` in red, green and blue.
+    pub fn axes(&mut self, transform: &cgmath::Matrix4<f32>, size: f32) {
+        let origin = cgmath::Point3::from_homogeneous(transform.w);
+
+        [(transform.x, RED), (transform.y, GREEN), (transform.z, BLUE)].into_iter().for_each(|(axis, color)| {
+            self.line(origin, origin + axis.truncate() * size, color);
+        });
+    }
+
+    /// A square grid on the XZ plane around `center`, with `divisions` cells per side.
+    pub fn grid(&mut self, center: cgmath::Point3<f32>, size: f32, divisions: u32, color: [f32; 4]) {
+        let half = size * 0.5;
+        let divisions = divisions.max(1);
+
+        (0..=divisions).for_each(|line| {
+            let offset = -half + size * line as f32 / divisions as f32;
+
+            self.line(center + cgmath::Vector3::new(offset, 0.0, -half), center + cgmath::Vector3::new(offset, 0.0, half), color);
+            self.line(center + cgmath::Vector3::new(-half, 0.0, offset), center + cgmath::Vector3::new(half, 0.0, offset), color);
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth_tested.is_empty() && self.overlay.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.depth_tested.clear();
+        self.overlay.clear();
+    }
+
+    /// The twelve edges between eight corners ordered by their x, y and z bits.
+    fn box_edges(&mut self, corners: &[cgmath::Point3<f32>; 8], color: [f32; 4]) {
+        const EDGES: [(usize, usize); 12] = [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ];
+
+        EDGES.iter().for_each(|(a, b)| self.line(corners[*a], corners[*b], color));
+    }
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Corner `i` takes `max` on x if bit 0 is set, on y for bit 1 and on z for bit 2.
+fn aabb_corners(aabb: &Aabb) -> [cgmath::Point3<f32>; 8] {
+    std::array::from_fn(|index| cgmath::Point3::new(
+        if index & 1 == 0 { aabb.min.x } else { aabb.max.x },
+        if index & 2 == 0 { aabb.min.y } else { aabb.max.y },
+        if index & 4 == 0 { aabb.min.z } else { aabb.max.z },
+    ))
+}
+
+/// Uploads and draws the lines of a `DebugDraw` as a line list. The vertex
+/// buffer grows to fit the largest frame seen so far.
+pub struct DebugDrawRenderer {
+    vertex_buffer: Option<Rc<Buffer>>,
+}
+
+impl DebugDrawRenderer {
+    pub fn new(ctx: &mut Context) -> Self {
+        let shader = ctx.create_shader("debug_draw_shader", "shaders/debug_draw.wgsl");
+
+        [("debug_draw_pipeline", wgpu::CompareFunction::LessEqual), ("debug_draw_overlay_pipeline", wgpu::CompareFunction::Always)]
+            .into_iter()
+            .for_each(|(label, depth_compare)| {
+                RenderPipelineBuilder::new(ctx, label, &shader)
+                    .with_vertex_buffers(&[DebugVertex::buffer_layout()])
+                    .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Alpha)
+                    .with_depth(WORLD_DEPTH_FORMAT, depth_compare, false)
+                    .with_topology(wgpu::PrimitiveTopology::LineList)
+                    .with_cull_mode(None)
+                    .build();
+            });
+
+        Self {
+            vertex_buffer: None,
+        }
+    }
+
+    pub fn render(&mut self, ctx: &mut Context, encoder: &mut wgpu::CommandEncoder, debug_draw: &DebugDraw, camera: &PerspectiveCamera) {
+        if debug_draw.is_empty() {
+            return;
+        }
+
+        let depth_tested_count = debug_draw.depth_tested.len() as u32;
+        let overlay_count = debug_draw.overlay.len() as u32;
+        let size = ((depth_tested_count + overlay_count) as usize * std::mem::size_of::<DebugVertex>()) as wgpu::BufferAddress;
+
+        if self.vertex_buffer.as_ref().is_none_or(|buffer| buffer.gpu_buffer.size() < size) {
+            self.vertex_buffer = Some(ctx.create_buffer_with_size(
+                "debug_draw_vertex_buffer",
+                size.next_power_of_two(),
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
+            ));
+        }
+
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+
+        let queue = &ctx.device.queue;
+        queue.write_buffer(&vertex_buffer.gpu_buffer, 0, bytemuck::cast_slice(&debug_draw.depth_tested));
+        queue.write_buffer(&vertex_buffer.gpu_buffer, depth_tested_count as u64 * std::mem::size_of::<DebugVertex>() as u64, bytemuck::cast_slice(&debug_draw.overlay));
+
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let world_depth_texture_view = ctx.get_texture_view("world_depth_texture_view");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_draw_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &world_color_texture_view.gpu_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &world_depth_texture_view.gpu_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_bind_group(0, &camera.bind_group.gpu_bind_group, &[]);
+
+        render_pass.set_vertex_buffer(0, vertex_buffer.gpu_buffer.slice(..));
+
+        [("debug_draw_pipeline", 0..depth_tested_count), ("debug_draw_overlay_pipeline", depth_tested_count..depth_tested_count + overlay_count)]
+            .into_iter()
+            .filter(|(_, vertices)| !vertices.is_empty())
+            .for_each(|(pipeline, vertices)| {
+                render_pass.set_pipeline(&ctx.get_render_pipeline(pipeline).gpu_render_pipeline);
+
+                render_pass.draw(vertices, 0..1);
+            });
+    }
+}
@@ -0,0 +1,455 @@
+use std::{collections::HashMap, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::world::{camera::PerspectiveCamera, model::{Model, ModelPermutation}, world::Mesh};
+
+use super::{context::{BindGroupEntry, Buffer, Context}, culling::RenderStats, graphics::{WORLD_COLOR_FORMAT, WORLD_DEPTH_FORMAT, WORLD_TARGET_SIZE}, pipeline::{BlendPreset, RenderPipelineBuilder}, render_queue::RenderState, vertex_input::Vertex};
+
+
+pub const OVERDRAW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+/// Layer count that shows as full red in the overdraw heat-map.
+const MAX_OVERDRAW: f32 = 8.0;
+
+const WIREFRAME_COLOR: [f32; 4] = [1.0, 0.55, 0.0, 0.9];
+
+/// What the world target shows. Everything except `Lit` and `Wireframe`
+/// replaces the shaded scene; the values match debug_view.wgsl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    Lit = 0,
+    /// Triangle edges drawn over the shaded scene.
+    Wireframe = 1,
+    Normals = 2,
+    Uvs = 3,
+    /// Distance from the camera, white when close.
+    Depth = 4,
+    /// How many fragments each pixel received, ignoring depth.
+    Overdraw = 5,
+    /// A random colour per mesh.
+    MeshColors = 6,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 7] = [
+        DebugView::Lit,
+        DebugView::Wireframe,
+        DebugView::Normals,
+        DebugView::Uvs,
+        DebugView::Depth,
+        DebugView::Overdraw,
+        DebugView::MeshColors,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::Lit => "Lit",
+            DebugView::Wireframe => "Wireframe",
+            DebugView::Normals => "Normals",
+            DebugView::Uvs => "UVs",
+            DebugView::Depth => "Depth",
+            DebugView::Overdraw => "Overdraw",
+            DebugView::MeshColors => "Mesh colours",
+        }
+    }
+
+    /// The view after this one in `ALL`, wrapping around.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|view| view == self).unwrap_or(0);
+
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn replaces_shading(&self) -> bool {
+        !matches!(self, DebugView::Lit | DebugView::Wireframe)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct DebugViewUniform {
+    pub wireframe_color: [f32; 4],
+    pub camera_position: [f32; 3],
+    pub far: f32,
+    pub mode: u32,
+    pub max_overdraw: f32,
+    pub _padding: [u32; 2],
+}
+
+crate::uniform_layout!(DebugViewUniform { wireframe_color, camera_position, far, mode, max_overdraw, _padding });
+
+/// A corner of a triangle in the barycentric wireframe fallback. Keeps what
+/// skinned and morphed pipelines read of the vertex it was copied from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct WireframeVertex {
+    pub vertex: Vertex,
+    pub barycentric: [f32; 3],
+    /// Of the vertex in the mesh, which indexes its morph target deltas.
+    pub vertex_index: u32,
+}
+
+const WIREFRAME_VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4, 4 => Uint16x4, 5 => Float32x4, 6 => Float32x3, 7 => Uint32];
+
+impl WireframeVertex {
+    pub fn buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<WireframeVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &WIREFRAME_VERTEX_ATTRIBUTES,
+        }
+    }
+
+    /// Unrolls an indexed mesh so that every triangle has its own corners.
+    pub fn from_mesh(mesh: &Mesh) -> Vec<WireframeVertex> {
+        const CORNERS: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+        mesh.indices
+            .iter()
+            .enumerate()
+            .map(|(corner, index)| WireframeVertex {
+                vertex: mesh.vertices[*index as usize],
+                barycentric: CORNERS[corner % 3],
+                vertex_index: *index as u32,
+            })
+            .collect()
+    }
+}
+
+struct WireframeMesh {
+    /// Held so the mesh buffer, whose address keys this entry, is not reused.
+    _source: Rc<Buffer>,
+    vertex_buffer: Rc<Buffer>,
+    vertex_count: u32,
+}
+
+/// Draws the debug views selected through `RenderSettings::debug_view`.
+///
+/// The wireframe uses `PolygonMode::Line` when the device has
+/// `POLYGON_MODE_LINE`, and otherwise unrolls each mesh once into a
+/// barycentric vertex buffer and draws edges in the fragment shader.
+pub struct DebugViewRenderer {
+    line_polygon_mode: bool,
+    wireframe_meshes: HashMap<usize, WireframeMesh>,
+}
+
+impl DebugViewRenderer {
+    pub fn new(ctx: &mut Context) -> Self {
+        let line_polygon_mode = ctx.device.features.contains(wgpu::Features::POLYGON_MODE_LINE);
+
+        // One of each for every permutation of the models' pipelines, so
+        // that the views follow their skinning and morph targets.
+        let [shaded_pipeline, ..] = ModelPermutation::ALL.map(|permutation| {
+            let shader = ctx.create_shader_permutation("debug_view_shader", "shaders/debug_view.wgsl", &permutation.defines());
+
+            if let Err(error) = shader.reflection.validate_uniform::<DebugViewUniform>(2, 0) {
+                panic!("{} does not match DebugViewUniform: {}", shader.code_path, error);
+            }
+
+            let shaded_pipeline = RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("debug_view"), &shader)
+                .with_vertex_buffers(&[permutation.vertex_layout()])
+                .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Replace)
+                .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::Less, true)
+                .build();
+
+            RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("debug_overdraw"), &shader)
+                .with_fragment_entry_point("fs_overdraw")
+                .with_vertex_buffers(&[permutation.vertex_layout()])
+                .with_color_target(OVERDRAW_FORMAT, BlendPreset::Additive)
+                .build();
+
+            if line_polygon_mode {
+                RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("debug_wireframe"), &shader)
+                    .with_fragment_entry_point("fs_wireframe")
+                    .with_vertex_buffers(&[permutation.vertex_layout()])
+                    .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Alpha)
+                    .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::LessEqual, false)
+                    .with_depth_bias(-1, -1.0, 0.0)
+                    .with_polygon_mode(wgpu::PolygonMode::Line)
+                    .with_cull_mode(None)
+                    .build();
+            } else {
+                RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("debug_wireframe"), &shader)
+                    .with_vertex_entry_point("vs_barycentric")
+                    .with_fragment_entry_point("fs_barycentric")
+                    .with_vertex_buffers(&[WireframeVertex::buffer_layout()])
+                    .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Alpha)
+                    .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::LessEqual, false)
+                    .with_cull_mode(None)
+                    .build();
+            }
+
+            shaded_pipeline
+        });
+
+        let uniform_buffer = ctx.create_buffer_with_size(
+            "debug_view_uniform_buffer",
+            std::mem::size_of::<DebugViewUniform>() as u64,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        );
+
+        let debug_bind_group_layout = shaded_pipeline.bind_group_layouts[2].clone();
+
+        ctx.create_bind_group(
+            "debug_view_bind_group",
+            &debug_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        let overdraw_texture = ctx.create_texture(
+            "debug_overdraw_texture",
+            WORLD_TARGET_SIZE,
+            1,
+            1,
+            wgpu::TextureDimension::D2,
+            OVERDRAW_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        );
+
+        let overdraw_view = ctx.create_texture_view(&overdraw_texture.gpu_texture, "debug_overdraw_texture_view");
+
+        let resolve_shader = ctx.create_shader("debug_overdraw_shader", "shaders/debug_overdraw.wgsl");
+
+        let resolve_pipeline = RenderPipelineBuilder::new(ctx, "debug_overdraw_resolve_pipeline", &resolve_shader)
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Replace)
+            .build();
+
+        let resolve_bind_group_layout = resolve_pipeline.bind_group_layouts[0].clone();
+
+        ctx.create_bind_group(
+            "debug_overdraw_bind_group",
+            &resolve_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&overdraw_view.gpu_texture_view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        Self {
+            line_polygon_mode,
+            wireframe_meshes: HashMap::new(),
+        }
+    }
+
+    /// Creates the barycentric wireframe buffers of meshes seen for the first
+    /// time. Does nothing when lines are rasterised natively.
+    pub fn prepare(&mut self, ctx: &mut Context, meshes: &[(&Model, &Mesh)]) {
+        if self.line_polygon_mode {
+            return;
+        }
+
+        meshes.iter().for_each(|(_, mesh)| {
+            let key = Rc::as_ptr(&mesh.vertex_buffer) as usize;
+
+            if self.wireframe_meshes.contains_key(&key) {
+                return;
+            }
+
+            let vertices = WireframeVertex::from_mesh(mesh);
+
+            let vertex_buffer = ctx.create_buffer("debug_wireframe_vertex_buffer", bytemuck::cast_slice(&vertices), wgpu::BufferUsages::VERTEX);
+
+            self.wireframe_meshes.insert(key, WireframeMesh {
+                _source: mesh.vertex_buffer.clone(),
+                vertex_buffer,
+                vertex_count: vertices.len() as u32,
+            });
+        });
+    }
+
+    /// Draws `view` into the world target. `Wireframe` is drawn over what is
+    /// already there, the other views clear it. Returns the counters of the pass.
+    pub fn render(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera, view: DebugView) -> RenderStats {
+        let uniform = DebugViewUniform {
+            wireframe_color: WIREFRAME_COLOR,
+            camera_position: camera.position().into(),
+            far: camera.far(),
+            mode: view as u32,
+            max_overdraw: MAX_OVERDRAW,
+            _padding: [0; 2],
+        };
+
+        ctx.device.queue.write_buffer(&ctx.get_buffer("debug_view_uniform_buffer").gpu_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        match view {
+            DebugView::Lit => RenderStats::default(),
+            DebugView::Wireframe => self.render_wireframe(ctx, encoder, meshes, camera),
+            DebugView::Overdraw => self.render_overdraw(ctx, encoder, meshes, camera),
+            _ => self.render_shaded(ctx, encoder, meshes, camera),
+        }
+    }
+
+    fn render_shaded(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera) -> RenderStats {
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let world_depth_texture_view = ctx.get_texture_view("world_depth_texture_view");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_view_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &world_color_texture_view.gpu_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &world_depth_texture_view.gpu_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        self.draw_meshes(ctx, &mut render_pass, meshes, camera, "debug_view")
+    }
+
+    fn render_overdraw(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera) -> RenderStats {
+        let overdraw_view = ctx.get_texture_view("debug_overdraw_texture_view");
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let resolve_pipeline = ctx.get_render_pipeline("debug_overdraw_resolve_pipeline");
+        let resolve_bind_group = ctx.get_bind_group("debug_overdraw_bind_group");
+
+        let stats = {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("debug_overdraw_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &overdraw_view.gpu_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    }
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            self.draw_meshes(ctx, &mut render_pass, meshes, camera, "debug_overdraw")
+        };
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("debug_overdraw_resolve_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &world_color_texture_view.gpu_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    }
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&resolve_pipeline.gpu_render_pipeline);
+
+            render_pass.set_bind_group(0, &resolve_bind_group.gpu_bind_group, &[]);
+
+            render_pass.draw(0..3, 0..1);
+        }
+
+        stats
+    }
+
+    fn render_wireframe(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera) -> RenderStats {
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let world_depth_texture_view = ctx.get_texture_view("world_depth_texture_view");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_wireframe_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &world_color_texture_view.gpu_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &world_depth_texture_view.gpu_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        if self.line_polygon_mode {
+            return self.draw_meshes(ctx, &mut render_pass, meshes, camera, "debug_wireframe");
+        }
+
+        let mut state = RenderState::default();
+
+        meshes.iter().for_each(|(model, mesh)| {
+            let Some(wireframe) = self.wireframe_meshes.get(&(Rc::as_ptr(&mesh.vertex_buffer) as usize)) else {
+                return;
+            };
+
+            state.set_pipeline(&mut render_pass, ctx.get_render_pipeline(&model.permutation().pipeline_label("debug_wireframe")));
+
+            state.set_bind_group(&mut render_pass, 0, &camera.bind_group);
+
+            state.set_bind_group(&mut render_pass, 1, model.draw_bind_group(mesh));
+
+            state.set_bind_group(&mut render_pass, 2, ctx.get_bind_group("debug_view_bind_group"));
+
+            state.set_vertex_buffer(&mut render_pass, &wireframe.vertex_buffer);
+
+            state.draw(&mut render_pass, 0..wireframe.vertex_count);
+        });
+
+        state.stats
+    }
+
+    /// Draws every mesh with the permutation of the given `debug_view.wgsl`
+    /// pipeline that matches its model, passing a per-mesh id as the instance
+    /// index for `DebugView::MeshColors`.
+    fn draw_meshes<'a>(&self, ctx: &'a Context, render_pass: &mut wgpu::RenderPass<'a>, meshes: &[(&'a Model, &'a Mesh)], camera: &'a PerspectiveCamera, pipeline: &str) -> RenderStats {
+        let mut state = RenderState::default();
+
+        meshes.iter().for_each(|(model, mesh)| {
+            let mesh_id = (*mesh as *const Mesh as usize >> 3) as u32;
+
+            state.set_pipeline(render_pass, ctx.get_render_pipeline(&model.permutation().pipeline_label(pipeline)));
+
+            state.set_bind_group(render_pass, 0, &camera.bind_group);
+
+            state.set_bind_group(render_pass, 1, model.draw_bind_group(mesh));
+
+            state.set_bind_group(render_pass, 2, ctx.get_bind_group("debug_view_bind_group"));
+
+            state.set_vertex_buffer(render_pass, &mesh.vertex_buffer);
+
+            let (index_buffer, num_elements) = mesh.lod(model.lod);
+
+            state.set_index_buffer(render_pass, index_buffer, wgpu::IndexFormat::Uint16);
+
+            state.draw_indexed_instance(render_pass, 0..num_elements, mesh_id);
+        });
+
+        state.stats
+    }
+}
@@ -0,0 +1,230 @@
+use std::rc::Rc;
+
+use crate::world::{camera::PerspectiveCamera, model::Model, world::Mesh};
+
+use super::{context::{BindGroup, BindGroupEntry, Context, RenderPipeline, TextureView}, graphics::{WORLD_COLOR_FORMAT, WORLD_TARGET_SIZE}, lighting::SceneLightingUniform, picking::{id_attachment, PickIds}, pipeline::{BlendPreset, RenderPipelineBuilder}, render_queue::{RenderQueue, RenderState}, culling::RenderStats};
+
+
+pub const GBUFFER_ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+pub const GBUFFER_NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const GBUFFER_MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Which shading path a `Graphics` instance renders the world with. Forward
+/// unless another is asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderPath {
+    #[default]
+    Forward,
+    Deferred,
+    /// Forward shading with culling and draw submission done on the GPU, see `GpuDrivenRenderer`.
+    GpuDriven,
+}
+
+/// What the deferred lighting pass writes to the HDR target. Anything other
+/// than `Lit` displays a single G-buffer channel for debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GBufferView {
+    Lit = 0,
+    Albedo = 1,
+    Normal = 2,
+    Material = 3,
+    Depth = 4,
+}
+
+impl GBufferView {
+    pub const ALL: [GBufferView; 5] = [
+        GBufferView::Lit,
+        GBufferView::Albedo,
+        GBufferView::Normal,
+        GBufferView::Material,
+        GBufferView::Depth,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GBufferView::Lit => "Lit",
+            GBufferView::Albedo => "Albedo",
+            GBufferView::Normal => "Normal",
+            GBufferView::Material => "Metallic / Roughness",
+            GBufferView::Depth => "Depth",
+        }
+    }
+}
+
+/// Colour targets of the G-buffer pass, in attachment order, before the pick id.
+pub fn gbuffer_color_targets() -> [Option<wgpu::ColorTargetState>; 3] {
+    [GBUFFER_ALBEDO_FORMAT, GBUFFER_NORMAL_FORMAT, GBUFFER_MATERIAL_FORMAT].map(|format| {
+        Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL
+        })
+    })
+}
+
+/// Renders opaque meshes into a G-buffer and lights them in a full-screen pass.
+/// Only created for `RenderPath::Deferred`.
+pub struct DeferredRenderer {
+    albedo_view: Rc<TextureView>,
+    normal_view: Rc<TextureView>,
+    material_view: Rc<TextureView>,
+    world_color_view: Rc<TextureView>,
+    world_depth_view: Rc<TextureView>,
+    pick_id_view: Rc<TextureView>,
+    gbuffer_bind_group: Rc<BindGroup>,
+    lighting_pipeline: Rc<RenderPipeline>,
+}
+
+impl DeferredRenderer {
+    pub fn new(
+        ctx: &mut Context,
+        world_color_texture_view: &Rc<TextureView>,
+        world_depth_texture_view: &Rc<TextureView>,
+        pick_id_texture_view: &Rc<TextureView>,
+    ) -> Self {
+        let [albedo_view, normal_view, material_view] = [
+            ("gbuffer_albedo", GBUFFER_ALBEDO_FORMAT),
+            ("gbuffer_normal", GBUFFER_NORMAL_FORMAT),
+            ("gbuffer_material", GBUFFER_MATERIAL_FORMAT),
+        ].map(|(label, format)| {
+            let texture = ctx.create_texture(
+                &format!("{}_texture", label),
+                WORLD_TARGET_SIZE,
+                1,
+                1,
+                wgpu::TextureDimension::D2,
+                format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            );
+
+            ctx.create_texture_view(&texture.gpu_texture, &format!("{}_texture_view", label))
+        });
+
+        let lighting_shader = ctx.create_shader("deferred_lighting_shader", "shaders/deferred_lighting.wgsl");
+
+        if let Err(error) = lighting_shader.reflection.validate_uniform::<SceneLightingUniform>(1, 0) {
+            panic!("{} does not match SceneLightingUniform: {}", lighting_shader.code_path, error);
+        }
+
+        let lighting_pipeline = RenderPipelineBuilder::new(ctx, "deferred_lighting_pipeline", &lighting_shader)
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Replace)
+            .build();
+
+        let gbuffer_bind_group_layout = lighting_pipeline.bind_group_layouts[0].clone();
+
+        let gbuffer_bind_group = ctx.create_bind_group(
+            "gbuffer_bind_group",
+            &gbuffer_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&albedo_view.gpu_texture_view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&normal_view.gpu_texture_view)
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&material_view.gpu_texture_view)
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&world_depth_texture_view.gpu_texture_view)
+                },
+            ]
+        );
+
+        Self {
+            albedo_view,
+            normal_view,
+            material_view,
+            world_color_view: world_color_texture_view.clone(),
+            world_depth_view: world_depth_texture_view.clone(),
+            pick_id_view: pick_id_texture_view.clone(),
+            gbuffer_bind_group,
+            lighting_pipeline,
+        }
+    }
+
+    /// Picks up the lighting pipeline if it was rebuilt after a shader hot
+    /// reload.
+    pub fn refresh_pipelines(&mut self, ctx: &Context) {
+        if let Some(current) = ctx.render_pipelines.get(&self.lighting_pipeline.label) {
+            if !Rc::ptr_eq(current, &self.lighting_pipeline) {
+                self.lighting_pipeline = current.clone();
+            }
+        }
+    }
+
+    /// Rasterises every model into the G-buffer and the pick ids, then resolves
+    /// lighting for each pixel into the world colour target.
+    /// Returns the counters of the G-buffer pass.
+    pub fn render(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera, ids: &PickIds) -> RenderStats {
+        let lights_bind_group = ctx.get_bind_group("lights_bind_group");
+
+        let mut state = RenderState::default();
+
+        let clear_attachment = |view| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            }
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("gbuffer_render_pass"),
+                color_attachments: &[
+                    clear_attachment(&self.albedo_view.gpu_texture_view),
+                    clear_attachment(&self.normal_view.gpu_texture_view),
+                    clear_attachment(&self.material_view.gpu_texture_view),
+                    id_attachment(&self.pick_id_view, true),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.world_depth_view.gpu_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            state.set_bind_group(&mut render_pass, 0, &camera.bind_group);
+
+            RenderQueue::opaque(meshes, camera, |model| model.gbuffer_pipeline.as_ref()).submit(&mut render_pass, &mut state, Some(ids));
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("deferred_lighting_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.world_color_view.gpu_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    }
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&self.lighting_pipeline.gpu_render_pipeline);
+
+            render_pass.set_bind_group(0, &self.gbuffer_bind_group.gpu_bind_group, &[]);
+
+            render_pass.set_bind_group(1, &lights_bind_group.gpu_bind_group, &[]);
+
+            render_pass.draw(0..3, 0..1);
+        }
+
+        state.stats
+    }
+}
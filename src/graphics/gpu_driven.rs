@@ -0,0 +1,404 @@
+use std::{collections::{HashMap, HashSet}, rc::Rc};
+
+use crate::world::{camera::PerspectiveCamera, material::AlphaMode, model::{Model, ModelPermutation}, world::{Mesh, World}};
+
+use super::{context::{BindGroupEntry, BindGroupLayout, Buffer, ComputePass, Context, ReadbackBuffer}, culling::RenderStats, graphics::{WORLD_COLOR_FORMAT, WORLD_DEPTH_FORMAT}, picking::{id_attachment, PickIds, PICKING_ID_FORMAT}, pipeline::{BlendPreset, RenderPipelineBuilder}, preprocessor::Defines, vertex_input::Vertex};
+
+
+pub const CULL_WORKGROUP_SIZE: u32 = 64;
+
+const DRAW_INDEXED_INDIRECT_SIZE: wgpu::BufferAddress = std::mem::size_of::<wgpu::util::DrawIndexedIndirect>() as wgpu::BufferAddress;
+
+/// Everything the cull and draw shaders need to know about one mesh instance.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuObject {
+    pub model: [[f32; 4]; 4],
+    pub base_color: [f32; 4],
+    pub bounds_center: [f32; 3],
+    pub metallic: f32,
+    pub bounds_extents: [f32; 3],
+    pub roughness: f32,
+    pub index_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub pick_id: u32,
+}
+
+crate::uniform_layout!(GpuObject { model, base_color, bounds_center, metallic, bounds_extents, roughness, index_count, first_index, base_vertex, pick_id });
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CullUniform {
+    pub planes: [[f32; 4]; 6],
+    pub object_count: u32,
+    pub culling_enabled: u32,
+    pub _padding: [u32; 2],
+}
+
+crate::uniform_layout!(CullUniform { planes, object_count, culling_enabled, _padding });
+
+/// The per-frame inputs and outputs of `GpuDrivenRenderer::render`.
+pub struct GpuDrivenFrame<'a> {
+    pub frustum_culling: bool,
+    /// The ids written for each mesh, see `PickIds`.
+    pub ids: &'a PickIds,
+    pub stats: &'a mut RenderStats,
+}
+
+/// Where a mesh lives in the shared vertex and index buffers.
+struct MeshRange {
+    /// Held so the mesh buffer, whose address keys this entry, is not reused.
+    _source: Rc<Buffer>,
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+}
+
+/// Renders the world with all geometry in shared vertex and index buffers and
+/// one indirect draw per mesh instance. A compute pass culls the instances
+/// against the camera frustum and writes the draw commands, which are then
+/// issued with a single `multi_draw_indexed_indirect` when the device supports
+/// it, and one `draw_indexed_indirect` per instance otherwise.
+///
+/// The shared buffers hold rest-pose vertices without joints, and the one
+/// pipeline neither blends nor discards, so skinned, morphed, blended and
+/// alpha-masked models are left out, see `GpuDrivenRenderer::draws`, for the
+/// caller to draw with their own pipelines.
+pub struct GpuDrivenRenderer {
+    cull_bind_group_layout: Rc<BindGroupLayout>,
+    objects_bind_group_layout: Rc<BindGroupLayout>,
+    /// Keyed by the address of a mesh's vertex buffer.
+    mesh_ranges: HashMap<usize, MeshRange>,
+    object_capacity: u32,
+    visible_count_readback: ReadbackBuffer,
+    visible_count: Option<u32>,
+}
+
+impl GpuDrivenRenderer {
+    /// Draws pass the object index as their first instance, which needs
+    /// `INDIRECT_FIRST_INSTANCE`.
+    pub fn is_supported(ctx: &Context) -> bool {
+        ctx.device.features.contains(wgpu::Features::INDIRECT_FIRST_INSTANCE)
+    }
+
+    /// Whether `model` is drawn by this renderer rather than with its own
+    /// pipelines, which only it does for untextured opaque materials.
+    pub fn draws(model: &Model) -> bool {
+        model.permutation() == ModelPermutation::default()
+            && model.material.alpha_mode == AlphaMode::Opaque
+            && model.material.base_color_texture.is_none()
+    }
+
+    pub fn new(ctx: &mut Context) -> Self {
+        let defines = Defines::from([("WORKGROUP_SIZE".to_string(), CULL_WORKGROUP_SIZE.to_string())]);
+
+        let cull_shader = ctx.create_shader_permutation("gpu_cull_shader", "shaders/gpu_cull.wgsl", &defines);
+
+        let cull_validation = cull_shader.reflection.validate_uniform::<CullUniform>(0, 0)
+            .and_then(|_| cull_shader.reflection.validate_uniform::<GpuObject>(0, 1));
+
+        if let Err(error) = cull_validation {
+            panic!("{} does not match the GPU-driven layouts: {}", cull_shader.code_path, error);
+        }
+
+        let cull_bind_group_layouts = ctx.create_reflected_bind_group_layouts("gpu_driven_cull_pipeline", &cull_shader)
+            .unwrap_or_else(|error| panic!("Failed to reflect {}: {}", cull_shader.code_path, error));
+
+        let cull_pipeline_layout = ctx.device.logical_device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("gpu_driven_cull_pipeline_layout"),
+            bind_group_layouts: &[&cull_bind_group_layouts[0].gpu_bind_group_layout],
+            push_constant_ranges: &[]
+        });
+
+        ctx.create_compute_pipeline("gpu_driven_cull_pipeline", cull_pipeline_layout, &cull_shader.shader, "cs_main");
+
+        let shader = ctx.create_shader("gpu_driven_shader", "shaders/gpu_driven.wgsl");
+
+        let validation = shader.reflection.validate_vertex_layout("vs_main", &[Vertex::buffer_layout()])
+            .and_then(|_| shader.reflection.validate_uniform::<GpuObject>(1, 0));
+
+        if let Err(error) = validation {
+            panic!("{} does not match the GPU-driven layouts: {}", shader.code_path, error);
+        }
+
+        let pipeline = RenderPipelineBuilder::new(ctx, "gpu_driven_pipeline", &shader)
+            .with_vertex_buffers(&[Vertex::buffer_layout()])
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Replace)
+            .with_color_target(PICKING_ID_FORMAT, BlendPreset::Disabled)
+            .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::Less, true)
+            .build();
+
+        ctx.create_buffer(
+            "gpu_driven_cull_uniform_buffer",
+            bytemuck::cast_slice(&[<CullUniform as bytemuck::Zeroable>::zeroed()]),
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        );
+
+        ctx.create_storage_buffer("gpu_driven_visible_count_buffer", bytemuck::cast_slice(&[0u32]), wgpu::BufferUsages::empty());
+
+        let visible_count_readback = ReadbackBuffer::new(&ctx.device.logical_device, "gpu_driven_visible_count_readback", 4);
+
+        let mut renderer = Self {
+            cull_bind_group_layout: cull_bind_group_layouts[0].clone(),
+            objects_bind_group_layout: pipeline.bind_group_layouts[1].clone(),
+            mesh_ranges: HashMap::new(),
+            object_capacity: 0,
+            visible_count_readback,
+            visible_count: None,
+        };
+
+        renderer.allocate_objects(ctx, CULL_WORKGROUP_SIZE);
+
+        renderer
+    }
+
+    /// (Re)creates the object and indirect buffers and the bind groups using them.
+    fn allocate_objects(&mut self, ctx: &mut Context, capacity: u32) {
+        let object_buffer = ctx.create_buffer_with_size(
+            "gpu_driven_object_buffer",
+            capacity as wgpu::BufferAddress * std::mem::size_of::<GpuObject>() as wgpu::BufferAddress,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
+        );
+
+        let indirect_buffer = ctx.create_buffer_with_size(
+            "gpu_driven_indirect_buffer",
+            capacity as wgpu::BufferAddress * DRAW_INDEXED_INDIRECT_SIZE,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT
+        );
+
+        let cull_uniform_buffer = ctx.buffers["gpu_driven_cull_uniform_buffer"].clone();
+        let visible_count_buffer = ctx.buffers["gpu_driven_visible_count_buffer"].clone();
+
+        ctx.create_bind_group(
+            "gpu_driven_cull_bind_group",
+            &self.cull_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: cull_uniform_buffer.gpu_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: object_buffer.gpu_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: indirect_buffer.gpu_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: visible_count_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        ctx.create_bind_group(
+            "gpu_driven_objects_bind_group",
+            &self.objects_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: object_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        self.object_capacity = capacity;
+    }
+
+    /// Packs every distinct mesh of the world into the shared buffers, if
+    /// meshes were added or removed since the last call.
+    fn upload_geometry(&mut self, ctx: &mut Context, world: &World) {
+        let meshes = world.models.iter().filter(|model| Self::draws(model)).flat_map(|model| model.meshes.iter());
+
+        let keys: HashSet<usize> = meshes.clone().map(mesh_key).collect();
+
+        if keys.len() == self.mesh_ranges.len() && keys.iter().all(|key| self.mesh_ranges.contains_key(key)) {
+            return;
+        }
+
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        self.mesh_ranges.clear();
+
+        meshes.for_each(|mesh| {
+            self.mesh_ranges.entry(mesh_key(mesh)).or_insert_with(|| {
+                let range = MeshRange {
+                    _source: mesh.vertex_buffer.clone(),
+                    first_index: indices.len() as u32,
+                    index_count: mesh.indices.len() as u32,
+                    base_vertex: vertices.len() as i32,
+                };
+
+                vertices.extend_from_slice(&mesh.vertices);
+                indices.extend(mesh.indices.iter().map(|index| *index as u32));
+
+                range
+            });
+        });
+
+        ctx.create_buffer("gpu_driven_vertex_buffer", bytemuck::cast_slice(&vertices), wgpu::BufferUsages::VERTEX);
+
+        ctx.create_buffer("gpu_driven_index_buffer", bytemuck::cast_slice(&indices), wgpu::BufferUsages::INDEX);
+    }
+
+    /// Uploads the world's objects, culls them on the GPU and draws the
+    /// survivors into the world colour, depth and pick id targets, clearing
+    /// them first. The drawn count added to `frame.stats` lags a frame or two
+    /// behind, as it is read back asynchronously.
+    pub fn render(
+        &mut self,
+        ctx: &mut Context,
+        encoder: &mut wgpu::CommandEncoder,
+        world: &World,
+        camera: &PerspectiveCamera,
+        frame: GpuDrivenFrame,
+    ) {
+        let GpuDrivenFrame { frustum_culling, ids, stats } = frame;
+
+        if let Some(data) = self.visible_count_readback.try_read(&ctx.device.logical_device) {
+            self.visible_count = Some(*bytemuck::from_bytes::<u32>(&data));
+        }
+
+        self.upload_geometry(ctx, world);
+
+        let mesh_ranges = &self.mesh_ranges;
+
+        let objects: Vec<GpuObject> = world.models.iter().filter(|model| Self::draws(model)).flat_map(|model| {
+            let model_matrix = model.model_matrix();
+            let material = model.material.clone();
+
+            model.meshes.iter().map(move |mesh| {
+                let range = &mesh_ranges[&mesh_key(mesh)];
+
+                GpuObject {
+                    model: model_matrix.into(),
+                    base_color: material.base_color,
+                    bounds_center: mesh.bounds.center().into(),
+                    metallic: material.metallic,
+                    bounds_extents: mesh.bounds.extents().into(),
+                    roughness: material.roughness,
+                    index_count: range.index_count,
+                    first_index: range.first_index,
+                    base_vertex: range.base_vertex,
+                    pick_id: ids.id(mesh),
+                }
+            })
+        }).collect();
+
+        let object_count = objects.len() as u32;
+
+        if object_count > self.object_capacity {
+            self.allocate_objects(ctx, object_count.next_power_of_two());
+        }
+
+        let cull_uniform = CullUniform {
+            planes: camera.frustum().planes.map(|plane| [plane.normal.x, plane.normal.y, plane.normal.z, plane.distance]),
+            object_count,
+            culling_enabled: frustum_culling as u32,
+            _padding: [0; 2],
+        };
+
+        ctx.device.queue.write_buffer(&ctx.get_buffer("gpu_driven_object_buffer").gpu_buffer, 0, bytemuck::cast_slice(&objects));
+        ctx.device.queue.write_buffer(&ctx.get_buffer("gpu_driven_cull_uniform_buffer").gpu_buffer, 0, bytemuck::cast_slice(&[cull_uniform]));
+
+        if object_count > 0 {
+            let visible_count_buffer = ctx.get_buffer("gpu_driven_visible_count_buffer");
+
+            encoder.clear_buffer(&visible_count_buffer.gpu_buffer, 0, None);
+
+            ComputePass {
+                label: "gpu_driven_cull_pass".to_string(),
+                pipeline: "gpu_driven_cull_pipeline".to_string(),
+                bind_groups: vec!["gpu_driven_cull_bind_group".to_string()],
+                workgroups: (object_count.div_ceil(CULL_WORKGROUP_SIZE), 1, 1),
+            }.record(ctx, encoder);
+
+            self.visible_count_readback.copy_from_buffer(encoder, &visible_count_buffer.gpu_buffer, 0);
+        }
+
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let world_depth_texture_view = ctx.get_texture_view("world_depth_texture_view");
+        let pick_id_texture_view = ctx.get_texture_view("picking_id_texture_view");
+        let lights_bind_group = ctx.get_bind_group("lights_bind_group");
+        let objects_bind_group = ctx.get_bind_group("gpu_driven_objects_bind_group");
+        let pipeline = ctx.get_render_pipeline("gpu_driven_pipeline");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("gpu_driven_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &world_color_texture_view.gpu_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.1,
+                        g: 0.2,
+                        b: 0.3,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                }
+            }), id_attachment(pick_id_texture_view, true)],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &world_depth_texture_view.gpu_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        let drawn = self.visible_count.unwrap_or(object_count).min(object_count);
+
+        stats.drawn += drawn;
+        stats.culled += object_count - drawn;
+
+        if object_count == 0 {
+            return;
+        }
+
+        let vertex_buffer = ctx.get_buffer("gpu_driven_vertex_buffer");
+        let index_buffer = ctx.get_buffer("gpu_driven_index_buffer");
+        let indirect_buffer = ctx.get_buffer("gpu_driven_indirect_buffer");
+
+        render_pass.set_pipeline(&pipeline.gpu_render_pipeline);
+
+        render_pass.set_bind_group(0, &camera.bind_group.gpu_bind_group, &[]);
+
+        render_pass.set_bind_group(1, &objects_bind_group.gpu_bind_group, &[]);
+
+        render_pass.set_bind_group(2, &lights_bind_group.gpu_bind_group, &[]);
+
+        render_pass.set_vertex_buffer(0, vertex_buffer.gpu_buffer.slice(..));
+
+        render_pass.set_index_buffer(index_buffer.gpu_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 3;
+
+        if ctx.device.features.contains(wgpu::Features::MULTI_DRAW_INDIRECT) {
+            render_pass.multi_draw_indexed_indirect(&indirect_buffer.gpu_buffer, 0, object_count);
+            stats.draw_calls += 1;
+        } else {
+            stats.draw_calls += object_count;
+
+            (0..object_count as wgpu::BufferAddress).for_each(|index| {
+                render_pass.draw_indexed_indirect(&indirect_buffer.gpu_buffer, index * DRAW_INDEXED_INDIRECT_SIZE);
+            });
+        }
+    }
+
+    /// Starts reading back the visible count. Call after submitting the frame.
+    pub fn after_submit(&self) {
+        self.visible_count_readback.map();
+    }
+}
+
+fn mesh_key(mesh: &Mesh) -> usize {
+    Rc::as_ptr(&mesh.vertex_buffer) as usize
+}
@@ -3,20 +3,40 @@ use egui_wgpu::renderer::ScreenDescriptor;
 use winit::{dpi::PhysicalSize, window::Window};
 
 
-use crate::world::{camera::PerspectiveCamera, world::World};
+use crate::world::{camera::PerspectiveCamera, model::Model, world::{Mesh, World}};
 
-use super::{context::{BindGroupEntry, BindGroupLayoutEntry, Context}, gui::{example_gui, Gui}, renderer::Renderer, vertex_input::{Vertex, SQUARE_INDICES, SQUARE_VERTICES}};
+use super::{context::{BindGroupEntry, BindGroupLayoutEntry, ComputePass, Context}, culling::{visible_meshes, visible_model_meshes, RenderStats}, debug_draw::{DebugDraw, DebugDrawRenderer}, debug_view::{DebugView, DebugViewRenderer}, deferred::{DeferredRenderer, RenderPath}, gpu_driven::{GpuDrivenFrame, GpuDrivenRenderer}, grid::GridRenderer, lod::{select_lods, LodSettings}, outline::OutlineRenderer, picking::{id_attachment, PickIds, PickResult, Picker}, gui::{animation_gui, example_gui, render_settings_gui, selection_gui, shader_errors_gui, Gui}, hot_reload::ShaderHotReloader, render_queue::{RenderQueue, RenderState}, lighting::Lighting, pipeline::{BlendPreset, RenderPipelineBuilder}, renderer::{RenderSettings, Renderer}, transparency::TransparencyRenderer, vertex_input::{Vertex, SQUARE_INDICES, SQUARE_VERTICES}};
 
 
+pub const WORLD_TARGET_SIZE: wgpu::Extent3d = wgpu::Extent3d { width: 1600, height: 1200, depth_or_array_layers: 1 };
+pub const WORLD_COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const WORLD_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
 pub struct Graphics {
     pub ctx: Context,
+    pub settings: RenderSettings,
     size: winit::dpi::PhysicalSize<u32>,
     renderer: Renderer,
     gui: Gui,
+    lighting: Lighting,
+    render_path: RenderPath,
+    deferred: Option<DeferredRenderer>,
+    gpu_driven: Option<GpuDrivenRenderer>,
+    transparency: TransparencyRenderer,
+    debug_views: DebugViewRenderer,
+    /// Lines to draw this frame, cleared after every `render`.
+    pub debug_draw: DebugDraw,
+    debug_draw_renderer: DebugDrawRenderer,
+    grid: GridRenderer,
+    picker: Picker,
+    outline: OutlineRenderer,
+    compute_passes: Vec<ComputePass>,
+    pub stats: RenderStats,
+    shader_hot_reloader: ShaderHotReloader,
 }
 
 impl Graphics {
-    pub async fn new(window: &Window) -> Self 
+    pub async fn new(window: &Window, render_path: RenderPath) -> Self 
     {
         let size = window.inner_size();
 
@@ -28,11 +48,11 @@ impl Graphics {
 
         let world_color_texture = ctx.create_texture(
             "world_color_texture", 
-            wgpu::Extent3d { width: 1600, height: 1200, depth_or_array_layers: 1,}, 
+            WORLD_TARGET_SIZE, 
             1, 
             1, 
             wgpu::TextureDimension::D2, 
-            wgpu::TextureFormat::Rgba8UnormSrgb, 
+            WORLD_COLOR_FORMAT, 
             wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
         );
 
@@ -40,12 +60,12 @@ impl Graphics {
 
         let world_depth_texture = ctx.create_texture(
             "world_depth_texture", 
-            wgpu::Extent3d { width: 1600, height: 1200, depth_or_array_layers: 1,}, 
+            WORLD_TARGET_SIZE, 
             1, 
             1, 
             wgpu::TextureDimension::D2, 
-            wgpu::TextureFormat::Depth32Float, 
-            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            WORLD_DEPTH_FORMAT, 
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC
         );
             
         let world_depth_texture_view = ctx.create_texture_view(&world_depth_texture.gpu_texture, "world_depth_texture_view");
@@ -101,101 +121,220 @@ impl Graphics {
         );
 
 
-        let screen_quad_shader = ctx.create_shader("screen_quad_shader", "./src/assets/shaders/screen_quad.wgsl");
+        let screen_quad_shader = ctx.create_shader("screen_quad_shader", "shaders/screen_quad.wgsl");
 
-        let screen_quad_pipeline_layout = ctx.device.logical_device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("screen_quad_pipeline_layout"),
-            bind_group_layouts: &[
-                &world_texture_bind_group_layout.gpu_bind_group_layout,
-            ],
-            push_constant_ranges: &[]
-        });
+        let surface_format = ctx.surface_config.format;
 
-        ctx.create_render_pipeline(
-            "screen_quad_pipeline",
-            screen_quad_pipeline_layout,
-            &screen_quad_shader.shader,
-            &[Vertex::buffer_layout()],
-            Some(wgpu::ColorTargetState {
-                format: ctx.surface_config.format,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL
-            }),
-            None,
-            wgpu::PrimitiveTopology::TriangleList,
-            wgpu::PolygonMode::Fill
-        );
+        RenderPipelineBuilder::new(&mut ctx, "screen_quad_pipeline", &screen_quad_shader)
+            .with_bind_group_layouts(vec![world_texture_bind_group_layout])
+            .with_vertex_buffers(&[Vertex::buffer_layout()])
+            .with_color_target(surface_format, BlendPreset::Replace)
+            .build();
+
+        let lighting = Lighting::new(&mut ctx);
+
+        let render_path = match render_path {
+            RenderPath::GpuDriven if !GpuDrivenRenderer::is_supported(&ctx) => {
+                log::warn!("GPU-driven rendering needs INDIRECT_FIRST_INSTANCE, falling back to forward rendering");
+                RenderPath::Forward
+            },
+            render_path => render_path,
+        };
+
+        let picker = Picker::new(&mut ctx);
+
+        let deferred = match render_path {
+            RenderPath::Deferred => Some(DeferredRenderer::new(&mut ctx, &world_color_texture_view, &world_depth_texture_view, picker.id_view())),
+            _ => None,
+        };
+
+        let gpu_driven = match render_path {
+            RenderPath::GpuDriven => Some(GpuDrivenRenderer::new(&mut ctx)),
+            _ => None,
+        };
+
+        let transparency = TransparencyRenderer::new(&mut ctx);
+
+        let debug_views = DebugViewRenderer::new(&mut ctx);
+
+        let debug_draw_renderer = DebugDrawRenderer::new(&mut ctx);
+
+        let grid = GridRenderer::new(&mut ctx);
+
+        let outline = OutlineRenderer::new(&mut ctx, &world_depth_texture_view, surface_format);
 
         Self {
             ctx,
+            settings: RenderSettings::default(),
             size,
             renderer,
             gui,
+            lighting,
+            render_path,
+            deferred,
+            gpu_driven,
+            transparency,
+            debug_views,
+            debug_draw: DebugDraw::new(),
+            debug_draw_renderer,
+            grid,
+            picker,
+            outline,
+            compute_passes: Vec::new(),
+            stats: RenderStats::default(),
+            shader_hot_reloader: ShaderHotReloader::new(),
         }
     }
 
+    /// Recompiles shaders that changed on disk and hands the rebuilt pipelines
+    /// to the world and the deferred renderer. Meant to be called once per
+    /// frame.
+    pub fn reload_shaders(&mut self, world: &mut World) {
+        if self.shader_hot_reloader.poll(&mut self.ctx) {
+            world.refresh_pipelines(&self.ctx);
+
+            if let Some(deferred) = self.deferred.as_mut() {
+                deferred.refresh_pipelines(&self.ctx);
+            }
+        }
+    }
+
+    /// Registers a compute dispatch that is recorded at the start of every
+    /// frame, so its results are visible to all render passes of that frame.
+    pub fn add_compute_pass(&mut self, compute_pass: ComputePass) {
+        self.compute_passes.push(compute_pass);
+    }
+
+    pub fn remove_compute_pass(&mut self, label: &str) {
+        self.compute_passes.retain(|compute_pass| compute_pass.label != label);
+    }
+
+    /// Queues a pick at a position in window pixels. The result comes out of
+    /// `poll_pick` once the GPU has finished, usually a frame or two later.
+    pub fn pick(&mut self, window_position: (f32, f32)) {
+        let x = window_position.0 / self.ctx.surface_config.width as f32 * WORLD_TARGET_SIZE.width as f32;
+        let y = window_position.1 / self.ctx.surface_config.height as f32 * WORLD_TARGET_SIZE.height as f32;
+
+        self.picker.request((x.max(0.0) as u32, y.max(0.0) as u32));
+    }
+
+    pub fn poll_pick(&mut self) -> Option<PickResult> {
+        self.picker.poll(&self.ctx)
+    }
+
+    pub fn render_path(&self) -> RenderPath {
+        self.render_path
+    }
+
     pub fn resize(&mut self, new_size: &PhysicalSize<u32>) {
         self.ctx.resize(new_size);
     }
 
-    pub fn render(&mut self, world: &World, camera: &PerspectiveCamera, window: &Window) {
+    pub fn render(&mut self, world: &mut World, camera: &PerspectiveCamera, window: &Window) {
+
+        self.lighting.update_uniforms(&self.ctx, world, camera, self.settings.gbuffer_view as u32);
 
         let mut encoder = self.ctx.create_encoder("command_encoder");
 
+        self.compute_passes.iter().for_each(|compute_pass| {
+            compute_pass.record(&self.ctx, &mut encoder);
+        });
 
-        let world_color_texture_view = self.ctx.get_texture_view("world_color_texture_view");
-        let world_depth_texture_view = self.ctx.get_texture_view("world_depth_texture_view");
-        let screen_quad_pipeline = self.ctx.get_render_pipeline("screen_quad_pipeline");
-        let screen_quad_vertex_buffer = self.ctx.get_buffer("square_vertex_buffer");
-        let screen_quad_index_buffer = self.ctx.get_buffer("square_index_buffer");
-        let world_texture_bind_group = self.ctx.get_bind_group("world_texture_bind_group");
+        self.stats = RenderStats::default();
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("world_render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &world_color_texture_view.gpu_texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    }
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &world_depth_texture_view.gpu_texture_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
+        // The GPU-driven path draws every mesh at full detail.
+        let lod = LodSettings {
+            enabled: self.settings.lod.enabled && self.gpu_driven.is_none(),
+            ..self.settings.lod
+        };
+
+        select_lods(world, camera, &lod);
+
+        let debug_view = self.settings.debug_view;
+        let frustum = camera.frustum();
+        let frustum = self.settings.frustum_culling.then_some(&frustum);
+
+        let pick_ids = PickIds::new(world);
+
+        if debug_view.replaces_shading() {
+            let meshes = visible_meshes(world, frustum, &mut self.stats);
+
+            self.stats += self.debug_views.render(&self.ctx, &mut encoder, &meshes, camera, debug_view);
+        } else if let Some(gpu_driven) = &mut self.gpu_driven {
+            // Draws the opaque, unanimated models in one pass.
+            gpu_driven.render(&mut self.ctx, &mut encoder, world, camera, GpuDrivenFrame {
+                frustum_culling: self.settings.frustum_culling,
+                ids: &pick_ids,
+                stats: &mut self.stats,
             });
-            
-            render_pass.set_bind_group(0, &camera.bind_group.gpu_bind_group, &[]);
 
-            world.models.iter().for_each(|model| {
-                model.meshes.iter().for_each(|mesh| {
-                    // First Order Bind Groups
-                    render_pass.set_pipeline(&model.pipeline.gpu_render_pipeline);
-            
-                    render_pass.set_bind_group(1, &model.bind_group.gpu_bind_group, &[]);
-            
-                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.gpu_buffer.slice(..));
-            
-                    render_pass.set_index_buffer(mesh.index_buffer.gpu_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            
-                    render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
-                })
+            let other_models = world.models.iter().filter(|model| !GpuDrivenRenderer::draws(model));
+
+            let (transparent_meshes, opaque_meshes): (Vec<_>, Vec<_>) = visible_model_meshes(other_models, frustum, &mut self.stats)
+                .into_iter()
+                .partition(|(model, _)| model.material.alpha_mode.is_transparent());
+
+            if !opaque_meshes.is_empty() {
+                self.stats += self.render_forward(&mut encoder, &opaque_meshes, camera, &pick_ids, false);
+            }
+
+            self.grid.render(&self.ctx, &mut encoder, camera, &self.settings.grid);
+
+            self.stats += self.transparency.render(&self.ctx, &mut encoder, &transparent_meshes, camera, self.settings.transparency);
+        } else {
+            let meshes = visible_meshes(world, frustum, &mut self.stats);
+
+            let (transparent_meshes, opaque_meshes): (Vec<_>, Vec<_>) = meshes
+                .into_iter()
+                .partition(|(model, _)| model.material.alpha_mode.is_transparent());
+
+            self.stats += match &self.deferred {
+                Some(deferred) => deferred.render(&self.ctx, &mut encoder, &opaque_meshes, camera, &pick_ids),
+                None => self.render_forward(&mut encoder, &opaque_meshes, camera, &pick_ids, true),
+            };
+
+            // Drawn before blended meshes, which don't write depth.
+            self.grid.render(&self.ctx, &mut encoder, camera, &self.settings.grid);
+
+            self.stats += self.transparency.render(&self.ctx, &mut encoder, &transparent_meshes, camera, self.settings.transparency);
+        }
+
+        if debug_view == DebugView::Wireframe {
+            let meshes = visible_meshes(world, frustum, &mut RenderStats::default());
+
+            self.debug_views.prepare(&mut self.ctx, &meshes);
+
+            self.stats += self.debug_views.render(&self.ctx, &mut encoder, &meshes, camera, debug_view);
+        }
+
+        // Debug views that replace shading don't write ids.
+        let pick_ids = (!debug_view.replaces_shading()).then_some(pick_ids);
+
+        self.picker.render(&self.ctx, &mut encoder, pick_ids, camera);
+
+        let outline_seeds = self.outline.render(&self.ctx, &mut encoder, world, camera, &self.settings.outline);
+
+        if self.settings.show_bounds {
+            world.models.iter().filter_map(|model| model.world_bounds()).for_each(|bounds| {
+                self.debug_draw.aabb(&bounds, [1.0, 0.9, 0.2, 1.0]);
+            });
+        }
+
+        if self.settings.show_light_volumes {
+            world.lights.iter().for_each(|light| {
+                self.debug_draw.sphere(light.position, light.range, light.color.extend(1.0).into());
             });
         }
 
+        self.debug_draw_renderer.render(&mut self.ctx, &mut encoder, &self.debug_draw, camera);
+
+        self.debug_draw.clear();
+
+        let screen_quad_pipeline = self.ctx.get_render_pipeline("screen_quad_pipeline");
+        let screen_quad_vertex_buffer = self.ctx.get_buffer("square_vertex_buffer");
+        let screen_quad_index_buffer = self.ctx.get_buffer("square_index_buffer");
+        let world_texture_bind_group = self.ctx.get_bind_group("world_texture_bind_group");
+
         let surface_texture = self.ctx.surface
             .get_current_texture()
             .expect("");
@@ -233,6 +372,10 @@ impl Graphics {
 
             render_pass.draw_indexed(0..SQUARE_INDICES.len() as u32, 0, 0..1);
         }
+
+        if let Some(seeds) = outline_seeds {
+            self.outline.composite(&self.ctx, &mut encoder, &surface_texture_view, seeds);
+        }
     
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [self.ctx.surface_config.width, self.ctx.surface_config.height],
@@ -245,13 +388,74 @@ impl Graphics {
             &window,
             &surface_texture_view,
             screen_descriptor,
-            |ui| example_gui(ui),
+            |ui| {
+                example_gui(ui);
+                render_settings_gui(ui, self.render_path, &mut self.settings, &self.stats);
+                selection_gui(ui, world);
+                animation_gui(ui, world);
+                shader_errors_gui(ui, &self.shader_hot_reloader.errors);
+            },
         );
 
         self.ctx.device.queue.submit(std::iter::once(encoder.finish()));
 
+        if let Some(gpu_driven) = &self.gpu_driven {
+            gpu_driven.after_submit();
+        }
+
+        self.picker.after_submit();
+
         surface_texture.present();    
     }
-}
 
+    /// Draws opaque meshes with their models' pipelines, clearing the world
+    /// targets first if `clear`.
+    fn render_forward(&self, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera, ids: &PickIds, clear: bool) -> RenderStats {
+        let world_color_texture_view = self.ctx.get_texture_view("world_color_texture_view");
+        let world_depth_texture_view = self.ctx.get_texture_view("world_depth_texture_view");
+        let lights_bind_group = self.ctx.get_bind_group("lights_bind_group");
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("world_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &world_color_texture_view.gpu_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: if clear {
+                            wgpu::LoadOp::Clear(wgpu::Color {
+                                r: 0.1,
+                                g: 0.2,
+                                b: 0.3,
+                                a: 1.0,
+                            })
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: wgpu::StoreOp::Store,
+                    }
+                }), id_attachment(self.picker.id_view(), clear)],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &world_depth_texture_view.gpu_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: if clear { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load },
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            
+            let mut state = RenderState::default();
+
+            state.set_bind_group(&mut render_pass, 0, &camera.bind_group);
+
+            state.set_bind_group(&mut render_pass, 3, lights_bind_group);
 
+            RenderQueue::opaque(meshes, camera, |model| model.pipeline.as_ref()).submit(&mut render_pass, &mut state, Some(ids));
+
+            state.stats
+        }
+    }
+}
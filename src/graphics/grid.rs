@@ -0,0 +1,182 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::SquareMatrix;
+
+use crate::world::camera::PerspectiveCamera;
+
+use super::{context::{BindGroupEntry, Context}, graphics::{WORLD_COLOR_FORMAT, WORLD_DEPTH_FORMAT}, pipeline::{BlendPreset, RenderPipelineBuilder}};
+
+
+/// Appearance of the ground grid, edited from the render settings window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridSettings {
+    pub enabled: bool,
+    /// Size of a minor cell when the camera is close.
+    pub cell_size: f32,
+    /// Minor cells per major line, which is also the factor between spacings
+    /// when `adaptive` is on.
+    pub major_every: u32,
+    /// Grows the spacing with the camera radius so cells stay a similar size on screen.
+    pub adaptive: bool,
+    /// Distance from the camera at which the grid has faded out completely.
+    pub fade_distance: f32,
+    pub minor_color: [f32; 4],
+    pub major_color: [f32; 4],
+    pub x_axis_color: [f32; 4],
+    pub z_axis_color: [f32; 4],
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cell_size: 1.0,
+            major_every: 10,
+            adaptive: true,
+            fade_distance: 60.0,
+            minor_color: [0.5, 0.5, 0.5, 0.35],
+            major_color: [0.7, 0.7, 0.7, 0.6],
+            x_axis_color: [0.9, 0.2, 0.2, 0.9],
+            z_axis_color: [0.2, 0.4, 0.9, 0.9],
+        }
+    }
+}
+
+impl GridSettings {
+    /// The minor cell size for a camera at `radius`, and how far the camera
+    /// is towards the next coarser size, used to fade the minor lines out.
+    pub fn spacing(&self, radius: f32) -> (f32, f32) {
+        let factor = self.major_every.max(2) as f32;
+
+        if !self.adaptive {
+            return (self.cell_size, 0.0);
+        }
+
+        // Roughly `factor` major cells across the view before stepping up.
+        let level = (radius.max(f32::EPSILON) / (self.cell_size * factor)).log(factor).max(0.0);
+
+        (self.cell_size * factor.powf(level.floor()), level.fract())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct GridUniform {
+    pub view_projection: [[f32; 4]; 4],
+    pub inverse_view_projection: [[f32; 4]; 4],
+    pub camera_position: [f32; 3],
+    pub cell_size: f32,
+    pub minor_color: [f32; 4],
+    pub major_color: [f32; 4],
+    pub x_axis_color: [f32; 4],
+    pub z_axis_color: [f32; 4],
+    pub major_every: f32,
+    pub fade_distance: f32,
+    pub lod_fraction: f32,
+    pub _padding: f32,
+}
+
+crate::uniform_layout!(GridUniform { view_projection, inverse_view_projection, camera_position, cell_size, minor_color, major_color, x_axis_color, z_axis_color, major_every, fade_distance, lod_fraction, _padding });
+
+/// Draws an infinite, anti-aliased grid on the ground plane over the world
+/// target, depth tested against the scene.
+pub struct GridRenderer {
+}
+
+impl GridRenderer {
+    pub fn new(ctx: &mut Context) -> Self {
+        let shader = ctx.create_shader("grid_shader", "shaders/grid.wgsl");
+
+        if let Err(error) = shader.reflection.validate_uniform::<GridUniform>(0, 0) {
+            panic!("{} does not match GridUniform: {}", shader.code_path, error);
+        }
+
+        let pipeline = RenderPipelineBuilder::new(ctx, "grid_pipeline", &shader)
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Alpha)
+            .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::LessEqual, false)
+            .with_cull_mode(None)
+            .build();
+
+        let uniform_buffer = ctx.create_buffer_with_size(
+            "grid_uniform_buffer",
+            std::mem::size_of::<GridUniform>() as u64,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        );
+
+        let bind_group_layout = pipeline.bind_group_layouts[0].clone();
+
+        ctx.create_bind_group(
+            "grid_bind_group",
+            &bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        Self {
+        }
+    }
+
+    pub fn render(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, camera: &PerspectiveCamera, settings: &GridSettings) {
+        if !settings.enabled {
+            return;
+        }
+
+        let (cell_size, lod_fraction) = settings.spacing(camera.radius());
+
+        let view_projection = camera.view_projection_matrix();
+        let inverse_view_projection = view_projection.invert().unwrap_or(cgmath::Matrix4::identity());
+
+        let uniform = GridUniform {
+            view_projection: view_projection.into(),
+            inverse_view_projection: inverse_view_projection.into(),
+            camera_position: camera.position().into(),
+            cell_size,
+            minor_color: settings.minor_color,
+            major_color: settings.major_color,
+            x_axis_color: settings.x_axis_color,
+            z_axis_color: settings.z_axis_color,
+            major_every: settings.major_every.max(2) as f32,
+            fade_distance: settings.fade_distance,
+            lod_fraction: if settings.adaptive { lod_fraction } else { 0.0 },
+            _padding: 0.0,
+        };
+
+        ctx.device.queue.write_buffer(&ctx.get_buffer("grid_uniform_buffer").gpu_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let world_depth_texture_view = ctx.get_texture_view("world_depth_texture_view");
+        let pipeline = ctx.get_render_pipeline("grid_pipeline");
+        let bind_group = ctx.get_bind_group("grid_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("grid_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &world_color_texture_view.gpu_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &world_depth_texture_view.gpu_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&pipeline.gpu_render_pipeline);
+
+        render_pass.set_bind_group(0, &bind_group.gpu_bind_group, &[]);
+
+        render_pass.draw(0..3, 0..1);
+    }
+}
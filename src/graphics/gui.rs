@@ -1,9 +1,15 @@
+use std::collections::BTreeMap;
+
 use egui_winit::State;
 use wgpu::{CommandEncoder, Device, TextureFormat, TextureView}; // Import wgpu types directly
 use egui::{epaint::Shadow, Align2, Context, Visuals};
 use egui_wgpu::renderer::{Renderer, ScreenDescriptor};
 use winit::window::Window;
 
+use crate::world::world::World;
+
+use super::{culling::RenderStats, debug_view::DebugView, deferred::{GBufferView, RenderPath}, grid::GridSettings, lod::LodSettings, outline::{OutlineSettings, MAX_OUTLINE_WIDTH}, renderer::RenderSettings, transparency::TransparencyMode};
+
 pub struct Gui {
     pub ctx: Context,
     state: State,
@@ -111,4 +117,240 @@ pub fn example_gui(ui: &Context) {
 
             // proto_scene.egui(ui);
         });
-}
\ No newline at end of file
+}
+
+pub fn render_settings_gui(ui: &Context, render_path: RenderPath, settings: &mut RenderSettings, stats: &RenderStats) {
+    egui::Window::new("Render Settings")
+        .default_open(true)
+        .resizable(false)
+        .anchor(Align2::RIGHT_TOP, [0.0, 0.0])
+        .show(ui, |ui| {
+            ui.label(format!("Render path: {:?}", render_path));
+
+            if render_path == RenderPath::Deferred {
+                egui::ComboBox::from_label("G-buffer view")
+                    .selected_text(settings.gbuffer_view.name())
+                    .show_ui(ui, |ui| {
+                        GBufferView::ALL.iter().for_each(|view| {
+                            ui.selectable_value(&mut settings.gbuffer_view, *view, view.name());
+                        });
+                    });
+            }
+
+            egui::ComboBox::from_label("Debug view (V)")
+                .selected_text(settings.debug_view.name())
+                .show_ui(ui, |ui| {
+                    DebugView::ALL.iter().for_each(|view| {
+                        ui.selectable_value(&mut settings.debug_view, *view, view.name());
+                    });
+                });
+
+            if render_path != RenderPath::GpuDriven {
+                egui::ComboBox::from_label("Transparency")
+                    .selected_text(settings.transparency.name())
+                    .show_ui(ui, |ui| {
+                        TransparencyMode::ALL.iter().for_each(|mode| {
+                            ui.selectable_value(&mut settings.transparency, *mode, mode.name());
+                        });
+                    });
+            }
+
+            ui.checkbox(&mut settings.show_bounds, "Show bounds");
+            ui.checkbox(&mut settings.show_light_volumes, "Show light volumes");
+
+            grid_settings_gui(ui, &mut settings.grid);
+            outline_settings_gui(ui, &mut settings.outline);
+
+            // The GPU-driven path packs each mesh once, at full detail.
+            if render_path != RenderPath::GpuDriven {
+                lod_settings_gui(ui, &mut settings.lod);
+            }
+
+            ui.checkbox(&mut settings.frustum_culling, "Frustum culling");
+            ui.label(format!("Meshes drawn: {}, culled: {}, triangles: {}", stats.drawn, stats.culled, stats.triangles));
+            ui.label(format!(
+                "Draw calls: {}, pipeline switches: {}, bind group switches: {}",
+                stats.draw_calls, stats.pipeline_switches, stats.bind_group_switches
+            ));
+        });
+}
+
+fn grid_settings_gui(ui: &mut egui::Ui, grid: &mut GridSettings) {
+    ui.collapsing("Grid", |ui| {
+        ui.checkbox(&mut grid.enabled, "Show grid");
+        ui.checkbox(&mut grid.adaptive, "Adapt spacing to camera distance");
+        ui.add(egui::Slider::new(&mut grid.cell_size, 0.01..=10.0).logarithmic(true).text("Cell size"));
+        ui.add(egui::Slider::new(&mut grid.major_every, 2..=20).text("Cells per major line"));
+        ui.add(egui::Slider::new(&mut grid.fade_distance, 5.0..=500.0).logarithmic(true).text("Fade distance"));
+
+        [
+            ("Minor lines", &mut grid.minor_color),
+            ("Major lines", &mut grid.major_color),
+            ("X axis", &mut grid.x_axis_color),
+            ("Z axis", &mut grid.z_axis_color),
+        ].into_iter().for_each(|(label, color)| {
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgba_unmultiplied(color);
+                ui.label(label);
+            });
+        });
+    });
+}
+
+fn outline_settings_gui(ui: &mut egui::Ui, outline: &mut OutlineSettings) {
+    ui.collapsing("Selection outline", |ui| {
+        ui.checkbox(&mut outline.enabled, "Show outline");
+        ui.add(egui::Slider::new(&mut outline.width, 1.0..=MAX_OUTLINE_WIDTH).text("Width"));
+        ui.add(egui::Slider::new(&mut outline.occluded_opacity, 0.0..=1.0).text("Occluded opacity"));
+
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgba_unmultiplied(&mut outline.color);
+            ui.label("Color");
+        });
+    });
+}
+
+fn lod_settings_gui(ui: &mut egui::Ui, lod: &mut LodSettings) {
+    ui.collapsing("Level of detail", |ui| {
+        ui.checkbox(&mut lod.enabled, "Simplify distant models");
+
+        lod.thresholds.iter_mut().enumerate().for_each(|(level, threshold)| {
+            ui.add(egui::Slider::new(threshold, 0.001..=1.0).logarithmic(true).text(format!("LOD {} below screen height", level + 1)));
+        });
+
+        ui.add(egui::Slider::new(&mut lod.hysteresis, 0.0..=0.5).text("Hysteresis"));
+    });
+}
+
+/// Lists the models of the world with a checkbox to select each one.
+pub fn selection_gui(ui: &Context, world: &mut World) {
+    egui::Window::new("Selection")
+        .default_open(false)
+        .resizable(false)
+        .anchor(Align2::LEFT_TOP, [0.0, 0.0])
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Select all").clicked() {
+                    world.selection = (0..world.models.len()).collect();
+                }
+
+                if ui.button("Clear").clicked() {
+                    world.selection.clear();
+                }
+            });
+
+            egui::ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                (0..world.models.len()).for_each(|model| {
+                    let mut selected = world.selection.contains(&model);
+
+                    if ui.checkbox(&mut selected, format!("Model {}", model)).changed() {
+                        if selected {
+                            world.selection.push(model);
+                        } else {
+                            world.selection.retain(|index| *index != model);
+                        }
+                    }
+                });
+            });
+        });
+}
+
+/// Plays the animations of the first selected model that has any.
+pub fn animation_gui(ui: &Context, world: &mut World) {
+    let animated = world.selection.iter().copied().find(|model| world.models[*model].skin.as_ref().is_some_and(|skin| !skin.animations.is_empty()));
+
+    let Some(skin) = animated.and_then(|model| world.models[model].skin.as_mut()) else {
+        return;
+    };
+
+    let animations = skin.animations.clone();
+    let player = &mut skin.player;
+
+    let clip_name = |clip: Option<usize>| clip.and_then(|clip| animations.get(clip)).map_or("None", |clip| clip.name.as_str());
+
+    egui::Window::new("Animation")
+        .default_open(true)
+        .resizable(false)
+        .anchor(Align2::RIGHT_BOTTOM, [0.0, 0.0])
+        .show(ui, |ui| {
+            egui::ComboBox::from_label("Clip").selected_text(clip_name(player.current.map(|current| current.clip))).show_ui(ui, |ui| {
+                animations.iter().enumerate().for_each(|(index, clip)| {
+                    if ui.selectable_label(player.current.is_some_and(|current| current.clip == index), &clip.name).clicked() {
+                        player.play(index);
+                    }
+                });
+            });
+
+            ui.horizontal(|ui| {
+                if player.playing {
+                    if ui.button("Pause").clicked() {
+                        player.pause();
+                    }
+                } else if ui.button("Play").clicked() {
+                    player.resume();
+                }
+
+                if ui.button("Stop").clicked() {
+                    player.stop();
+                }
+
+                ui.checkbox(&mut player.looping, "Loop");
+            });
+
+            ui.add(egui::Slider::new(&mut player.speed, -2.0..=2.0).text("Speed"));
+
+            if let Some(current) = player.current.as_mut() {
+                let duration = animations.get(current.clip).map_or(0.0, |clip| clip.duration);
+
+                ui.add(egui::Slider::new(&mut current.time, 0.0..=duration).text("Time"));
+            }
+
+            ui.separator();
+
+            let mut blend = player.blend.map(|blend| blend.clip);
+
+            egui::ComboBox::from_label("Blend with").selected_text(clip_name(blend)).show_ui(ui, |ui| {
+                ui.selectable_value(&mut blend, None, "None");
+
+                animations.iter().enumerate().for_each(|(index, clip)| {
+                    ui.selectable_value(&mut blend, Some(index), &clip.name);
+                });
+            });
+
+            let mut weight = player.blend_weight;
+
+            ui.add_enabled(blend.is_some(), egui::Slider::new(&mut weight, 0.0..=1.0).text("Weight"));
+
+            match blend {
+                Some(clip) if player.blend.is_none_or(|blend| blend.clip != clip) || weight != player.blend_weight => player.blend(clip, weight),
+                None => player.blend = None,
+                _ => {},
+            }
+
+            if ui.add_enabled(blend.is_some(), egui::Button::new("Crossfade over 0.5s")).clicked() {
+                if let Some(clip) = blend {
+                    player.crossfade(clip, 0.5);
+                }
+            }
+        });
+}
+
+pub fn shader_errors_gui(ui: &Context, errors: &BTreeMap<String, String>) {
+    if errors.is_empty() {
+        return;
+    }
+
+    egui::Window::new("Shader Errors")
+        .default_open(true)
+        .resizable(true)
+        .anchor(Align2::LEFT_BOTTOM, [0.0, 0.0])
+        .show(ui, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                errors.iter().for_each(|(label, error)| {
+                    ui.colored_label(egui::Color32::LIGHT_RED, label);
+                    ui.label(egui::RichText::new(error).monospace());
+                    ui.separator();
+                });
+            });
+        });
+}
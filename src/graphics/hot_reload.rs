@@ -0,0 +1,126 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use crate::assets::watcher::{any_changed, FileWatcher};
+
+use super::{context::{Context, RenderPipeline, RenderPipelineKey, Shader}, preprocessor::Defines};
+
+
+/// Watches the files behind every `Shader` in the context and recompiles the
+/// shaders that depend on a file when it changes on disk. Shaders that resolve
+/// to embedded assets have no file and are never reloaded. A shader that fails to compile keeps its previous
+/// module and pipelines, and the error is kept in `errors` for the GUI.
+pub struct ShaderHotReloader {
+    watcher: FileWatcher,
+    pub errors: BTreeMap<String, String>,
+}
+
+impl ShaderHotReloader {
+    pub fn new() -> Self {
+        Self {
+            watcher: FileWatcher::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// Starts watching the files (including `#include`d ones) of the shaders
+    /// compiled since the last call. Files already watched are skipped by the
+    /// watcher, so only new shaders touch the file system.
+    pub fn watch(&mut self, ctx: &mut Context) {
+        if ctx.unwatched_shaders.is_empty() {
+            return;
+        }
+
+        let labels = std::mem::take(&mut ctx.unwatched_shaders);
+
+        self.watcher.watch(&ctx.assets, labels
+            .iter()
+            .filter_map(|label| ctx.shaders.get(label))
+            .flat_map(|shader| shader.dependencies.iter().map(String::as_str)));
+    }
+
+    /// Reloads every shader whose file changed since the last poll. Returns true
+    /// if any render pipeline in the context was replaced.
+    pub fn poll(&mut self, ctx: &mut Context) -> bool {
+        self.watch(ctx);
+
+        let changed_paths = self.watcher.poll();
+
+        if changed_paths.is_empty() {
+            return false;
+        }
+
+        let changed_shaders: Vec<(String, String, Defines)> = ctx.shaders
+            .values()
+            .filter(|shader| any_changed(&ctx.assets, shader.dependencies.iter().map(String::as_str), &changed_paths))
+            .map(|shader| (shader.label.clone(), shader.code_path.clone(), shader.defines.clone()))
+            .collect();
+
+        let mut rebuilt = false;
+
+        changed_shaders.iter().for_each(|(label, code_path, defines)| {
+            match reload_shader(ctx, label, code_path, defines) {
+                Ok(pipeline_count) => {
+                    log::info!("Reloaded shader {} ({} pipelines rebuilt)", label, pipeline_count);
+                    self.errors.remove(label);
+                    rebuilt |= pipeline_count > 0;
+                },
+                Err(error) => {
+                    log::error!("Failed to reload shader {}:\n{}", label, error);
+                    self.errors.insert(label.clone(), error);
+                },
+            }
+        });
+
+        rebuilt
+    }
+}
+
+impl Default for ShaderHotReloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compiles the shader at `code_path` and rebuilds every pipeline that uses it.
+/// Nothing in the context is replaced unless all of that succeeds.
+fn reload_shader(ctx: &mut Context, label: &str, code_path: &str, defines: &Defines) -> Result<usize, String> {
+    let (source, reflection) = Shader::load(&ctx.assets, code_path, defines)?;
+
+    let device = &ctx.device.logical_device;
+
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    let shader = Rc::new(Shader::from_source(device, label, code_path, defines, source, reflection));
+
+    let pipelines: Vec<(RenderPipelineKey, Rc<RenderPipeline>, Rc<RenderPipeline>)> = ctx.render_pipeline_cache
+        .iter()
+        .filter(|(_, pipeline)| pipeline.shader_label == label)
+        .map(|(key, pipeline)| (key.clone(), pipeline.clone(), Rc::new(pipeline.rebuild(device, &shader))))
+        .collect();
+
+    if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+        return Err(error.to_string());
+    }
+
+    let pipeline_count = pipelines.len();
+
+    // A recompile can include different files, which need watching too.
+    if ctx.shaders.get(label).is_none_or(|old_shader| old_shader.dependencies != shader.dependencies) {
+        ctx.unwatched_shaders.push(label.to_string());
+    }
+
+    ctx.shaders.insert(label.to_string(), shader);
+
+    // Cached pipelines can be shared by several labels, so every label that
+    // pointed at the old pipeline is moved to the rebuilt one.
+    pipelines.into_iter().for_each(|(key, old_pipeline, new_pipeline)| {
+        ctx.render_pipelines
+            .values_mut()
+            .filter(|pipeline| Rc::ptr_eq(pipeline, &old_pipeline))
+            .for_each(|pipeline| *pipeline = new_pipeline.clone());
+
+        ctx.render_pipeline_cache.insert(key, new_pipeline);
+    });
+
+    Ok(pipeline_count)
+}
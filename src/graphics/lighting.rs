@@ -0,0 +1,128 @@
+use bytemuck::Zeroable;
+use cgmath::SquareMatrix;
+
+use crate::world::{camera::PerspectiveCamera, light::PointLightUniform, world::World};
+
+use super::context::{BindGroupEntry, BindGroupLayoutEntry, Context};
+
+
+pub const MAX_POINT_LIGHTS: usize = 1024;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SceneLightingUniform {
+    pub inverse_view_projection: [[f32; 4]; 4],
+    pub camera_position: [f32; 4],
+    pub ambient: [f32; 4],
+    pub clear_color: [f32; 4],
+    pub light_count: u32,
+    pub debug_view: u32,
+    pub _padding: [u32; 2],
+}
+
+crate::uniform_layout!(SceneLightingUniform { inverse_view_projection, camera_position, ambient, clear_color, light_count, debug_view, _padding });
+
+/// Owns the scene-wide light buffers that both the forward shader and the
+/// deferred lighting pass read from.
+pub struct Lighting {
+    pub ambient: [f32; 3],
+    pub clear_color: [f32; 3],
+}
+
+impl Lighting {
+    pub fn new(ctx: &mut Context) -> Self {
+        let lights = vec![PointLightUniform::zeroed(); MAX_POINT_LIGHTS];
+
+        let lights_storage_buffer = ctx.create_buffer(
+            "lights_storage_buffer",
+            bytemuck::cast_slice(&lights),
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST
+        );
+
+        let scene_lighting_uniform_buffer = ctx.create_buffer(
+            "scene_lighting_uniform_buffer",
+            bytemuck::cast_slice(&[SceneLightingUniform::zeroed()]),
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        );
+
+        let lights_bind_group_layout = ctx.create_bind_group_layout(
+            "lights_bind_group_layout",
+            vec![
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    }
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    }
+                },
+            ]
+        );
+
+        ctx.create_bind_group(
+            "lights_bind_group",
+            &lights_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: scene_lighting_uniform_buffer.gpu_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: lights_storage_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        Self {
+            ambient: [0.03, 0.03, 0.03],
+            clear_color: [0.1, 0.2, 0.3],
+        }
+    }
+
+    pub fn update_uniforms(&self, ctx: &Context, world: &World, camera: &PerspectiveCamera, debug_view: u32) {
+        if world.lights.len() > MAX_POINT_LIGHTS {
+            log::warn!("{} point lights in the world, only the first {} are shaded", world.lights.len(), MAX_POINT_LIGHTS);
+        }
+
+        let lights: Vec<PointLightUniform> = world.lights
+            .iter()
+            .take(MAX_POINT_LIGHTS)
+            .map(PointLightUniform::from)
+            .collect();
+
+        let inverse_view_projection = camera.view_projection_matrix()
+            .invert()
+            .unwrap_or(cgmath::Matrix4::identity());
+
+        let position = camera.position();
+
+        let uniform = SceneLightingUniform {
+            inverse_view_projection: inverse_view_projection.into(),
+            camera_position: [position.x, position.y, position.z, 1.0],
+            ambient: [self.ambient[0], self.ambient[1], self.ambient[2], 1.0],
+            clear_color: [self.clear_color[0], self.clear_color[1], self.clear_color[2], 1.0],
+            light_count: lights.len() as u32,
+            debug_view,
+            _padding: [0; 2],
+        };
+
+        let uniform_buffer = ctx.get_buffer("scene_lighting_uniform_buffer");
+        ctx.device.queue.write_buffer(&uniform_buffer.gpu_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        if !lights.is_empty() {
+            let storage_buffer = ctx.get_buffer("lights_storage_buffer");
+            ctx.device.queue.write_buffer(&storage_buffer.gpu_buffer, 0, bytemuck::cast_slice(&lights));
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use cgmath::InnerSpace;
+
+use crate::world::{camera::PerspectiveCamera, world::{Mesh, World}};
+
+
+/// Simplified levels `ModelBuilder` generates for each mesh by default, on
+/// top of the full-detail one.
+pub const MAX_LOD_LEVELS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodSettings {
+    pub enabled: bool,
+    /// Level `i + 1` is used once a model's bounding sphere covers less than
+    /// `thresholds[i]` of the viewport height. Should decrease.
+    pub thresholds: [f32; MAX_LOD_LEVELS],
+    /// How far, relative to a threshold, the size has to go past it before
+    /// the level changes, so that models near a threshold don't flicker
+    /// between levels.
+    pub hysteresis: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            thresholds: [0.3, 0.12, 0.05],
+            hysteresis: 0.1,
+        }
+    }
+}
+
+/// Sets `Model::lod` of every model from the size it covers on screen.
+pub fn select_lods(world: &mut World, camera: &PerspectiveCamera, settings: &LodSettings) {
+    world.models.iter_mut().for_each(|model| {
+        let levels = model.meshes.iter().map(Mesh::lod_count).max().unwrap_or(1).min(MAX_LOD_LEVELS + 1);
+
+        let Some(bounds) = model.world_bounds().filter(|_| settings.enabled) else {
+            model.lod = 0;
+            return;
+        };
+
+        let size = camera.projected_size(bounds.center(), bounds.extents().magnitude());
+
+        model.lod = select_lod(model.lod, size, &settings.thresholds[..levels - 1], settings.hysteresis);
+    });
+}
+
+/// Moves from `current` one level at a time while the size is past the
+/// threshold of the next level by more than the hysteresis.
+fn select_lod(current: usize, size: f32, thresholds: &[f32], hysteresis: f32) -> usize {
+    let mut lod = current.min(thresholds.len());
+
+    while lod < thresholds.len() && size < thresholds[lod] * (1.0 - hysteresis) {
+        lod += 1;
+    }
+
+    while lod > 0 && size > thresholds[lod - 1] * (1.0 + hysteresis) {
+        lod -= 1;
+    }
+
+    lod
+}
@@ -3,3 +3,19 @@ pub mod context;
 pub mod renderer;
 pub mod vertex_input;
 pub mod gui;
+pub mod lighting;
+pub mod deferred;
+pub mod hot_reload;
+pub mod preprocessor;
+pub mod reflection;
+pub mod pipeline;
+pub mod culling;
+pub mod gpu_driven;
+pub mod render_queue;
+pub mod debug_view;
+pub mod debug_draw;
+pub mod grid;
+pub mod picking;
+pub mod outline;
+pub mod transparency;
+pub mod lod;
@@ -0,0 +1,318 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::world::{camera::PerspectiveCamera, model::ModelPermutation, world::World};
+
+use super::{context::{BindGroupEntry, Context, TextureView}, graphics::WORLD_TARGET_SIZE, pipeline::{BlendPreset, RenderPipelineBuilder}, render_queue::RenderState};
+
+
+pub const OUTLINE_MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg8Unorm;
+pub const OUTLINE_SEEDS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Float;
+
+/// Widest outline, in world target pixels, the settings allow.
+pub const MAX_OUTLINE_WIDTH: f32 = 64.0;
+
+/// Appearance of the outline drawn around selected models.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlineSettings {
+    pub enabled: bool,
+    pub color: [f32; 4],
+    /// In pixels of the world target.
+    pub width: f32,
+    /// Opacity of the parts of the outline around hidden parts of a model,
+    /// 0 to hide them.
+    pub occluded_opacity: f32,
+}
+
+impl Default for OutlineSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            color: [1.0, 0.6, 0.1, 1.0],
+            width: 3.0,
+            occluded_opacity: 0.35,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct OutlineUniform {
+    pub color: [f32; 4],
+    pub width: f32,
+    pub occluded_opacity: f32,
+    pub _padding: [f32; 2],
+}
+
+crate::uniform_layout!(OutlineUniform { color, width, occluded_opacity, _padding });
+
+/// Outlines the selected models with a jump flood post-process.
+///
+/// The selected meshes are rasterised into a mask, jump flooding turns the
+/// mask into the nearest covered pixel for every pixel, and a full-screen pass
+/// draws the outline over the surface after the screen quad. Since the mask
+/// ignores the scene's depth, the outline also follows hidden parts, which
+/// the mask marks so they can be drawn dimmer.
+pub struct OutlineRenderer {
+}
+
+impl OutlineRenderer {
+    pub fn new(ctx: &mut Context, world_depth_texture_view: &TextureView, surface_format: wgpu::TextureFormat) -> Self {
+        let [mask_view, seeds_a_view, seeds_b_view] = [
+            ("outline_mask", OUTLINE_MASK_FORMAT),
+            ("outline_seeds_a", OUTLINE_SEEDS_FORMAT),
+            ("outline_seeds_b", OUTLINE_SEEDS_FORMAT),
+        ].map(|(label, format)| {
+            let texture = ctx.create_texture(
+                &format!("{}_texture", label),
+                WORLD_TARGET_SIZE,
+                1,
+                1,
+                wgpu::TextureDimension::D2,
+                format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            );
+
+            ctx.create_texture_view(&texture.gpu_texture, &format!("{}_texture_view", label))
+        });
+
+        // Overlapping meshes keep the largest value, so a pixel counts as
+        // visible if any selected surface at it is.
+        let max = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Max,
+        };
+
+        // One for each permutation of the models' pipelines, so that the
+        // mask follows their skinning and morph targets.
+        let [mask_pipeline, ..] = ModelPermutation::ALL.map(|permutation| {
+            let mask_shader = ctx.create_shader_permutation("outline_mask_shader", "shaders/outline_mask.wgsl", &permutation.defines());
+
+            RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("outline_mask"), &mask_shader)
+                .with_vertex_buffers(&[permutation.vertex_layout()])
+                .with_color_target_states(&[Some(wgpu::ColorTargetState {
+                    format: OUTLINE_MASK_FORMAT,
+                    blend: Some(wgpu::BlendState { color: max, alpha: max }),
+                    write_mask: wgpu::ColorWrites::ALL
+                })])
+                .with_cull_mode(None)
+                .build()
+        });
+
+        let depth_bind_group_layout = mask_pipeline.bind_group_layouts[2].clone();
+
+        ctx.create_bind_group(
+            "outline_depth_bind_group",
+            &depth_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&world_depth_texture_view.gpu_texture_view)
+                },
+            ]
+        );
+
+        let jfa_shader = ctx.create_shader("outline_jfa_shader", "shaders/outline_jfa.wgsl");
+
+        let jfa_init_pipeline = RenderPipelineBuilder::new(ctx, "outline_jfa_init_pipeline", &jfa_shader)
+            .with_fragment_entry_point("fs_init")
+            .with_color_target(OUTLINE_SEEDS_FORMAT, BlendPreset::Disabled)
+            .build();
+
+        RenderPipelineBuilder::new(ctx, "outline_jfa_step_pipeline", &jfa_shader)
+            .with_fragment_entry_point("fs_step")
+            .with_color_target(OUTLINE_SEEDS_FORMAT, BlendPreset::Disabled)
+            .build();
+
+        let jfa_bind_group_layout = jfa_init_pipeline.bind_group_layouts[0].clone();
+
+        let composite_shader = ctx.create_shader("outline_composite_shader", "shaders/outline_composite.wgsl");
+
+        if let Err(error) = composite_shader.reflection.validate_uniform::<OutlineUniform>(0, 2) {
+            panic!("{} does not match OutlineUniform: {}", composite_shader.code_path, error);
+        }
+
+        let composite_pipeline = RenderPipelineBuilder::new(ctx, "outline_composite_pipeline", &composite_shader)
+            .with_color_target(surface_format, BlendPreset::Alpha)
+            .build();
+
+        let composite_bind_group_layout = composite_pipeline.bind_group_layouts[0].clone();
+
+        let uniform_buffer = ctx.create_buffer_with_size(
+            "outline_uniform_buffer",
+            std::mem::size_of::<OutlineUniform>() as u64,
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        );
+
+        // One bind group per seed texture, so every pass can read the texture
+        // the previous one wrote.
+        [("a", &seeds_a_view), ("b", &seeds_b_view)].into_iter().for_each(|(name, seeds_view)| {
+            ctx.create_bind_group(
+                &format!("outline_jfa_{}_bind_group", name),
+                &jfa_bind_group_layout.gpu_bind_group_layout,
+                vec![
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&mask_view.gpu_texture_view)
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&seeds_view.gpu_texture_view)
+                    },
+                ]
+            );
+
+            ctx.create_bind_group(
+                &format!("outline_composite_{}_bind_group", name),
+                &composite_bind_group_layout.gpu_bind_group_layout,
+                vec![
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&mask_view.gpu_texture_view)
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&seeds_view.gpu_texture_view)
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: uniform_buffer.gpu_buffer.as_entire_binding()
+                    },
+                ]
+            );
+        });
+
+        Self {
+        }
+    }
+
+    /// Builds the mask and runs the jump flood for the selected models. Must
+    /// come after the scene has been drawn, as the mask reads its depth.
+    /// Returns which seed texture, "a" or "b", holds the result, or `None` if
+    /// nothing is outlined.
+    pub fn render(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, world: &World, camera: &PerspectiveCamera, settings: &OutlineSettings) -> Option<&'static str> {
+        if !settings.enabled || world.selection.is_empty() {
+            return None;
+        }
+
+        let width = settings.width.clamp(1.0, MAX_OUTLINE_WIDTH);
+
+        let uniform = OutlineUniform {
+            color: settings.color,
+            width,
+            occluded_opacity: settings.occluded_opacity,
+            _padding: [0.0; 2],
+        };
+
+        ctx.device.queue.write_buffer(&ctx.get_buffer("outline_uniform_buffer").gpu_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let color_attachment = |view| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            }
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("outline_mask_render_pass"),
+                color_attachments: &[color_attachment(&ctx.get_texture_view("outline_mask_texture_view").gpu_texture_view)],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            let mut state = RenderState::default();
+
+            world.selection.iter().filter_map(|index| world.models.get(*index)).for_each(|model| {
+                state.set_pipeline(&mut render_pass, ctx.get_render_pipeline(&model.permutation().pipeline_label("outline_mask")));
+
+                state.set_bind_group(&mut render_pass, 0, &camera.bind_group);
+
+                state.set_bind_group(&mut render_pass, 2, ctx.get_bind_group("outline_depth_bind_group"));
+
+                model.meshes.iter().for_each(|mesh| {
+                    state.set_bind_group(&mut render_pass, 1, model.draw_bind_group(mesh));
+
+                    state.set_vertex_buffer(&mut render_pass, &mesh.vertex_buffer);
+
+                    let (index_buffer, num_elements) = mesh.lod(model.lod);
+
+                    state.set_index_buffer(&mut render_pass, index_buffer, wgpu::IndexFormat::Uint16);
+
+                    state.draw_indexed(&mut render_pass, 0..num_elements);
+                });
+            });
+        }
+
+        // The init pass writes "a", then each step reads what the previous
+        // pass wrote. Steps halve from the first power of two covering the width.
+        let mut steps = Vec::new();
+        let mut step = (width as u32).next_power_of_two();
+
+        while step >= 1 {
+            steps.push(step);
+            step /= 2;
+        }
+
+        let mut target = "a";
+
+        let fullscreen_pass = |encoder: &mut wgpu::CommandEncoder, pipeline: &str, source: &str, target: &str, step: u32| {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("outline_jfa_render_pass"),
+                color_attachments: &[color_attachment(&ctx.get_texture_view(&format!("outline_seeds_{}_texture_view", target)).gpu_texture_view)],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&ctx.get_render_pipeline(pipeline).gpu_render_pipeline);
+
+            render_pass.set_bind_group(0, &ctx.get_bind_group(&format!("outline_jfa_{}_bind_group", source)).gpu_bind_group, &[]);
+
+            render_pass.draw(0..3, step..step + 1);
+        };
+
+        // The init pass only reads the mask, but must not bind the seed
+        // texture it writes, hence the "b" bind group.
+        fullscreen_pass(encoder, "outline_jfa_init_pipeline", "b", target, 0);
+
+        steps.into_iter().for_each(|step| {
+            let source = target;
+            target = if source == "a" { "b" } else { "a" };
+
+            fullscreen_pass(encoder, "outline_jfa_step_pipeline", source, target, step);
+        });
+
+        Some(target)
+    }
+
+    /// Draws the outline computed by `render` over `surface_view`.
+    pub fn composite(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, surface_view: &wgpu::TextureView, seeds: &str) {
+        let pipeline = ctx.get_render_pipeline("outline_composite_pipeline");
+        let bind_group = ctx.get_bind_group(&format!("outline_composite_{}_bind_group", seeds));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("outline_composite_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&pipeline.gpu_render_pipeline);
+
+        render_pass.set_bind_group(0, &bind_group.gpu_bind_group, &[]);
+
+        render_pass.draw(0..3, 0..1);
+    }
+}
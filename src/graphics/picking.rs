@@ -0,0 +1,244 @@
+use std::{collections::HashMap, rc::Rc};
+
+use cgmath::SquareMatrix;
+
+use crate::world::{camera::PerspectiveCamera, world::{Mesh, World}};
+
+use super::{context::{Context, ReadbackBuffer, TextureView}, graphics::WORLD_TARGET_SIZE};
+
+
+pub const PICKING_ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+/// What was under a picked pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickHit {
+    /// Index into `World::models`.
+    pub model: usize,
+    /// Index into that model's `meshes`.
+    pub mesh: usize,
+    pub position: cgmath::Point3<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickResult {
+    /// The pixel of the world target that was read.
+    pub pixel: (u32, u32),
+    /// `None` if nothing covers the pixel.
+    pub hit: Option<PickHit>,
+}
+
+/// The ids the world passes write for a frame's meshes. Opaque pipelines take
+/// a mesh's id as the instance index of its draw and write it to the id
+/// attachment; 0 is left for pixels that nothing covers.
+pub struct PickIds {
+    /// Keyed by the address of the mesh.
+    ids: HashMap<usize, u32>,
+    /// `(model, mesh)` for each id, offset by one.
+    meshes: Vec<(usize, usize)>,
+}
+
+impl PickIds {
+    pub fn new(world: &World) -> Self {
+        let meshes: Vec<(usize, usize)> = world.models
+            .iter()
+            .enumerate()
+            .flat_map(|(model_index, model)| (0..model.meshes.len()).map(move |mesh_index| (model_index, mesh_index)))
+            .collect();
+
+        let ids = meshes
+            .iter()
+            .enumerate()
+            .map(|(index, (model, mesh))| (&world.models[*model].meshes[*mesh] as *const Mesh as usize, index as u32 + 1))
+            .collect();
+
+        Self {
+            ids,
+            meshes,
+        }
+    }
+
+    /// 0 for meshes that are not in the world.
+    pub fn id(&self, mesh: &Mesh) -> u32 {
+        self.ids.get(&(mesh as *const Mesh as usize)).copied().unwrap_or(0)
+    }
+}
+
+/// The id attachment of the world passes, cleared to 0 by the first of them.
+pub fn id_attachment(view: &TextureView, clear: bool) -> Option<wgpu::RenderPassColorAttachment<'_>> {
+    Some(wgpu::RenderPassColorAttachment {
+        view: &view.gpu_texture_view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: if clear { wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT) } else { wgpu::LoadOp::Load },
+            store: wgpu::StoreOp::Store,
+        }
+    })
+}
+
+struct PickInFlight {
+    pixel: (u32, u32),
+    meshes: Vec<(usize, usize)>,
+    /// Of the camera the ids were rendered with, to turn the depth back into a position.
+    inverse_view_projection: cgmath::Matrix4<f32>,
+    id: Option<u32>,
+    depth: Option<f32>,
+}
+
+/// Finds the mesh under a pixel of the world target. The opaque world pass
+/// writes the id of every mesh into an extra `R32Uint` attachment, and the id
+/// and depth under the pixel are read back asynchronously, so the result comes
+/// out of `poll` a frame or two after `request`. The position is unprojected
+/// from the depth.
+///
+/// Blended meshes don't write ids or depth, so picks go through them. Only
+/// one pick is in flight at a time; requesting another before it completes
+/// replaces the pending pixel.
+pub struct Picker {
+    id_view: Rc<TextureView>,
+    pending: Option<(u32, u32)>,
+    in_flight: Option<PickInFlight>,
+    id_readback: ReadbackBuffer,
+    depth_readback: ReadbackBuffer,
+}
+
+impl Picker {
+    pub fn new(ctx: &mut Context) -> Self {
+        let id_texture = ctx.create_texture(
+            "picking_id_texture",
+            WORLD_TARGET_SIZE,
+            1,
+            1,
+            wgpu::TextureDimension::D2,
+            PICKING_ID_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+        );
+
+        let id_view = ctx.create_texture_view(&id_texture.gpu_texture, "picking_id_texture_view");
+
+        Self {
+            id_view,
+            pending: None,
+            in_flight: None,
+            id_readback: ReadbackBuffer::new(&ctx.device.logical_device, "picking_id_readback", 4),
+            depth_readback: ReadbackBuffer::new(&ctx.device.logical_device, "picking_depth_readback", 4),
+        }
+    }
+
+    /// The view the world passes write ids to.
+    pub fn id_view(&self) -> &Rc<TextureView> {
+        &self.id_view
+    }
+
+    /// Queues a pick at `pixel` of the world target.
+    pub fn request(&mut self, pixel: (u32, u32)) {
+        self.pending = Some((
+            pixel.0.min(WORLD_TARGET_SIZE.width - 1),
+            pixel.1.min(WORLD_TARGET_SIZE.height - 1),
+        ));
+    }
+
+    /// Records the readback of the id and depth under the pending pixel, if
+    /// a pick is pending and none is in flight. `ids` are those the world was
+    /// drawn with this frame, or `None` if no pass wrote ids, e.g. under a
+    /// debug view, in which case the pick misses.
+    pub fn render(&mut self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, ids: Option<PickIds>, camera: &PerspectiveCamera) {
+        if self.in_flight.is_some() {
+            return;
+        }
+
+        let Some(pixel) = self.pending.take() else {
+            return;
+        };
+
+        let Some(ids) = ids else {
+            self.in_flight = Some(PickInFlight {
+                pixel,
+                meshes: Vec::new(),
+                inverse_view_projection: cgmath::Matrix4::identity(),
+                id: Some(0),
+                depth: Some(1.0),
+            });
+            return;
+        };
+
+        let id_texture = ctx.get_texture("picking_id_texture");
+        let depth_texture = ctx.get_texture("world_depth_texture");
+
+        let layout = wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: None,
+            rows_per_image: None,
+        };
+
+        let size = wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 };
+
+        let id_copied = self.id_readback.copy_from_texture(encoder, texel(&id_texture.gpu_texture, pixel, wgpu::TextureAspect::All), layout, size);
+        let depth_copied = self.depth_readback.copy_from_texture(encoder, texel(&depth_texture.gpu_texture, pixel, wgpu::TextureAspect::DepthOnly), layout, size);
+
+        if id_copied && depth_copied {
+            self.in_flight = Some(PickInFlight {
+                pixel,
+                meshes: ids.meshes,
+                inverse_view_projection: camera.view_projection_matrix().invert().unwrap_or(cgmath::Matrix4::identity()),
+                id: None,
+                depth: None,
+            });
+        } else {
+            self.pending = Some(pixel);
+        }
+    }
+
+    /// Starts mapping the readbacks. Call after submitting the frame.
+    pub fn after_submit(&self) {
+        self.id_readback.map();
+        self.depth_readback.map();
+    }
+
+    /// Returns the result of the pick in flight once both texels have been read.
+    pub fn poll(&mut self, ctx: &Context) -> Option<PickResult> {
+        let in_flight = self.in_flight.as_mut()?;
+        let device = &ctx.device.logical_device;
+
+        if let Some(data) = self.id_readback.try_read(device) {
+            in_flight.id = Some(*bytemuck::from_bytes::<u32>(&data[..4]));
+        }
+
+        if let Some(data) = self.depth_readback.try_read(device) {
+            in_flight.depth = Some(*bytemuck::from_bytes::<f32>(&data[..4]));
+        }
+
+        let (Some(id), Some(depth)) = (in_flight.id, in_flight.depth) else {
+            return None;
+        };
+
+        let in_flight = self.in_flight.take()?;
+
+        // The centre of the pixel, in the clip space the depth was written in.
+        let ndc_x = (in_flight.pixel.0 as f32 + 0.5) / WORLD_TARGET_SIZE.width as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (in_flight.pixel.1 as f32 + 0.5) / WORLD_TARGET_SIZE.height as f32 * 2.0;
+
+        let position = cgmath::Point3::from_homogeneous(in_flight.inverse_view_projection * cgmath::Vector4::new(ndc_x, ndc_y, depth, 1.0));
+
+        let hit = id.checked_sub(1)
+            .and_then(|index| in_flight.meshes.get(index as usize))
+            .map(|(model, mesh)| PickHit {
+                model: *model,
+                mesh: *mesh,
+                position,
+            });
+
+        Some(PickResult {
+            pixel: in_flight.pixel,
+            hit,
+        })
+    }
+}
+
+fn texel(texture: &wgpu::Texture, pixel: (u32, u32), aspect: wgpu::TextureAspect) -> wgpu::ImageCopyTexture<'_> {
+    wgpu::ImageCopyTexture {
+        texture,
+        mip_level: 0,
+        origin: wgpu::Origin3d { x: pixel.0, y: pixel.1, z: 0 },
+        aspect,
+    }
+}
@@ -0,0 +1,207 @@
+use std::rc::Rc;
+
+use super::context::{BindGroupLayout, Context, RenderPipeline, RenderPipelineState, Shader};
+
+
+/// Common blend configurations for a colour target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendPreset {
+    /// No blending. Required for formats that can't be blended, e.g. integer targets.
+    Disabled,
+    Replace,
+    /// Straight (non-premultiplied) alpha blending.
+    Alpha,
+    PremultipliedAlpha,
+    Additive,
+    Multiply,
+}
+
+impl BlendPreset {
+    pub fn blend_state(&self) -> Option<wgpu::BlendState> {
+        match self {
+            BlendPreset::Disabled => None,
+            BlendPreset::Replace => Some(wgpu::BlendState::REPLACE),
+            BlendPreset::Alpha => Some(wgpu::BlendState::ALPHA_BLENDING),
+            BlendPreset::PremultipliedAlpha => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            BlendPreset::Additive => Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }),
+            BlendPreset::Multiply => Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::DstAlpha,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            }),
+        }
+    }
+}
+
+/// Builds a `RenderPipeline` through the context's pipeline cache.
+///
+/// Defaults to the `vs_main`/`fs_main` entry points, a triangle list with
+/// back-face culling and counter-clockwise front faces, no colour targets, no
+/// depth and a single sample. Bind group layouts are reflected from the shader
+/// unless given with `with_bind_group_layouts`.
+pub struct RenderPipelineBuilder<'a> {
+    ctx: &'a mut Context,
+    label: &'a str,
+    shader: &'a Shader,
+    bind_group_layouts: Option<Vec<Rc<BindGroupLayout>>>,
+    state: RenderPipelineState,
+}
+
+impl<'a> RenderPipelineBuilder<'a> {
+    pub fn new(ctx: &'a mut Context, label: &'a str, shader: &'a Shader) -> Self {
+        Self {
+            ctx,
+            label,
+            shader,
+            bind_group_layouts: None,
+            state: RenderPipelineState {
+                vertex_entry_point: "vs_main".to_string(),
+                fragment_entry_point: Some("fs_main".to_string()),
+                buffers: Vec::new(),
+                color_target_states: Vec::new(),
+                depth_stencil_state: None,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false
+                },
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false
+                },
+            },
+        }
+    }
+
+    pub fn with_vertex_entry_point(mut self, entry_point: &str) -> Self {
+        self.state.vertex_entry_point = entry_point.to_string();
+        self
+    }
+
+    pub fn with_fragment_entry_point(mut self, entry_point: &str) -> Self {
+        self.state.fragment_entry_point = Some(entry_point.to_string());
+        self
+    }
+
+    /// Builds the pipeline without a fragment stage, e.g. for depth-only passes.
+    pub fn without_fragment_stage(mut self) -> Self {
+        self.state.fragment_entry_point = None;
+        self
+    }
+
+    pub fn with_bind_group_layouts(mut self, bind_group_layouts: Vec<Rc<BindGroupLayout>>) -> Self {
+        self.bind_group_layouts = Some(bind_group_layouts);
+        self
+    }
+
+    pub fn with_vertex_buffers(mut self, buffers: &[wgpu::VertexBufferLayout<'static>]) -> Self {
+        self.state.buffers = buffers.to_vec();
+        self
+    }
+
+    /// Adds a colour target that writes all channels.
+    pub fn with_color_target(mut self, format: wgpu::TextureFormat, blend: BlendPreset) -> Self {
+        self.state.color_target_states.push(Some(wgpu::ColorTargetState {
+            format,
+            blend: blend.blend_state(),
+            write_mask: wgpu::ColorWrites::ALL
+        }));
+        self
+    }
+
+    /// Adds colour targets as-is, in attachment order.
+    pub fn with_color_target_states(mut self, color_target_states: &[Option<wgpu::ColorTargetState>]) -> Self {
+        self.state.color_target_states.extend_from_slice(color_target_states);
+        self
+    }
+
+    pub fn with_depth(mut self, format: wgpu::TextureFormat, depth_compare: wgpu::CompareFunction, depth_write_enabled: bool) -> Self {
+        self.state.depth_stencil_state = Some(wgpu::DepthStencilState {
+            format,
+            depth_compare,
+            depth_write_enabled,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        });
+        self
+    }
+
+    /// Requires `with_depth` to have been called first.
+    pub fn with_depth_bias(mut self, constant: i32, slope_scale: f32, clamp: f32) -> Self {
+        self.depth_stencil_state("with_depth_bias").bias = wgpu::DepthBiasState {
+            constant,
+            slope_scale,
+            clamp
+        };
+        self
+    }
+
+    /// Requires `with_depth` to have been called first.
+    pub fn with_stencil(mut self, stencil: wgpu::StencilState) -> Self {
+        self.depth_stencil_state("with_stencil").stencil = stencil;
+        self
+    }
+
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.state.primitive.topology = topology;
+        self
+    }
+
+    pub fn with_polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.state.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.state.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn with_front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.state.primitive.front_face = front_face;
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.state.multisample.count = sample_count;
+        self
+    }
+
+    pub fn with_alpha_to_coverage(mut self, enabled: bool) -> Self {
+        self.state.multisample.alpha_to_coverage_enabled = enabled;
+        self
+    }
+
+    pub fn build(self) -> Rc<RenderPipeline> {
+        self.ctx.create_render_pipeline(self.label, self.shader, self.bind_group_layouts, self.state)
+    }
+
+    fn depth_stencil_state(&mut self, method: &str) -> &mut wgpu::DepthStencilState {
+        self.state.depth_stencil_state
+            .as_mut()
+            .unwrap_or_else(|| panic!("{} on pipeline {} requires with_depth to be called first", method, self.label))
+    }
+}
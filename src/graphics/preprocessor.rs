@@ -0,0 +1,442 @@
+use std::{collections::{BTreeMap, HashSet}, fmt};
+
+
+/// Preprocessor symbols, keyed by name. A define without a value maps to an
+/// empty string and only affects `#ifdef`/`#ifndef`.
+pub type Defines = BTreeMap<String, String>;
+
+/// Where a line of preprocessed WGSL came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    pub location: SourceLocation,
+    pub message: String,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// The output of `preprocess`: plain WGSL plus enough bookkeeping to report
+/// errors against the original files.
+#[derive(Debug, Clone)]
+pub struct PreprocessedShader {
+    pub code: String,
+    /// Every file that was read, starting with the root shader.
+    pub dependencies: Vec<String>,
+    /// The original location of each line of `code`, indexed by 0-based line.
+    line_map: Vec<SourceLocation>,
+}
+
+impl PreprocessedShader {
+    /// Maps a 1-based line of the preprocessed code back to its original file and line.
+    pub fn original_location(&self, line_number: usize) -> Option<&SourceLocation> {
+        line_number
+            .checked_sub(1)
+            .and_then(|index| self.line_map.get(index))
+    }
+}
+
+/// Expands `#include`, `#define`, `#undef`, `#ifdef`, `#ifndef`, `#else` and
+/// `#endif` in the shader at `path`.
+///
+/// Include paths are relative to the directory of the root shader, so every
+/// file in a shader tree refers to e.g. `"common/camera.wgsl"` the same way.
+/// Each file is included at most once. Defined names with a value are replaced
+/// wherever they appear as a whole identifier in the code.
+///
+/// `load` reads a file by path; it is the only I/O the preprocessor does, so
+/// the whole thing runs on in-memory sources as well.
+pub fn preprocess(
+    path: &str,
+    defines: &Defines,
+    load: &dyn Fn(&str) -> Result<String, String>,
+) -> Result<PreprocessedShader, PreprocessError> {
+    let root = match path.rfind('/') {
+        Some(index) => &path[..=index],
+        None => "",
+    };
+
+    let mut state = PreprocessState {
+        root,
+        load,
+        defines: defines.clone(),
+        included: HashSet::new(),
+        include_stack: Vec::new(),
+        output: PreprocessedShader {
+            code: String::new(),
+            dependencies: Vec::new(),
+            line_map: Vec::new(),
+        },
+    };
+
+    let code = load(path).map_err(|message| PreprocessError {
+        location: SourceLocation { file: path.to_string(), line: 0 },
+        message,
+    })?;
+
+    state.process_file(path, &code)?;
+
+    Ok(state.output)
+}
+
+struct Conditional {
+    active: bool,
+    seen_else: bool,
+    location: SourceLocation,
+}
+
+struct PreprocessState<'a> {
+    root: &'a str,
+    load: &'a dyn Fn(&str) -> Result<String, String>,
+    defines: Defines,
+    included: HashSet<String>,
+    include_stack: Vec<String>,
+    output: PreprocessedShader,
+}
+
+impl PreprocessState<'_> {
+    fn process_file(&mut self, path: &str, code: &str) -> Result<(), PreprocessError> {
+        self.included.insert(path.to_string());
+        self.include_stack.push(path.to_string());
+        self.output.dependencies.push(path.to_string());
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+
+        for (index, line) in code.lines().enumerate() {
+            let location = SourceLocation { file: path.to_string(), line: index + 1 };
+            let active = conditionals.iter().all(|conditional| conditional.active);
+            let trimmed = line.trim_start();
+
+            let Some(directive) = trimmed.strip_prefix('#') else {
+                if active {
+                    self.emit(&self.substitute(line), location);
+                }
+                continue;
+            };
+
+            let (name, argument) = match directive.split_once(char::is_whitespace) {
+                Some((name, argument)) => (name, argument.trim()),
+                None => (directive.trim_end(), ""),
+            };
+
+            let error = |message: String| PreprocessError { location: location.clone(), message };
+
+            match name {
+                "ifdef" | "ifndef" => {
+                    let symbol = parse_identifier(argument).map_err(error)?;
+                    let defined = self.defines.contains_key(symbol);
+
+                    conditionals.push(Conditional {
+                        active: if name == "ifdef" { defined } else { !defined },
+                        seen_else: false,
+                        location,
+                    });
+                },
+                "else" => {
+                    let conditional = conditionals.last_mut()
+                        .ok_or_else(|| error("#else without #ifdef".to_string()))?;
+
+                    if conditional.seen_else {
+                        return Err(error(format!("duplicate #else for #ifdef at {}", conditional.location)));
+                    }
+
+                    conditional.active = !conditional.active;
+                    conditional.seen_else = true;
+                },
+                "endif" => {
+                    conditionals.pop()
+                        .ok_or_else(|| error("#endif without #ifdef".to_string()))?;
+                },
+                _ if !active => {},
+                "define" => {
+                    let (symbol, value) = match argument.split_once(char::is_whitespace) {
+                        Some((symbol, value)) => (symbol, value.trim()),
+                        None => (argument, ""),
+                    };
+
+                    let symbol = parse_identifier(symbol).map_err(error)?;
+
+                    self.defines.insert(symbol.to_string(), value.to_string());
+                },
+                "undef" => {
+                    let symbol = parse_identifier(argument).map_err(error)?;
+
+                    self.defines.remove(symbol);
+                },
+                "include" => {
+                    let include = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .ok_or_else(|| error(format!("expected #include \"path\", found #include {}", argument)))?;
+
+                    let include_path = format!("{}{}", self.root, include);
+
+                    if self.include_stack.contains(&include_path) {
+                        return Err(error(format!("{} includes itself through {}", include, self.include_stack.join(" -> "))));
+                    }
+
+                    if self.included.contains(&include_path) {
+                        continue;
+                    }
+
+                    let included_code = (self.load)(&include_path)
+                        .map_err(|message| error(format!("failed to include {}: {}", include, message)))?;
+
+                    self.process_file(&include_path, &included_code)?;
+                },
+                _ => return Err(error(format!("unknown preprocessor directive #{}", name))),
+            }
+        }
+
+        if let Some(conditional) = conditionals.last() {
+            return Err(PreprocessError {
+                location: conditional.location.clone(),
+                message: "#ifdef without matching #endif".to_string(),
+            });
+        }
+
+        self.include_stack.pop();
+
+        Ok(())
+    }
+
+    fn emit(&mut self, line: &str, location: SourceLocation) {
+        self.output.code.push_str(line);
+        self.output.code.push('\n');
+        self.output.line_map.push(location);
+    }
+
+    /// Replaces every identifier that has a non-empty define with its value.
+    fn substitute(&self, line: &str) -> String {
+        if self.defines.values().all(String::is_empty) {
+            return line.to_string();
+        }
+
+        let mut output = String::with_capacity(line.len());
+        let mut rest = line;
+
+        while let Some(start) = rest.find(is_identifier_start) {
+            let (before, from_start) = rest.split_at(start);
+            let end = from_start
+                .find(|c: char| !is_identifier_char(c))
+                .unwrap_or(from_start.len());
+            let (identifier, after) = from_start.split_at(end);
+
+            output.push_str(before);
+
+            // Digits directly before a letter belong to a literal such as `1u`, not an identifier.
+            let inside_literal = before.chars().last().is_some_and(is_identifier_char);
+
+            match self.defines.get(identifier) {
+                Some(value) if !value.is_empty() && !inside_literal => output.push_str(value),
+                _ => output.push_str(identifier),
+            }
+
+            rest = after;
+        }
+
+        output.push_str(rest);
+        output
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn parse_identifier(argument: &str) -> Result<&str, String> {
+    let valid = argument.starts_with(is_identifier_start) && argument.chars().all(is_identifier_char);
+
+    if valid {
+        Ok(argument)
+    } else {
+        Err(format!("expected an identifier, found '{}'", argument))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::graphics::context::{permutation_label, Shader};
+
+    use super::*;
+
+    fn sources(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files.iter().map(|(path, code)| (path.to_string(), code.to_string())).collect()
+    }
+
+    fn run(files: &[(&str, &str)], defines: &[(&str, &str)]) -> Result<PreprocessedShader, PreprocessError> {
+        let files = sources(files);
+        let defines: Defines = defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+
+        preprocess("shaders/main.wgsl", &defines, &|path| files.get(path).cloned().ok_or_else(|| format!("{} not found", path)))
+    }
+
+    fn lines(shader: &PreprocessedShader) -> Vec<&str> {
+        shader.code.lines().collect()
+    }
+
+    #[test]
+    fn nested_includes_are_relative_to_the_root_shader() {
+        let shader = run(&[
+            ("shaders/main.wgsl", "#include \"common/a.wgsl\"\nmain"),
+            ("shaders/common/a.wgsl", "#include \"common/b.wgsl\"\na"),
+            ("shaders/common/b.wgsl", "b"),
+        ], &[]).unwrap();
+
+        assert_eq!(lines(&shader), ["b", "a", "main"]);
+        assert_eq!(shader.dependencies, ["shaders/main.wgsl", "shaders/common/a.wgsl", "shaders/common/b.wgsl"]);
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let shader = run(&[
+            ("shaders/main.wgsl", "#include \"common/a.wgsl\"\n#include \"common/b.wgsl\"\n#include \"common/a.wgsl\"\nmain"),
+            ("shaders/common/a.wgsl", "a"),
+            ("shaders/common/b.wgsl", "#include \"common/a.wgsl\"\nb"),
+        ], &[]).unwrap();
+
+        assert_eq!(lines(&shader), ["a", "b", "main"]);
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let error = run(&[
+            ("shaders/main.wgsl", "#include \"common/a.wgsl\""),
+            ("shaders/common/a.wgsl", "#include \"common/b.wgsl\""),
+            ("shaders/common/b.wgsl", "\n#include \"common/a.wgsl\""),
+        ], &[]).unwrap_err();
+
+        assert_eq!(error.location, SourceLocation { file: "shaders/common/b.wgsl".to_string(), line: 2 });
+        assert!(error.message.contains("includes itself"), "{}", error.message);
+    }
+
+    #[test]
+    fn missing_includes_are_errors() {
+        let error = run(&[("shaders/main.wgsl", "\n\n#include \"missing.wgsl\"")], &[]).unwrap_err();
+
+        assert_eq!(error.location, SourceLocation { file: "shaders/main.wgsl".to_string(), line: 3 });
+    }
+
+    #[test]
+    fn conditionals_nest() {
+        let code = "\
+#ifdef A
+a
+#ifdef B
+ab
+#else
+a_not_b
+#endif
+#else
+not_a
+#ifndef B
+not_a_not_b
+#endif
+#endif";
+
+        let shader = |defines: &[(&str, &str)]| lines(&run(&[("shaders/main.wgsl", code)], defines).unwrap()).join(" ");
+
+        assert_eq!(shader(&[("A", ""), ("B", "")]), "a ab");
+        assert_eq!(shader(&[("A", "")]), "a a_not_b");
+        assert_eq!(shader(&[("B", "")]), "not_a");
+        assert_eq!(shader(&[]), "not_a not_a_not_b");
+    }
+
+    #[test]
+    fn defines_in_code_apply_to_later_lines_and_includes() {
+        let shader = run(&[
+            ("shaders/main.wgsl", "#define SIZE 4\n#define FLAG\n#include \"common/a.wgsl\"\n#undef FLAG\n#ifdef FLAG\nflag\n#endif\nSIZE"),
+            ("shaders/common/a.wgsl", "#ifdef FLAG\narray<f32, SIZE>\n#endif\nSIZES 4u"),
+        ], &[]).unwrap();
+
+        assert_eq!(lines(&shader), ["array<f32, 4>", "SIZES 4u", "4"]);
+    }
+
+    #[test]
+    fn defines_with_values_replace_whole_identifiers() {
+        let shader = run(&[("shaders/main.wgsl", "let x = COUNT + MY_COUNT + COUNT_2 + 1COUNT;")], &[("COUNT", "8")]).unwrap();
+
+        assert_eq!(lines(&shader), ["let x = 8 + MY_COUNT + COUNT_2 + 1COUNT;"]);
+    }
+
+    #[test]
+    fn unterminated_conditionals_are_errors() {
+        let error = run(&[("shaders/main.wgsl", "a\n#ifdef A\n#ifdef B\n#endif")], &[]).unwrap_err();
+
+        assert_eq!(error.location, SourceLocation { file: "shaders/main.wgsl".to_string(), line: 2 });
+        assert!(error.message.contains("#endif"), "{}", error.message);
+    }
+
+    #[test]
+    fn unbalanced_directives_are_errors() {
+        for code in ["#endif", "#else", "#ifdef A\n#else\n#else\n#endif", "#ifdef 1A\n#endif", "#pragma once", "#include common.wgsl"] {
+            assert!(run(&[("shaders/main.wgsl", code)], &[]).is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn conditionals_do_not_leak_out_of_includes() {
+        let error = run(&[
+            ("shaders/main.wgsl", "#include \"common/a.wgsl\"\n#endif"),
+            ("shaders/common/a.wgsl", "#ifdef A"),
+        ], &[]).unwrap_err();
+
+        assert_eq!(error.location.file, "shaders/common/a.wgsl");
+    }
+
+    #[test]
+    fn lines_map_back_to_their_original_file() {
+        let shader = run(&[
+            ("shaders/main.wgsl", "#include \"common/a.wgsl\"\n\nmain"),
+            ("shaders/common/a.wgsl", "#ifdef A\nskipped\n#endif\na"),
+        ], &[]).unwrap();
+
+        assert_eq!(lines(&shader), ["a", "", "main"]);
+        assert_eq!(shader.original_location(1), Some(&SourceLocation { file: "shaders/common/a.wgsl".to_string(), line: 4 }));
+        assert_eq!(shader.original_location(3), Some(&SourceLocation { file: "shaders/main.wgsl".to_string(), line: 3 }));
+        assert_eq!(shader.original_location(0), None);
+        assert_eq!(shader.original_location(4), None);
+    }
+
+    #[test]
+    fn compile_errors_report_the_original_file_and_line() {
+        let shader = run(&[
+            ("shaders/main.wgsl", "#include \"common/a.wgsl\"\n\nfn main_value() -> f32 {\n    return value();\n}"),
+            ("shaders/common/a.wgsl", "#ifdef A\nskipped\n#endif\nfn value() -> f32 {\n    return 1.0 +;\n}"),
+        ], &[]).unwrap();
+
+        let error = Shader::validate(&shader, "shaders/main.wgsl").err().unwrap();
+
+        assert!(error.starts_with("shaders/common/a.wgsl:5:"), "{}", error);
+    }
+
+    #[test]
+    fn permutation_labels_list_defines_in_order() {
+        let defines: Defines = [("SKINNED", ""), ("MAX_JOINTS", "64")].into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+
+        assert_eq!(permutation_label("cube_shader", &Defines::new()), "cube_shader");
+        assert_eq!(permutation_label("cube_shader", &defines), "cube_shader[MAX_JOINTS=64,SKINNED]");
+    }
+}
@@ -0,0 +1,638 @@
+use std::{collections::BTreeMap, fmt};
+
+use super::context::BindGroupLayoutEntry;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectionError {
+    MissingBinding { group: u32, binding: u32 },
+    MissingEntryPoint(String),
+    NotABuffer { name: String },
+    UnsupportedType { name: String, description: String },
+    SizeMismatch { name: String, rust_size: u32, wgsl_size: u32 },
+    MemberMismatch { name: String, member: String, rust_offset: u32, rust_size: u32, wgsl_offset: u32, wgsl_size: u32 },
+    MemberCountMismatch { name: String, rust_members: usize, wgsl_members: usize },
+    MissingVertexAttribute { location: u32, name: String },
+    VertexFormatMismatch { location: u32, name: String, buffer_format: wgpu::VertexFormat, shader_format: wgpu::VertexFormat },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectionError::MissingBinding { group, binding } =>
+                write!(f, "shader has no resource at @group({}) @binding({})", group, binding),
+            ReflectionError::MissingEntryPoint(name) =>
+                write!(f, "shader has no entry point named {}", name),
+            ReflectionError::NotABuffer { name } =>
+                write!(f, "{} is not a uniform or storage buffer", name),
+            ReflectionError::UnsupportedType { name, description } =>
+                write!(f, "{} has a type that cannot be reflected: {}", name, description),
+            ReflectionError::SizeMismatch { name, rust_size, wgsl_size } =>
+                write!(f, "{} is {} bytes in Rust but {} bytes in WGSL", name, rust_size, wgsl_size),
+            ReflectionError::MemberMismatch { name, member, rust_offset, rust_size, wgsl_offset, wgsl_size } =>
+                write!(
+                    f,
+                    "{}.{} is at offset {} with size {} in Rust but at offset {} with size {} in WGSL",
+                    name, member, rust_offset, rust_size, wgsl_offset, wgsl_size
+                ),
+            ReflectionError::MemberCountMismatch { name, rust_members, wgsl_members } =>
+                write!(f, "{} has {} members in Rust but {} in WGSL", name, rust_members, wgsl_members),
+            ReflectionError::MissingVertexAttribute { location, name } =>
+                write!(f, "vertex input {} at @location({}) is not provided by any vertex buffer", name, location),
+            ReflectionError::VertexFormatMismatch { location, name, buffer_format, shader_format } =>
+                write!(
+                    f,
+                    "vertex input {} at @location({}) is {:?} in the shader but {:?} in the vertex buffer",
+                    name, location, shader_format, buffer_format
+                ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+/// A field of a Rust uniform struct, as seen by `UniformLayout`.
+#[derive(Debug, Clone, Copy)]
+pub struct UniformMember {
+    pub name: &'static str,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Describes the memory layout of a `#[repr(C)]` struct that is uploaded to a
+/// uniform or storage buffer, so it can be checked against the WGSL struct it
+/// is bound to. Implement it with `uniform_layout!`. Members whose name starts
+/// with an underscore are treated as explicit padding and are not compared.
+pub trait UniformLayout: bytemuck::Pod {
+    fn members() -> Vec<UniformMember>;
+}
+
+/// Implements `UniformLayout` for a struct from the list of its fields.
+#[macro_export]
+macro_rules! uniform_layout {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::graphics::reflection::UniformLayout for $ty {
+            fn members() -> Vec<$crate::graphics::reflection::UniformMember> {
+                let value: $ty = bytemuck::Zeroable::zeroed();
+
+                vec![$(
+                    $crate::graphics::reflection::UniformMember {
+                        name: stringify!($field),
+                        offset: std::mem::offset_of!($ty, $field) as u32,
+                        size: std::mem::size_of_val(&value.$field) as u32,
+                    }
+                ),*]
+            }
+        }
+    };
+}
+
+/// A resource binding declared by the shader.
+#[derive(Debug, Clone)]
+pub struct ReflectedBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub name: String,
+    pub visibility: wgpu::ShaderStages,
+    pub ty: wgpu::BindingType,
+    /// Size in bytes of the bound type for buffers, or of one element for
+    /// runtime-sized storage arrays.
+    pub size: Option<u32>,
+}
+
+/// A `@location` input of a vertex entry point.
+#[derive(Debug, Clone)]
+pub struct ReflectedVertexInput {
+    pub location: u32,
+    pub name: String,
+    pub format: wgpu::VertexFormat,
+}
+
+/// A texture and the sampler it is sampled with.
+struct SamplingPair {
+    image: naga::Handle<naga::GlobalVariable>,
+    sampler: naga::Handle<naga::GlobalVariable>,
+}
+
+/// Reflection data for a validated WGSL module: the resources it binds, the
+/// vertex inputs of its entry points, and the layout of its structs.
+pub struct ShaderReflection {
+    module: naga::Module,
+    info: naga::valid::ModuleInfo,
+}
+
+impl ShaderReflection {
+    pub fn new(module: naga::Module, info: naga::valid::ModuleInfo) -> Self {
+        Self {
+            module,
+            info,
+        }
+    }
+
+    pub fn module(&self) -> &naga::Module {
+        &self.module
+    }
+
+    /// Every `@group @binding` resource, sorted by group then binding.
+    pub fn bindings(&self) -> Result<Vec<ReflectedBinding>, ReflectionError> {
+        let sampling = self.sampling_pairs();
+
+        let mut bindings = self.module.global_variables
+            .iter()
+            .filter_map(|(handle, global)| global.binding.as_ref().map(|binding| (handle, global, binding)))
+            .map(|(handle, global, resource_binding)| {
+                let name = global.name.clone().unwrap_or_default();
+
+                let visibility = self.module.entry_points
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| !self.info.get_entry_point(*index)[handle].is_empty())
+                    .fold(wgpu::ShaderStages::NONE, |stages, (_, entry_point)| stages | shader_stage(entry_point.stage));
+
+                let (ty, size) = self.binding_type(&name, handle, global, &sampling)?;
+
+                Ok(ReflectedBinding {
+                    group: resource_binding.group,
+                    binding: resource_binding.binding,
+                    name,
+                    visibility,
+                    ty,
+                    size,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        bindings.sort_by_key(|binding| (binding.group, binding.binding));
+
+        Ok(bindings)
+    }
+
+    /// The bind group indices used by the shader.
+    pub fn groups(&self) -> Result<Vec<u32>, ReflectionError> {
+        let mut groups: Vec<u32> = self.bindings()?.iter().map(|binding| binding.group).collect();
+        groups.dedup();
+        Ok(groups)
+    }
+
+    /// Layout entries for one bind group, ready for `Context::create_bind_group_layout`.
+    /// `min_binding_size` is left unset so that the layouts are identical to (and
+    /// therefore deduplicated with) layouts written by hand; use `validate_uniform`
+    /// to check buffer sizes instead.
+    pub fn bind_group_layout_entries(&self, group: u32) -> Result<Vec<BindGroupLayoutEntry>, ReflectionError> {
+        Ok(self.bindings()?
+            .into_iter()
+            .filter(|binding| binding.group == group)
+            .map(|binding| BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility: binding.visibility,
+                ty: binding.ty,
+            })
+            .collect())
+    }
+
+    /// Entries for every group from 0 to the highest one used. Groups the shader
+    /// skips get an empty layout.
+    pub fn all_bind_group_layout_entries(&self) -> Result<Vec<Vec<BindGroupLayoutEntry>>, ReflectionError> {
+        let bindings = self.bindings()?;
+        let group_count = bindings.iter().map(|binding| binding.group + 1).max().unwrap_or(0);
+
+        (0..group_count).map(|group| self.bind_group_layout_entries(group)).collect()
+    }
+
+    pub fn binding(&self, group: u32, binding: u32) -> Result<ReflectedBinding, ReflectionError> {
+        self.bindings()?
+            .into_iter()
+            .find(|reflected| reflected.group == group && reflected.binding == binding)
+            .ok_or(ReflectionError::MissingBinding { group, binding })
+    }
+
+    /// Size in bytes of the buffer bound at `group`/`binding`.
+    pub fn uniform_size(&self, group: u32, binding: u32) -> Result<u32, ReflectionError> {
+        let reflected = self.binding(group, binding)?;
+
+        reflected.size.ok_or(ReflectionError::NotABuffer { name: reflected.name })
+    }
+
+    /// The `@location` inputs of a vertex entry point, sorted by location.
+    pub fn vertex_inputs(&self, entry_point: &str) -> Result<Vec<ReflectedVertexInput>, ReflectionError> {
+        let entry = self.module.entry_points
+            .iter()
+            .find(|entry| entry.name == entry_point && entry.stage == naga::ShaderStage::Vertex)
+            .ok_or_else(|| ReflectionError::MissingEntryPoint(entry_point.to_string()))?;
+
+        let mut inputs = Vec::new();
+
+        for argument in &entry.function.arguments {
+            let name = argument.name.clone().unwrap_or_default();
+
+            match (&argument.binding, &self.module.types[argument.ty].inner) {
+                (Some(naga::Binding::Location { location, .. }), inner) => {
+                    inputs.push(ReflectedVertexInput {
+                        location: *location,
+                        format: vertex_format(&name, inner)?,
+                        name,
+                    });
+                },
+                (None, naga::TypeInner::Struct { members, .. }) => {
+                    for member in members {
+                        if let Some(naga::Binding::Location { location, .. }) = member.binding {
+                            let name = member.name.clone().unwrap_or_default();
+
+                            inputs.push(ReflectedVertexInput {
+                                location,
+                                format: vertex_format(&name, &self.module.types[member.ty].inner)?,
+                                name,
+                            });
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        inputs.sort_by_key(|input| input.location);
+
+        Ok(inputs)
+    }
+
+    /// Checks that every vertex input of `entry_point` is provided by one of the
+    /// buffer layouts, with a format the shader type can be read from (so e.g.
+    /// a `Unorm8x4` attribute may feed a `vec4<f32>`).
+    pub fn validate_vertex_layout(&self, entry_point: &str, buffers: &[wgpu::VertexBufferLayout]) -> Result<(), ReflectionError> {
+        let attributes: BTreeMap<u32, wgpu::VertexFormat> = buffers
+            .iter()
+            .flat_map(|buffer| buffer.attributes.iter())
+            .map(|attribute| (attribute.shader_location, attribute.format))
+            .collect();
+
+        self.vertex_inputs(entry_point)?.into_iter().try_for_each(|input| {
+            match attributes.get(&input.location) {
+                None => Err(ReflectionError::MissingVertexAttribute { location: input.location, name: input.name }),
+                Some(format) if vertex_format_shape(*format) != vertex_format_shape(input.format) => Err(ReflectionError::VertexFormatMismatch {
+                    location: input.location,
+                    name: input.name,
+                    buffer_format: *format,
+                    shader_format: input.format,
+                }),
+                Some(_) => Ok(()),
+            }
+        })
+    }
+
+    /// Checks that `T` has the same size and member layout as the WGSL struct
+    /// bound at `group`/`binding`. For runtime-sized storage arrays, `T` is
+    /// compared against the array element.
+    pub fn validate_uniform<T: UniformLayout>(&self, group: u32, binding: u32) -> Result<(), ReflectionError> {
+        let reflected = self.binding(group, binding)?;
+
+        let global = self.module.global_variables
+            .iter()
+            .map(|(_, global)| global)
+            .find(|global| global.binding.as_ref().is_some_and(|resource| resource.group == group && resource.binding == binding))
+            .ok_or(ReflectionError::MissingBinding { group, binding })?;
+
+        let mut ty = global.ty;
+
+        if let naga::TypeInner::Array { base, size: naga::ArraySize::Dynamic, .. } = self.module.types[ty].inner {
+            ty = base;
+        }
+
+        let name = self.module.types[ty].name.clone().unwrap_or(reflected.name);
+
+        let wgsl_size = self.module.types[ty].inner.size(self.module.to_ctx());
+        let rust_size = std::mem::size_of::<T>() as u32;
+
+        if rust_size != wgsl_size {
+            return Err(ReflectionError::SizeMismatch { name, rust_size, wgsl_size });
+        }
+
+        let naga::TypeInner::Struct { members, .. } = &self.module.types[ty].inner else {
+            return Ok(());
+        };
+
+        let rust_members: Vec<UniformMember> = T::members()
+            .into_iter()
+            .filter(|member| !member.name.starts_with('_'))
+            .collect();
+
+        if rust_members.len() != members.len() {
+            return Err(ReflectionError::MemberCountMismatch { name, rust_members: rust_members.len(), wgsl_members: members.len() });
+        }
+
+        rust_members.iter().zip(members.iter()).try_for_each(|(rust, wgsl)| {
+            let wgsl_size = self.module.types[wgsl.ty].inner.size(self.module.to_ctx());
+
+            if rust.offset != wgsl.offset || rust.size != wgsl_size {
+                return Err(ReflectionError::MemberMismatch {
+                    name: name.clone(),
+                    member: format!("{} ({})", rust.name, wgsl.name.clone().unwrap_or_default()),
+                    rust_offset: rust.offset,
+                    rust_size: rust.size,
+                    wgsl_offset: wgsl.offset,
+                    wgsl_size,
+                });
+            }
+
+            Ok(())
+        })
+    }
+
+    /// The (texture, sampler) pairs passed to `textureSample*` by any entry point,
+    /// including calls made from helper functions.
+    fn sampling_pairs(&self) -> Vec<SamplingPair> {
+        (0..self.module.entry_points.len())
+            .flat_map(|index| self.info.get_entry_point(index).sampling_set.iter())
+            .map(|key| SamplingPair { image: key.image, sampler: key.sampler })
+            .collect()
+    }
+
+    /// A non-comparison sampler can only filter when none of the textures it samples
+    /// are depth textures, which require a non-filtering sampler.
+    fn sampler_filters(&self, sampler: naga::Handle<naga::GlobalVariable>, sampling: &[SamplingPair]) -> bool {
+        !sampling
+            .iter()
+            .filter(|key| key.sampler == sampler)
+            .any(|key| matches!(
+                self.module.types[self.module.global_variables[key.image].ty].inner,
+                naga::TypeInner::Image { class: naga::ImageClass::Depth { .. }, .. }
+            ))
+    }
+
+    fn binding_type(
+        &self,
+        name: &str,
+        handle: naga::Handle<naga::GlobalVariable>,
+        global: &naga::GlobalVariable,
+        sampling: &[SamplingPair]
+    ) -> Result<(wgpu::BindingType, Option<u32>), ReflectionError> {
+        let inner = &self.module.types[global.ty].inner;
+
+        let unsupported = |description: String| ReflectionError::UnsupportedType { name: name.to_string(), description };
+
+        let buffer_size = || match inner {
+            naga::TypeInner::Array { base, size: naga::ArraySize::Dynamic, .. } => self.module.types[*base].inner.size(self.module.to_ctx()),
+            inner => inner.size(self.module.to_ctx()),
+        };
+
+        match (global.space, inner) {
+            (naga::AddressSpace::Uniform, _) => Ok((
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                },
+                Some(buffer_size()),
+            )),
+            (naga::AddressSpace::Storage { access }, _) => Ok((
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: !access.contains(naga::StorageAccess::STORE) },
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                },
+                Some(buffer_size()),
+            )),
+            (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => Ok((
+                wgpu::BindingType::Sampler(if *comparison {
+                    wgpu::SamplerBindingType::Comparison
+                } else if self.sampler_filters(handle, sampling) {
+                    wgpu::SamplerBindingType::Filtering
+                } else {
+                    wgpu::SamplerBindingType::NonFiltering
+                }),
+                None,
+            )),
+            (naga::AddressSpace::Handle, naga::TypeInner::Image { dim, arrayed, class }) => {
+                let view_dimension = view_dimension(*dim, *arrayed);
+
+                let ty = match class {
+                    naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                        sample_type: match kind {
+                            // A float texture only has to be filterable when it is sampled with a
+                            // filtering sampler; textures that are only loaded accept any float format.
+                            naga::ScalarKind::Float => wgpu::TextureSampleType::Float {
+                                filterable: sampling
+                                    .iter()
+                                    .filter(|key| key.image == handle)
+                                    .any(|key| {
+                                        let sampler = &self.module.global_variables[key.sampler];
+                                        matches!(self.module.types[sampler.ty].inner, naga::TypeInner::Sampler { comparison: false })
+                                            && self.sampler_filters(key.sampler, sampling)
+                                    }),
+                            },
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            naga::ScalarKind::Bool => return Err(unsupported("boolean texture".to_string())),
+                        },
+                        view_dimension,
+                        multisampled: *multi,
+                    },
+                    naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension,
+                        multisampled: *multi,
+                    },
+                    naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                        access: match (access.contains(naga::StorageAccess::LOAD), access.contains(naga::StorageAccess::STORE)) {
+                            (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                            (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                            _ => wgpu::StorageTextureAccess::WriteOnly,
+                        },
+                        format: storage_format(*format).ok_or_else(|| unsupported(format!("storage format {:?}", format)))?,
+                        view_dimension,
+                    },
+                };
+
+                Ok((ty, None))
+            },
+            (space, inner) => Err(unsupported(format!("{:?} in {:?}", inner, space))),
+        }
+    }
+}
+
+fn shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> Option<wgpu::TextureFormat> {
+    use naga::StorageFormat as Naga;
+    use wgpu::TextureFormat as Wgpu;
+
+    Some(match format {
+        Naga::R32Float => Wgpu::R32Float,
+        Naga::R32Uint => Wgpu::R32Uint,
+        Naga::R32Sint => Wgpu::R32Sint,
+        Naga::Rg32Float => Wgpu::Rg32Float,
+        Naga::Rg32Uint => Wgpu::Rg32Uint,
+        Naga::Rg32Sint => Wgpu::Rg32Sint,
+        Naga::Rgba8Unorm => Wgpu::Rgba8Unorm,
+        Naga::Rgba8Snorm => Wgpu::Rgba8Snorm,
+        Naga::Rgba8Uint => Wgpu::Rgba8Uint,
+        Naga::Rgba8Sint => Wgpu::Rgba8Sint,
+        Naga::Rgba16Float => Wgpu::Rgba16Float,
+        Naga::Rgba16Uint => Wgpu::Rgba16Uint,
+        Naga::Rgba16Sint => Wgpu::Rgba16Sint,
+        Naga::Rgba32Float => Wgpu::Rgba32Float,
+        Naga::Rgba32Uint => Wgpu::Rgba32Uint,
+        Naga::Rgba32Sint => Wgpu::Rgba32Sint,
+        _ => return None,
+    })
+}
+
+fn vertex_format(name: &str, inner: &naga::TypeInner) -> Result<wgpu::VertexFormat, ReflectionError> {
+    use wgpu::VertexFormat as Format;
+
+    let (kind, components) = match inner {
+        naga::TypeInner::Scalar { kind, width: 4 } => (*kind, 1),
+        naga::TypeInner::Vector { size, kind, width: 4 } => (*kind, *size as u8),
+        inner => return Err(ReflectionError::UnsupportedType { name: name.to_string(), description: format!("{:?}", inner) }),
+    };
+
+    let format = match (kind, components) {
+        (naga::ScalarKind::Float, 1) => Format::Float32,
+        (naga::ScalarKind::Float, 2) => Format::Float32x2,
+        (naga::ScalarKind::Float, 3) => Format::Float32x3,
+        (naga::ScalarKind::Float, 4) => Format::Float32x4,
+        (naga::ScalarKind::Uint, 1) => Format::Uint32,
+        (naga::ScalarKind::Uint, 2) => Format::Uint32x2,
+        (naga::ScalarKind::Uint, 3) => Format::Uint32x3,
+        (naga::ScalarKind::Uint, 4) => Format::Uint32x4,
+        (naga::ScalarKind::Sint, 1) => Format::Sint32,
+        (naga::ScalarKind::Sint, 2) => Format::Sint32x2,
+        (naga::ScalarKind::Sint, 3) => Format::Sint32x3,
+        (naga::ScalarKind::Sint, 4) => Format::Sint32x4,
+        _ => return Err(ReflectionError::UnsupportedType { name: name.to_string(), description: format!("{:?}", inner) }),
+    };
+
+    Ok(format)
+}
+
+/// The shader-side scalar kind and component count a vertex format is read as.
+fn vertex_format_shape(format: wgpu::VertexFormat) -> (naga::ScalarKind, u8) {
+    use wgpu::VertexFormat as Format;
+
+    match format {
+        Format::Float32 => (naga::ScalarKind::Float, 1),
+        Format::Float32x2 | Format::Float16x2 | Format::Unorm8x2 | Format::Snorm8x2 | Format::Unorm16x2 | Format::Snorm16x2 => (naga::ScalarKind::Float, 2),
+        Format::Float32x3 => (naga::ScalarKind::Float, 3),
+        Format::Float32x4 | Format::Float16x4 | Format::Unorm8x4 | Format::Snorm8x4 | Format::Unorm16x4 | Format::Snorm16x4 => (naga::ScalarKind::Float, 4),
+        Format::Uint32 => (naga::ScalarKind::Uint, 1),
+        Format::Uint32x2 | Format::Uint8x2 | Format::Uint16x2 => (naga::ScalarKind::Uint, 2),
+        Format::Uint32x3 => (naga::ScalarKind::Uint, 3),
+        Format::Uint32x4 | Format::Uint8x4 | Format::Uint16x4 => (naga::ScalarKind::Uint, 4),
+        Format::Sint32 => (naga::ScalarKind::Sint, 1),
+        Format::Sint32x2 | Format::Sint8x2 | Format::Sint16x2 => (naga::ScalarKind::Sint, 2),
+        Format::Sint32x3 => (naga::ScalarKind::Sint, 3),
+        Format::Sint32x4 | Format::Sint8x4 | Format::Sint16x4 => (naga::ScalarKind::Sint, 4),
+        Format::Float64 | Format::Float64x2 | Format::Float64x3 | Format::Float64x4 => (naga::ScalarKind::Float, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reflect(source: &str) -> ShaderReflection {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module)
+            .unwrap();
+        ShaderReflection::new(module, info)
+    }
+
+    fn binding_type(reflection: &ShaderReflection, name: &str) -> wgpu::BindingType {
+        reflection.bindings().unwrap().into_iter().find(|binding| binding.name == name).unwrap().ty
+    }
+
+    fn sample_type(reflection: &ShaderReflection, name: &str) -> wgpu::TextureSampleType {
+        match binding_type(reflection, name) {
+            wgpu::BindingType::Texture { sample_type, .. } => sample_type,
+            ty => panic!("{} is not a texture: {:?}", name, ty),
+        }
+    }
+
+    const SHADER: &str = "
+        @group(0) @binding(0) var t_color: texture_2d<f32>;
+        @group(0) @binding(1) var s_color: sampler;
+        @group(0) @binding(2) var t_ids: texture_2d<f32>;
+        @group(0) @binding(3) var t_depth: texture_depth_2d;
+        @group(0) @binding(4) var s_depth: sampler;
+        @group(0) @binding(5) var t_shadow: texture_depth_2d;
+        @group(0) @binding(6) var s_shadow: sampler_comparison;
+        @group(0) @binding(7) var t_unused_sampler: texture_2d<f32>;
+        @group(0) @binding(8) var s_unused: sampler;
+
+        fn sample_color(t: texture_2d<f32>, s: sampler, uv: vec2<f32>) -> vec4<f32> {
+            return textureSample(t, s, uv);
+        }
+
+        @fragment
+        fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+            let uv = position.xy;
+            let color = sample_color(t_color, s_color, uv);
+            let id = textureLoad(t_ids, vec2<i32>(position.xy), 0);
+            let depth = textureSample(t_depth, s_depth, uv);
+            let shadow = textureSampleCompare(t_shadow, s_shadow, uv, 0.5);
+            let other = textureLoad(t_unused_sampler, vec2<i32>(position.xy), 0);
+            return color + id + vec4<f32>(depth, shadow, 0.0, 0.0) + other;
+        }
+    ";
+
+    #[test]
+    fn texture_sampled_through_helper_with_filtering_sampler_is_filterable() {
+        let reflection = reflect(SHADER);
+        assert_eq!(sample_type(&reflection, "t_color"), wgpu::TextureSampleType::Float { filterable: true });
+        assert_eq!(binding_type(&reflection, "s_color"), wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering));
+    }
+
+    #[test]
+    fn loaded_texture_is_not_filterable_even_with_filtering_samplers_in_the_module() {
+        let reflection = reflect(SHADER);
+        assert_eq!(sample_type(&reflection, "t_ids"), wgpu::TextureSampleType::Float { filterable: false });
+        assert_eq!(sample_type(&reflection, "t_unused_sampler"), wgpu::TextureSampleType::Float { filterable: false });
+    }
+
+    #[test]
+    fn sampler_used_with_depth_texture_is_non_filtering() {
+        let reflection = reflect(SHADER);
+        assert_eq!(sample_type(&reflection, "t_depth"), wgpu::TextureSampleType::Depth);
+        assert_eq!(binding_type(&reflection, "s_depth"), wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering));
+    }
+
+    #[test]
+    fn comparison_sampler_and_unpaired_sampler() {
+        let reflection = reflect(SHADER);
+        assert_eq!(binding_type(&reflection, "s_shadow"), wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison));
+        assert_eq!(binding_type(&reflection, "s_unused"), wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering));
+    }
+
+    #[test]
+    fn texture_sampled_with_sampler_that_also_samples_depth_is_not_filterable() {
+        let reflection = reflect("
+            @group(0) @binding(0) var t_color: texture_2d<f32>;
+            @group(0) @binding(1) var t_depth: texture_depth_2d;
+            @group(0) @binding(2) var s_point: sampler;
+
+            @fragment
+            fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+                return textureSample(t_color, s_point, position.xy) * textureSample(t_depth, s_point, position.xy);
+            }
+        ");
+        assert_eq!(sample_type(&reflection, "t_color"), wgpu::TextureSampleType::Float { filterable: false });
+        assert_eq!(binding_type(&reflection, "s_point"), wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use cgmath::MetricSpace;
+
+use crate::world::{camera::PerspectiveCamera, model::Model, world::Mesh};
+
+use super::{context::{BindGroup, Buffer, RenderPipeline}, culling::RenderStats, picking::PickIds};
+
+
+const PIPELINE_BITS: u32 = 12;
+const MATERIAL_BITS: u32 = 14;
+const MESH_BITS: u32 = 14;
+const DEPTH_BITS: u32 = 24;
+
+/// Builds a key that sorts draws by pipeline, then material, then mesh, then
+/// depth, so state changes are grouped and equal-state draws go front to back.
+/// Ids wider than their field are truncated, which only costs sort quality.
+pub fn draw_key(pipeline_id: u64, material_id: u64, mesh_id: u64, depth: f32) -> u64 {
+    let field = |value: u64, bits: u32| value & ((1 << bits) - 1);
+
+    field(pipeline_id, PIPELINE_BITS) << (MATERIAL_BITS + MESH_BITS + DEPTH_BITS)
+        | field(material_id, MATERIAL_BITS) << (MESH_BITS + DEPTH_BITS)
+        | field(mesh_id, MESH_BITS) << DEPTH_BITS
+        | quantize_depth(depth)
+}
+
+/// The bit pattern of a non-negative float increases with its value, so its
+/// top bits are an order-preserving depth key that needs no far plane.
+fn quantize_depth(depth: f32) -> u64 {
+    (depth.max(0.0).to_bits() >> (32 - DEPTH_BITS)) as u64
+}
+
+/// Hands out small consecutive ids to objects in the order they are first seen.
+#[derive(Default)]
+struct IdAllocator {
+    ids: HashMap<usize, u64>,
+}
+
+impl IdAllocator {
+    fn id<T>(&mut self, object: *const T) -> u64 {
+        let next = self.ids.len() as u64;
+
+        *self.ids.entry(object as usize).or_insert(next)
+    }
+}
+
+pub struct DrawItem<'a> {
+    pub key: u64,
+    pub model: &'a Model,
+    pub mesh: &'a Mesh,
+    pub pipeline: &'a RenderPipeline,
+}
+
+/// The draws of one pass, sorted by `draw_key`.
+pub struct RenderQueue<'a> {
+    pub items: Vec<DrawItem<'a>>,
+}
+
+impl<'a> RenderQueue<'a> {
+    /// Queues every mesh with the pipeline `pipeline` picks for its model, sorted
+    /// by state and front to back from the camera.
+    pub fn opaque(
+        meshes: &[(&'a Model, &'a Mesh)],
+        camera: &PerspectiveCamera,
+        pipeline: impl Fn(&'a Model) -> &'a RenderPipeline,
+    ) -> Self {
+        let mut pipeline_ids = IdAllocator::default();
+        let mut material_ids = IdAllocator::default();
+        let mut mesh_ids = IdAllocator::default();
+
+        Self::sorted(meshes, camera, pipeline, |model, mesh, pipeline, depth| draw_key(
+            pipeline_ids.id(pipeline),
+            material_ids.id(model.material.as_ref()),
+            mesh_ids.id(mesh.vertex_buffer.as_ref()),
+            depth
+        ))
+    }
+
+    /// Queues every mesh sorted back to front only, as blending needs regardless
+    /// of how often that changes state.
+    pub fn transparent(
+        meshes: &[(&'a Model, &'a Mesh)],
+        camera: &PerspectiveCamera,
+        pipeline: impl Fn(&'a Model) -> &'a RenderPipeline,
+    ) -> Self {
+        Self::sorted(meshes, camera, pipeline, |_, _, _, depth| (1 << DEPTH_BITS) - 1 - quantize_depth(depth))
+    }
+
+    fn sorted(
+        meshes: &[(&'a Model, &'a Mesh)],
+        camera: &PerspectiveCamera,
+        pipeline: impl Fn(&'a Model) -> &'a RenderPipeline,
+        mut key: impl FnMut(&'a Model, &'a Mesh, &'a RenderPipeline, f32) -> u64,
+    ) -> Self {
+        let camera_position = camera.position();
+
+        let mut items: Vec<DrawItem<'a>> = meshes.iter().map(|(model, mesh)| {
+            let pipeline = pipeline(model);
+            let depth = mesh.bounds.transformed(&model.model_matrix()).center().distance(camera_position);
+
+            DrawItem {
+                key: key(model, mesh, pipeline, depth),
+                model,
+                mesh,
+                pipeline,
+            }
+        }).collect();
+
+        items.sort_by_key(|item| item.key);
+
+        Self {
+            items
+        }
+    }
+
+    /// Records every queued draw, binding the model at group 1 and its material
+    /// at group 2. Groups the pipelines share, such as the camera, must already
+    /// be set through `state`. Each mesh is drawn with its id in `ids` as the
+    /// instance index, or 0 without ids.
+    pub fn submit(&self, render_pass: &mut wgpu::RenderPass<'a>, state: &mut RenderState, ids: Option<&PickIds>) {
+        self.items.iter().for_each(|item| {
+            state.set_pipeline(render_pass, item.pipeline);
+
+            state.set_bind_group(render_pass, 1, item.model.draw_bind_group(item.mesh));
+
+            state.set_bind_group(render_pass, 2, &item.model.material.bind_group);
+
+            state.set_vertex_buffer(render_pass, &item.mesh.vertex_buffer);
+
+            let (index_buffer, num_elements) = item.mesh.lod(item.model.lod);
+
+            state.set_index_buffer(render_pass, index_buffer, wgpu::IndexFormat::Uint16);
+
+            state.draw_indexed_instance(render_pass, 0..num_elements, ids.map_or(0, |ids| ids.id(item.mesh)));
+        });
+    }
+}
+
+/// Remembers what is bound in a render pass so that redundant calls are
+/// skipped, and counts the calls that were made.
+#[derive(Default)]
+pub struct RenderState {
+    pipeline: Option<usize>,
+    bind_groups: [Option<usize>; 8],
+    vertex_buffer: Option<usize>,
+    index_buffer: Option<usize>,
+    pub stats: RenderStats,
+}
+
+impl RenderState {
+    pub fn set_pipeline<'a>(&mut self, render_pass: &mut wgpu::RenderPass<'a>, pipeline: &'a RenderPipeline) {
+        let address = pipeline as *const RenderPipeline as usize;
+
+        if self.pipeline != Some(address) {
+            render_pass.set_pipeline(&pipeline.gpu_render_pipeline);
+            self.pipeline = Some(address);
+            self.stats.pipeline_switches += 1;
+        }
+    }
+
+    pub fn set_bind_group<'a>(&mut self, render_pass: &mut wgpu::RenderPass<'a>, index: u32, bind_group: &'a BindGroup) {
+        let address = bind_group as *const BindGroup as usize;
+        let bound = &mut self.bind_groups[index as usize];
+
+        if *bound != Some(address) {
+            render_pass.set_bind_group(index, &bind_group.gpu_bind_group, &[]);
+            *bound = Some(address);
+            self.stats.bind_group_switches += 1;
+        }
+    }
+
+    pub fn set_vertex_buffer<'a>(&mut self, render_pass: &mut wgpu::RenderPass<'a>, buffer: &'a Buffer) {
+        let address = buffer as *const Buffer as usize;
+
+        if self.vertex_buffer != Some(address) {
+            render_pass.set_vertex_buffer(0, buffer.gpu_buffer.slice(..));
+            self.vertex_buffer = Some(address);
+        }
+    }
+
+    pub fn set_index_buffer<'a>(&mut self, render_pass: &mut wgpu::RenderPass<'a>, buffer: &'a Buffer, format: wgpu::IndexFormat) {
+        let address = buffer as *const Buffer as usize;
+
+        if self.index_buffer != Some(address) {
+            render_pass.set_index_buffer(buffer.gpu_buffer.slice(..), format);
+            self.index_buffer = Some(address);
+        }
+    }
+
+    pub fn draw_indexed(&mut self, render_pass: &mut wgpu::RenderPass<'_>, indices: std::ops::Range<u32>) {
+        self.stats.triangles += indices.len() as u32 / 3;
+        render_pass.draw_indexed(indices, 0, 0..1);
+        self.stats.draw_calls += 1;
+    }
+
+    /// Draws a single instance with the given instance index, which shaders can
+    /// use as a per-draw id.
+    pub fn draw_indexed_instance(&mut self, render_pass: &mut wgpu::RenderPass<'_>, indices: std::ops::Range<u32>, instance: u32) {
+        self.stats.triangles += indices.len() as u32 / 3;
+        render_pass.draw_indexed(indices, 0, instance..instance + 1);
+        self.stats.draw_calls += 1;
+    }
+
+    pub fn draw(&mut self, render_pass: &mut wgpu::RenderPass<'_>, vertices: std::ops::Range<u32>) {
+        render_pass.draw(vertices, 0..1);
+        self.stats.draw_calls += 1;
+    }
+}
@@ -1,17 +1,50 @@
 use crate::world::*;
 
-use super::context::Context;
+use super::{context::Context, debug_view::DebugView, deferred::GBufferView, grid::GridSettings, lod::LodSettings, outline::OutlineSettings, transparency::TransparencyMode};
 
 
 pub struct Renderer {
 }
 
+/// Per-frame options that the GUI is allowed to change.
+pub struct RenderSettings {
+    pub gbuffer_view: GBufferView,
+    pub frustum_culling: bool,
+    pub transparency: TransparencyMode,
+    pub debug_view: DebugView,
+    /// Draws the world bounds of every model through `DebugDraw`.
+    pub show_bounds: bool,
+    /// Draws the range of every point light through `DebugDraw`.
+    pub show_light_volumes: bool,
+    pub grid: GridSettings,
+    pub outline: OutlineSettings,
+    pub lod: LodSettings,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            gbuffer_view: GBufferView::Lit,
+            frustum_culling: true,
+            transparency: TransparencyMode::Sorted,
+            debug_view: DebugView::Lit,
+            show_bounds: false,
+            show_light_volumes: false,
+            grid: GridSettings::default(),
+            outline: OutlineSettings::default(),
+            lod: LodSettings::default(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct TransformUniform {
     pub transform: [[f32; 4]; 4]
 } 
 
+crate::uniform_layout!(TransformUniform { transform });
+
 impl Renderer {
     pub fn new(ctx: &Context) -> Self {
         Self {
@@ -0,0 +1,233 @@
+use crate::world::{camera::PerspectiveCamera, model::Model, world::Mesh};
+
+use super::{context::{BindGroupEntry, Context}, culling::RenderStats, graphics::{WORLD_COLOR_FORMAT, WORLD_TARGET_SIZE}, pipeline::{BlendPreset, RenderPipelineBuilder}, render_queue::{RenderQueue, RenderState}};
+
+
+pub const OIT_ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const OIT_REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// How materials with `AlphaMode::Blend` are composited over the opaque scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// Alpha blending, drawn back to front by mesh centre. Exact unless meshes
+    /// intersect or overlap out of order.
+    Sorted,
+    /// Weighted blended order-independent transparency. Needs no sorting but
+    /// only approximates the order of overlapping layers.
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub const ALL: [TransparencyMode; 2] = [
+        TransparencyMode::Sorted,
+        TransparencyMode::WeightedBlended,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransparencyMode::Sorted => "Sorted",
+            TransparencyMode::WeightedBlended => "Weighted blended (OIT)",
+        }
+    }
+}
+
+/// Colour targets of the weighted blended pass, in attachment order: summed
+/// weighted colour, and revealage multiplied by `1 - alpha` of every layer.
+pub fn oit_color_targets() -> [Option<wgpu::ColorTargetState>; 2] {
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+
+    let revealage = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    };
+
+    [
+        Some(wgpu::ColorTargetState {
+            format: OIT_ACCUM_FORMAT,
+            blend: Some(wgpu::BlendState { color: additive, alpha: additive }),
+            write_mask: wgpu::ColorWrites::ALL
+        }),
+        Some(wgpu::ColorTargetState {
+            format: OIT_REVEALAGE_FORMAT,
+            blend: Some(wgpu::BlendState { color: revealage, alpha: revealage }),
+            write_mask: wgpu::ColorWrites::ALL
+        }),
+    ]
+}
+
+/// Draws blended meshes over the world colour target once opaque geometry,
+/// and with it the depth buffer, is complete. Depth is tested but not written.
+pub struct TransparencyRenderer {
+}
+
+impl TransparencyRenderer {
+    pub fn new(ctx: &mut Context) -> Self {
+        let [accum_view, revealage_view] = [
+            ("oit_accum", OIT_ACCUM_FORMAT),
+            ("oit_revealage", OIT_REVEALAGE_FORMAT),
+        ].map(|(label, format)| {
+            let texture = ctx.create_texture(
+                &format!("{}_texture", label),
+                WORLD_TARGET_SIZE,
+                1,
+                1,
+                wgpu::TextureDimension::D2,
+                format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            );
+
+            ctx.create_texture_view(&texture.gpu_texture, &format!("{}_texture_view", label))
+        });
+
+        let composite_shader = ctx.create_shader("oit_composite_shader", "shaders/oit_composite.wgsl");
+
+        let composite_pipeline = RenderPipelineBuilder::new(ctx, "oit_composite_pipeline", &composite_shader)
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Alpha)
+            .build();
+
+        let composite_bind_group_layout = composite_pipeline.bind_group_layouts[0].clone();
+
+        ctx.create_bind_group(
+            "oit_composite_bind_group",
+            &composite_bind_group_layout.gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&accum_view.gpu_texture_view)
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&revealage_view.gpu_texture_view)
+                },
+            ]
+        );
+
+        Self {
+        }
+    }
+
+    /// Renders `meshes`, which should all have blended materials, onto the
+    /// world colour target. Returns the counters of the transparent pass.
+    pub fn render(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera, mode: TransparencyMode) -> RenderStats {
+        if meshes.is_empty() {
+            return RenderStats::default();
+        }
+
+        match mode {
+            TransparencyMode::Sorted => self.render_sorted(ctx, encoder, meshes, camera),
+            TransparencyMode::WeightedBlended => self.render_weighted_blended(ctx, encoder, meshes, camera),
+        }
+    }
+
+    fn render_sorted(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera) -> RenderStats {
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let lights_bind_group = ctx.get_bind_group("lights_bind_group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("transparent_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &world_color_texture_view.gpu_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }
+            })],
+            depth_stencil_attachment: Some(Self::depth_attachment(ctx)),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        let mut state = RenderState::default();
+
+        state.set_bind_group(&mut render_pass, 0, &camera.bind_group);
+
+        state.set_bind_group(&mut render_pass, 3, lights_bind_group);
+
+        RenderQueue::transparent(meshes, camera, |model| model.transparent_pipeline.as_ref()).submit(&mut render_pass, &mut state, None);
+
+        state.stats
+    }
+
+    fn render_weighted_blended(&self, ctx: &Context, encoder: &mut wgpu::CommandEncoder, meshes: &[(&Model, &Mesh)], camera: &PerspectiveCamera) -> RenderStats {
+        let accum_view = ctx.get_texture_view("oit_accum_texture_view");
+        let revealage_view = ctx.get_texture_view("oit_revealage_texture_view");
+        let world_color_texture_view = ctx.get_texture_view("world_color_texture_view");
+        let lights_bind_group = ctx.get_bind_group("lights_bind_group");
+        let composite_bind_group = ctx.get_bind_group("oit_composite_bind_group");
+        let composite_pipeline = ctx.get_render_pipeline("oit_composite_pipeline");
+
+        let mut state = RenderState::default();
+
+        let cleared_attachment = |view, color| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(color),
+                store: wgpu::StoreOp::Store,
+            }
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("oit_accumulate_render_pass"),
+                color_attachments: &[
+                    cleared_attachment(&accum_view.gpu_texture_view, wgpu::Color::TRANSPARENT),
+                    cleared_attachment(&revealage_view.gpu_texture_view, wgpu::Color::WHITE),
+                ],
+                depth_stencil_attachment: Some(Self::depth_attachment(ctx)),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            state.set_bind_group(&mut render_pass, 0, &camera.bind_group);
+
+            state.set_bind_group(&mut render_pass, 3, lights_bind_group);
+
+            // Order does not matter here, so the state-sorted queue is used.
+            RenderQueue::opaque(meshes, camera, |model| model.oit_pipeline.as_ref()).submit(&mut render_pass, &mut state, None);
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("oit_composite_render_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &world_color_texture_view.gpu_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            render_pass.set_pipeline(&composite_pipeline.gpu_render_pipeline);
+
+            render_pass.set_bind_group(0, &composite_bind_group.gpu_bind_group, &[]);
+
+            render_pass.draw(0..3, 0..1);
+        }
+
+        state.stats
+    }
+
+    /// The opaque depth buffer, tested against but left untouched.
+    fn depth_attachment(ctx: &Context) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &ctx.get_texture_view("world_depth_texture_view").gpu_texture_view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None
+        }
+    }
+}
@@ -2,74 +2,11 @@ use bytemuck::{Pod, Zeroable};
 
 
 
-// lib.rs
-pub static CUBE_VERTICES: &[Vertex] = &[
-    // Front face
-    Vertex{position: [-1.0, -1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [0.0, 0.0]},// Bottom-left
-    Vertex{position: [1.0, -1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [1.0, 0.0]},// Bottom-right
-    Vertex{position: [1.0,  1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [1.0, 1.0]},// Top-right
-    Vertex{position: [-1.0,  1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [0.0, 1.0]},
-    // Back
-    Vertex{position: [1.0, -1.0, -1.0], normal: [0.0,  0.0, -1.0], uv: [9.0, 9.0]},// Bottom-right
-    Vertex{position: [-1.0, -1.0, -1.0], normal: [0.0,  0.0, -1.0], uv: [9.0, 9.0]},// Bottom-left
-    Vertex{position: [-1.0,  1.0, -1.0], normal: [0.0,  0.0, -1.0], uv: [9.0, 9.0]},
-    Vertex{position: [1.0,  1.0, -1.0], normal: [0.0,  0.0, -1.0], uv: [9.0, 9.0]},// Top-right
-    // Left
-    Vertex{position: [-1.0,  1.0,  1.0], normal: [-1.0,  0.0,  0.0], uv: [9.0, 9.0]},// Top-right
-    Vertex{position: [-1.0,  1.0, -1.0], normal: [-1.0,  0.0,  0.0], uv: [9.0, 9.0]},// Top-left
-    Vertex{position: [-1.0, -1.0, -1.0], normal: [-1.0,  0.0,  0.0], uv: [9.0, 9.0]},// Bottom-left
-    Vertex{position: [-1.0, -1.0,  1.0], normal: [-1.0,  0.0,  0.0], uv: [9.0, 9.0]},
-    // Right
-    Vertex{position: [1.0,  1.0, -1.0], normal: [1.0,  0.0,  0.0], uv: [9.0, 9.0]},// Top-right
-    Vertex{position: [1.0,  1.0,  1.0], normal: [1.0,  0.0,  0.0], uv: [9.0, 9.0]},// Top-left
-    Vertex{position: [1.0, -1.0,  1.0], normal: [1.0,  0.0,  0.0], uv: [9.0, 9.0]},
-    Vertex{position: [1.0, -1.0, -1.0], normal: [1.0,  0.0,  0.0], uv: [9.0, 9.0]},// Bottom-right
-    // Top
-    Vertex{position: [-1.0,  1.0,  1.0], normal: [0.0,  1.0,  0.0], uv: [9.0, 9.0]},// Top-left
-    Vertex{position: [1.0,  1.0,  1.0], normal: [0.0,  1.0,  0.0], uv: [9.0, 9.0]},// Top-right
-    Vertex{position: [1.0,  1.0, -1.0], normal: [0.0,  1.0,  0.0], uv: [9.0, 9.0]},// Bottom-right
-    Vertex{position: [-1.0,  1.0, -1.0], normal: [0.0,  1.0,  0.0], uv: [9.0, 9.0]},
-    // Bottom
-    Vertex{position: [-1.0, -1.0, -1.0], normal: [0.0, -1.0,  0.0], uv: [9.0, 9.0]},// Bottom-left
-    Vertex{position: [1.0, -1.0, -1.0], normal: [0.0, -1.0,  0.0], uv: [9.0, 9.0]},// Bottom-right
-    Vertex{position: [1.0, -1.0,  1.0], normal: [0.0, -1.0,  0.0], uv: [9.0, 9.0]},// Top-right
-    Vertex{position: [-1.0, -1.0,  1.0], normal: [0.0, -1.0,  0.0], uv: [9.0, 9.0]},// Top-left
-            
-];
-
-
-pub const CUBE_INDICES: &[u16] = &[
-    // Front face
-    0, 1, 2,
-    2, 3, 0,
-    
-    // Back face
-    4, 5, 6,
-    6, 7, 4,
-    
-    // Left face
-    8, 9, 10,
-    10, 11, 8,
-
-    // Right face
-    12, 13, 14,
-    14, 15, 12,
-    
-    // Top face
-    16, 17, 18,
-    18, 19, 16,
-    
-    // Bottom face
-    20, 21, 22,
-    22, 23, 20,
-];
-
-
 pub const SQUARE_VERTICES: &[Vertex] = &[
-    Vertex{position: [-1.0, -1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [0.0, 1.0]},// Bottom-left
-    Vertex{position: [1.0, -1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [1.0, 1.0]},// Bottom-right
-    Vertex{position: [1.0,  1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [1.0, 0.0]},// Top-right
-    Vertex{position: [-1.0,  1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [0.0, 0.0]},
+    Vertex{position: [-1.0, -1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [0.0, 1.0], tangent: [1.0, 0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4]},// Bottom-left
+    Vertex{position: [1.0, -1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [1.0, 1.0], tangent: [1.0, 0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4]},// Bottom-right
+    Vertex{position: [1.0,  1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [1.0, 0.0], tangent: [1.0, 0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4]},// Top-right
+    Vertex{position: [-1.0,  1.0,  1.0], normal: [0.0,  0.0,  1.0], uv: [0.0, 0.0], tangent: [1.0, 0.0, 0.0, 1.0], joints: [0; 4], weights: [0.0; 4]},
 ];
 
 pub const SQUARE_INDICES: &[u16] = &[
@@ -84,33 +21,52 @@ pub const SQUARE_INDICES: &[u16] = &[
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
-    pub uv: [f32; 2]
+    pub uv: [f32; 2],
+    /// Direction of increasing u, with the handedness in `w`: the bitangent
+    /// is `cross(normal, tangent.xyz) * w` and points towards decreasing v,
+    /// as in glTF.
+    pub tangent: [f32; 4],
+    /// Joints of the model's skeleton the vertex follows, with their weights.
+    /// Only read by skinned pipelines, see `Vertex::skinned_buffer_layout`.
+    pub joints: [u16; 4],
+    pub weights: [f32; 4],
+}
+
+
+/// How far a morph target moves a vertex at a weight of 1. Stored in a
+/// storage buffer rather than as vertex attributes, since meshes can have any
+/// number of targets, so each delta is padded to `vec4`s.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct MorphDelta {
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+    pub tangent: [f32; 4],
 }
 
+crate::uniform_layout!(MorphDelta { position, normal, tangent });
+
+
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4];
+
+const SKINNED_VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x4, 4 => Uint16x4, 5 => Float32x4];
 
 impl Vertex {
     pub fn buffer_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress, 
             step_mode: wgpu::VertexStepMode::Vertex, 
-            attributes: &[ 
-                wgpu::VertexAttribute {
-                    offset: 0, 
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float32x2,
-                }
-            ]
+            attributes: &VERTEX_ATTRIBUTES,
         }
     }
-}
 
+    /// `buffer_layout` plus the joints and weights, for shaders built with
+    /// `SKINNED`.
+    pub fn skinned_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress, 
+            step_mode: wgpu::VertexStepMode::Vertex, 
+            attributes: &SKINNED_VERTEX_ATTRIBUTES,
+        }
+    }
+}
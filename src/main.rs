@@ -11,6 +11,7 @@ use winit::*;
 mod program;
 mod graphics;
 mod world;
+mod assets;
 
 fn main() {
    let world_loop = EventLoop::new().expect("Failed to start event loop");
@@ -1,23 +1,34 @@
+use std::time::Instant;
 
 use winit::{
-    event::{WindowEvent, *}, event_loop::{EventLoop, EventLoopWindowTarget}, keyboard::{KeyCode, PhysicalKey}, window::Window 
+    event::{WindowEvent, *}, event_loop::{EventLoop, EventLoopWindowTarget}, keyboard::{KeyCode, ModifiersState, PhysicalKey}, window::Window 
 };
 
-use crate::{graphics::graphics::Graphics, world::{camera::{CameraBuilder, CameraType, PerspectiveCamera}, world::World}};
+use crate::{assets::server::AssetServer, graphics::{deferred::RenderPath, graphics::Graphics, picking::PickResult}, world::{camera::{CameraBuilder, CameraType, PerspectiveCamera}, world::World}};
 
 
 pub struct Program<'a> {
     world: World,
+    assets: AssetServer,
     camera: PerspectiveCamera,
     graphics: Graphics,
     window: &'a Window, 
     last_mouse_pos: Option<(f32, f32)>,
+    cursor_position: (f32, f32),
+    /// How far the mouse moved while the left button was held, to tell
+    /// clicks, which pick, from orbit drags.
+    drag_distance: f32,
+    modifiers: ModifiersState,
+    last_frame: Instant,
 }
 
+/// Mouse movement, in pixels, below which a left click selects instead of orbiting.
+const CLICK_DRAG_THRESHOLD: f32 = 4.0;
+
 impl<'a> Program<'a> {
     pub async fn new(window: &'a Window) -> Self {
         
-        let mut graphics = Graphics::new(&window).await;
+        let mut graphics = Graphics::new(window, RenderPath::default()).await;
 
         let camera = CameraBuilder::new(&mut graphics.ctx)
             .with_position((0.0, 0.0, 5.0).into())
@@ -29,16 +40,23 @@ impl<'a> Program<'a> {
         let world = World::new(&mut graphics.ctx);
 
 
+        let assets = AssetServer::new(graphics.ctx.assets.clone());
+
         let last_mouse_pos = None;
     
         env_logger::init();
 
         Self {
             world,
+            assets,
             camera,
             graphics,
             window,
-            last_mouse_pos
+            last_mouse_pos,
+            cursor_position: (0.0, 0.0),
+            drag_distance: 0.0,
+            modifiers: ModifiersState::empty(),
+            last_frame: Instant::now(),
         }
     }
 
@@ -46,12 +64,50 @@ impl<'a> Program<'a> {
         let _ = world_loop.run(move |event, control_flow| {
             self.handle_window_input(&event, &control_flow);
 
+            if let Some(pick) = self.graphics.poll_pick() {
+                self.select(pick);
+            }
+
+            self.graphics.reload_shaders(&mut self.world);
+
+            self.assets.update(&mut self.graphics.ctx);
+            self.world.update_assets(&mut self.graphics.ctx, &mut self.assets);
+
+            let now = Instant::now();
+            self.world.animate(&self.graphics.ctx, now.duration_since(self.last_frame).as_secs_f32());
+            self.last_frame = now;
+
             self.camera.update_uniforms(&self.graphics.ctx);
 
-            self.graphics.render(&self.world, &self.camera, &self.window);
+            self.graphics.render(&mut self.world, &self.camera, self.window);
         });
     }
 
+    /// Selects the picked model, replacing the selection unless shift is held,
+    /// in which case the model is toggled. Clicking empty space clears it.
+    fn select(&mut self, pick: PickResult) {
+        let selection = &mut self.world.selection;
+
+        match pick.hit {
+            Some(hit) => {
+                log::info!("Picked model {} mesh {} at {:?}", hit.model, hit.mesh, hit.position);
+
+                if !self.modifiers.shift_key() {
+                    selection.clear();
+                }
+
+                match selection.iter().position(|model| *model == hit.model) {
+                    Some(index) => {
+                        selection.remove(index);
+                    },
+                    None => selection.push(hit.model),
+                }
+            },
+            None if !self.modifiers.shift_key() => selection.clear(),
+            None => {},
+        }
+    }
+
     fn handle_window_input(&mut self, event: &Event<()>, control_flow: &EventLoopWindowTarget<()>) {
         match event {
             Event::WindowEvent { window_id, event } => {
@@ -80,12 +136,36 @@ impl<'a> Program<'a> {
                             },
                         ..
                     } => {},
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    } => {
+                        let settings = &mut self.graphics.settings;
+                        settings.debug_view = settings.debug_view.next();
+                    },
+                    WindowEvent::ModifiersChanged(modifiers) => {
+                        self.modifiers = modifiers.state();
+                    },
+                    WindowEvent::CursorMoved { position, .. } => {
+                        self.cursor_position = (position.x as f32, position.y as f32);
+                    },
                     WindowEvent::MouseInput { button, state, .. } => {
                         if *button == MouseButton::Left {
                             if *state == ElementState::Pressed {
                                 self.last_mouse_pos = Some((0.0, 0.0));
+                                self.drag_distance = 0.0;
                             } else {
                                 self.last_mouse_pos = None;
+
+                                if self.drag_distance < CLICK_DRAG_THRESHOLD {
+                                    self.graphics.pick(self.cursor_position);
+                                }
                             }
                         }
                     },
@@ -96,6 +176,7 @@ impl<'a> Program<'a> {
                 match event {
                     DeviceEvent::MouseMotion { delta } => {
                         if let Some((_, _)) = self.last_mouse_pos {
+                            self.drag_distance += (delta.0 * delta.0 + delta.1 * delta.1).sqrt() as f32;
                             self.camera.process_mouse_movement(delta.0 as f32, -delta.1 as f32);
                         }
                     },
@@ -0,0 +1,487 @@
+use cgmath::{InnerSpace, Matrix4, Quaternion, Vector3, Vector4, VectorSpace};
+
+
+/// A node's translation, rotation and scale relative to its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// `weight` of the way from `self` to `other`.
+    pub fn blend(&self, other: &NodeTransform, weight: f32) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, weight),
+            rotation: slerp(self.rotation, other.rotation, weight),
+            scale: self.scale.lerp(other.scale, weight),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SkeletonNode {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub rest: NodeTransform,
+    /// Morph target weights of the node's mesh when not animated. Empty for
+    /// nodes without morph targets.
+    pub weights: Vec<f32>,
+}
+
+/// The node hierarchy of an animated model, parents before their children.
+///
+/// Skinned vertices follow joints, each of which is a node, and so do the
+/// meshes of nodes without a skin, as the only joint of their vertices.
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub nodes: Vec<SkeletonNode>,
+    /// The node each joint follows, as indexed by `Vertex::joints`.
+    pub joints: Vec<usize>,
+    /// Takes each joint's vertices from the model's space into the joint's.
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Vec<NodeTransform> {
+        self.nodes.iter().map(|node| node.rest).collect()
+    }
+
+    pub fn rest_weights(&self) -> Vec<Vec<f32>> {
+        self.nodes.iter().map(|node| node.weights.clone()).collect()
+    }
+
+    pub fn node(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    /// Each node's transform in the model's space.
+    pub fn global_transforms(&self, pose: &[NodeTransform]) -> Vec<Matrix4<f32>> {
+        let mut globals: Vec<Matrix4<f32>> = Vec::with_capacity(self.nodes.len());
+
+        self.nodes.iter().zip(pose).for_each(|(node, transform)| {
+            let local = transform.matrix();
+
+            globals.push(match node.parent {
+                Some(parent) => globals[parent] * local,
+                None => local,
+            });
+        });
+
+        globals
+    }
+
+    /// The matrices skinning moves each joint's vertices by to put them in
+    /// `pose`. Identity for every joint in the rest pose.
+    pub fn joint_matrices(&self, pose: &[NodeTransform]) -> Vec<Matrix4<f32>> {
+        let globals = self.global_transforms(pose);
+
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(node, inverse_bind_matrix)| globals[*node] * inverse_bind_matrix)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+/// The keyframes of one property of one node.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    /// Increasing, and never empty.
+    pub times: Vec<f32>,
+    /// xyz for translation and scale, xyzw for rotation. Cubic spline
+    /// channels hold an in-tangent, the value and an out-tangent for each
+    /// keyframe, as in glTF.
+    pub values: Vec<[f32; 4]>,
+}
+
+impl Channel {
+    /// The value at `time`, which is held before the first keyframe and after
+    /// the last.
+    pub fn sample(&self, time: f32) -> [f32; 4] {
+        let value = |key: usize| match self.interpolation {
+            Interpolation::CubicSpline => Vector4::from(self.values[key * 3 + 1]),
+            _ => Vector4::from(self.values[key]),
+        };
+
+        let (previous, next, t) = keyframe_span(&self.times, time);
+
+        if previous == next {
+            return value(previous).into();
+        }
+
+        let duration = self.times[next] - self.times[previous];
+
+        let sampled = match self.interpolation {
+            Interpolation::Step => value(previous),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                return quaternion_to_array(slerp(array_to_quaternion(value(previous).into()), array_to_quaternion(value(next).into()), t));
+            },
+            Interpolation::Linear => value(previous).lerp(value(next), t),
+            Interpolation::CubicSpline => {
+                let [from, out, to, into] = hermite(t);
+
+                let out_tangent = Vector4::from(self.values[previous * 3 + 2]);
+                let in_tangent = Vector4::from(self.values[next * 3]);
+
+                value(previous) * from + out_tangent * (duration * out) + value(next) * to + in_tangent * (duration * into)
+            },
+        };
+
+        match self.property {
+            Property::Rotation if sampled.magnitude2() > 0.0 => sampled.normalize().into(),
+            _ => sampled.into(),
+        }
+    }
+}
+
+/// The keyframes of the morph target weights of one node's mesh.
+#[derive(Debug, Clone)]
+pub struct WeightChannel {
+    pub node: usize,
+    pub interpolation: Interpolation,
+    /// Increasing, and never empty.
+    pub times: Vec<f32>,
+    /// Number of morph targets, and of weights in each keyframe.
+    pub targets: usize,
+    /// The weights of each keyframe in turn. Cubic spline channels hold the
+    /// in-tangents, the weights and the out-tangents of each keyframe, as in
+    /// glTF.
+    pub values: Vec<f32>,
+}
+
+impl WeightChannel {
+    /// The weights at `time`, which are held before the first keyframe and
+    /// after the last.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let value = |key: usize, target: usize| match self.interpolation {
+            Interpolation::CubicSpline => self.values[(key * 3 + 1) * self.targets + target],
+            _ => self.values[key * self.targets + target],
+        };
+
+        let (previous, next, t) = keyframe_span(&self.times, time);
+        let duration = self.times[next] - self.times[previous];
+
+        (0..self.targets).map(|target| match self.interpolation {
+            _ if previous == next => value(previous, target),
+            Interpolation::Step => value(previous, target),
+            Interpolation::Linear => value(previous, target) + (value(next, target) - value(previous, target)) * t,
+            Interpolation::CubicSpline => {
+                let [from, out, to, into] = hermite(t);
+
+                let out_tangent = self.values[(previous * 3 + 2) * self.targets + target];
+                let in_tangent = self.values[next * 3 * self.targets + target];
+
+                value(previous, target) * from + out_tangent * (duration * out) + value(next, target) * to + in_tangent * (duration * into)
+            },
+        }).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Time of the last keyframe of any channel, in seconds.
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+    pub weight_channels: Vec<WeightChannel>,
+}
+
+impl AnimationClip {
+    /// Overwrites the properties the clip animates with their values at `time`.
+    pub fn sample(&self, time: f32, pose: &mut [NodeTransform]) {
+        self.channels.iter().for_each(|channel| {
+            let Some(transform) = pose.get_mut(channel.node) else {
+                return;
+            };
+
+            let [x, y, z, w] = channel.sample(time);
+
+            match channel.property {
+                Property::Translation => transform.translation = Vector3::new(x, y, z),
+                Property::Rotation => transform.rotation = Quaternion::new(w, x, y, z),
+                Property::Scale => transform.scale = Vector3::new(x, y, z),
+            }
+        });
+    }
+
+    /// Overwrites the morph target weights the clip animates, indexed by
+    /// node, with their values at `time`.
+    pub fn sample_weights(&self, time: f32, weights: &mut [Vec<f32>]) {
+        self.weight_channels.iter().for_each(|channel| {
+            if let Some(node_weights) = weights.get_mut(channel.node) {
+                *node_weights = channel.sample(time);
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipPlayback {
+    /// Index of the clip in the model's clips.
+    pub clip: usize,
+    pub time: f32,
+}
+
+/// Plays a model's animation clips. A second clip can be blended over the
+/// current one by a fixed weight, or faded in until it replaces it.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    pub current: Option<ClipPlayback>,
+    pub blend: Option<ClipPlayback>,
+    /// How much of `blend` is in the pose, from 0 to 1.
+    pub blend_weight: f32,
+    /// How fast `blend_weight` rises while cross-fading, per second. The
+    /// blended clip becomes the current one when it reaches 1.
+    pub fade_rate: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            current: None,
+            blend: None,
+            blend_weight: 0.0,
+            fade_rate: 0.0,
+            speed: 1.0,
+            looping: true,
+            playing: true,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    /// Plays `clip` from the start, dropping any blend.
+    pub fn play(&mut self, clip: usize) {
+        self.current = Some(ClipPlayback { clip, time: 0.0 });
+        self.blend = None;
+        self.blend_weight = 0.0;
+        self.fade_rate = 0.0;
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = true;
+    }
+
+    /// Returns the model to its rest pose.
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.blend = None;
+        self.blend_weight = 0.0;
+        self.fade_rate = 0.0;
+    }
+
+    /// Blends `clip` over the current clip by `weight`, keeping its time if it
+    /// is already blended.
+    pub fn blend(&mut self, clip: usize, weight: f32) {
+        if self.blend.is_none_or(|blend| blend.clip != clip) {
+            self.blend = Some(ClipPlayback { clip, time: 0.0 });
+        }
+
+        self.blend_weight = weight.clamp(0.0, 1.0);
+        self.fade_rate = 0.0;
+    }
+
+    /// Fades from the current clip to `clip` over `duration` seconds.
+    pub fn crossfade(&mut self, clip: usize, duration: f32) {
+        if self.current.is_none() || duration <= 0.0 {
+            self.play(clip);
+            return;
+        }
+
+        self.blend = Some(ClipPlayback { clip, time: 0.0 });
+        self.blend_weight = 0.0;
+        self.fade_rate = 1.0 / duration;
+        self.playing = true;
+    }
+
+    /// Moves the clips `delta` seconds on, scaled by `speed`.
+    pub fn advance(&mut self, clips: &[AnimationClip], delta: f32) {
+        if !self.playing {
+            return;
+        }
+
+        let step = delta * self.speed;
+        let looping = self.looping;
+
+        [self.current.as_mut(), self.blend.as_mut()].into_iter().flatten().for_each(|playback| {
+            let duration = clips.get(playback.clip).map_or(0.0, |clip| clip.duration);
+
+            playback.time += step;
+
+            playback.time = if looping && duration > 0.0 {
+                playback.time.rem_euclid(duration)
+            } else {
+                playback.time.clamp(0.0, duration)
+            };
+        });
+
+        if self.fade_rate > 0.0 {
+            self.blend_weight += self.fade_rate * delta;
+
+            if self.blend_weight >= 1.0 {
+                self.current = self.blend.take();
+                self.blend_weight = 0.0;
+                self.fade_rate = 0.0;
+            }
+        }
+    }
+
+    /// Whether the current clip has stopped at its end.
+    pub fn finished(&self, clips: &[AnimationClip]) -> bool {
+        !self.looping && self.current.is_some_and(|current| clips.get(current.clip).is_none_or(|clip| current.time >= clip.duration))
+    }
+
+    /// The skeleton's pose at the clips' current times. Nodes a clip doesn't
+    /// animate keep their rest transform.
+    pub fn pose(&self, skeleton: &Skeleton, clips: &[AnimationClip]) -> Vec<NodeTransform> {
+        let sample = |playback: &ClipPlayback| {
+            let mut pose = skeleton.rest_pose();
+
+            if let Some(clip) = clips.get(playback.clip) {
+                clip.sample(playback.time, &mut pose);
+            }
+
+            pose
+        };
+
+        let pose = self.current.as_ref().map_or_else(|| skeleton.rest_pose(), sample);
+
+        match self.blend.as_ref() {
+            Some(blend) if self.blend_weight > 0.0 => {
+                pose.iter().zip(sample(blend)).map(|(from, to)| from.blend(&to, self.blend_weight)).collect()
+            },
+            _ => pose,
+        }
+    }
+
+    /// The morph target weights of each node at the clips' current times, as
+    /// `pose` samples the transforms.
+    pub fn morph_weights(&self, skeleton: &Skeleton, clips: &[AnimationClip]) -> Vec<Vec<f32>> {
+        let sample = |playback: &ClipPlayback| {
+            let mut weights = skeleton.rest_weights();
+
+            if let Some(clip) = clips.get(playback.clip) {
+                clip.sample_weights(playback.time, &mut weights);
+            }
+
+            weights
+        };
+
+        let weights = self.current.as_ref().map_or_else(|| skeleton.rest_weights(), sample);
+
+        match self.blend.as_ref() {
+            Some(blend) if self.blend_weight > 0.0 => {
+                weights.into_iter().zip(sample(blend)).map(|(from, to)| {
+                    from.iter().zip(&to).map(|(from, to)| from + (to - from) * self.blend_weight).collect()
+                }).collect()
+            },
+            _ => weights,
+        }
+    }
+}
+
+/// Where `time` falls among increasing keyframe `times`: the keyframes either
+/// side of it and how far it is from the first to the second. Before the
+/// first keyframe and after the last, both are that keyframe.
+fn keyframe_span(times: &[f32], time: f32) -> (usize, usize, f32) {
+    let last = times.len() - 1;
+
+    if time <= times[0] {
+        return (0, 0, 0.0);
+    }
+
+    if time >= times[last] {
+        return (last, last, 0.0);
+    }
+
+    let next = times.partition_point(|key_time| *key_time <= time);
+    let previous = next - 1;
+
+    let duration = times[next] - times[previous];
+    let t = if duration > 0.0 { (time - times[previous]) / duration } else { 0.0 };
+
+    (previous, next, t)
+}
+
+/// Cubic Hermite basis at `t`: the weights of the previous value, its
+/// out-tangent, the next value and its in-tangent. Tangents are per second,
+/// so they are also scaled by the time between the keyframes.
+fn hermite(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+
+    [2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t, -2.0 * t3 + 3.0 * t2, t3 - t2]
+}
+
+/// Spherical interpolation along the shorter arc.
+fn slerp(from: Quaternion<f32>, to: Quaternion<f32>, t: f32) -> Quaternion<f32> {
+    let mut cos = from.dot(to);
+    let to = if cos < 0.0 {
+        cos = -cos;
+        -to
+    } else {
+        to
+    };
+
+    // Nearly parallel, where the sine below vanishes.
+    if cos > 0.9995 {
+        return (from * (1.0 - t) + to * t).normalize();
+    }
+
+    let angle = cos.acos();
+    let sin = angle.sin();
+
+    (from * (((1.0 - t) * angle).sin() / sin) + to * ((t * angle).sin() / sin)).normalize()
+}
+
+fn array_to_quaternion([x, y, z, w]: [f32; 4]) -> Quaternion<f32> {
+    Quaternion::new(w, x, y, z)
+}
+
+fn quaternion_to_array(quaternion: Quaternion<f32>) -> [f32; 4] {
+    [quaternion.v.x, quaternion.v.y, quaternion.v.z, quaternion.s]
+}
+
+
@@ -0,0 +1,277 @@
+use cgmath::{EuclideanSpace, InnerSpace};
+
+
+/// An axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: cgmath::Point3<f32>,
+    pub max: cgmath::Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: cgmath::Point3<f32>, max: cgmath::Point3<f32>) -> Self {
+        Self {
+            min,
+            max
+        }
+    }
+
+    /// The smallest box containing every point, or `None` if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = cgmath::Point3<f32>>) -> Option<Self> {
+        points.into_iter().fold(None, |bounds: Option<Aabb>, point| {
+            Some(match bounds {
+                Some(bounds) => bounds.including(point),
+                None => Aabb::new(point, point),
+            })
+        })
+    }
+
+    pub fn center(&self) -> cgmath::Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    /// Half the size of the box along each axis.
+    pub fn extents(&self) -> cgmath::Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn including(&self, point: cgmath::Point3<f32>) -> Self {
+        Self {
+            min: cgmath::Point3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+            max: cgmath::Point3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        self.including(other.min).including(other.max)
+    }
+
+    pub fn contains(&self, point: cgmath::Point3<f32>) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x
+            && point.y >= self.min.y && point.y <= self.max.y
+            && point.z >= self.min.z && point.z <= self.max.z
+    }
+
+    /// The box enclosing this one after transforming it by `matrix`, which
+    /// must be affine.
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> Self {
+        let center = matrix * self.center().to_homogeneous();
+        let extents = self.extents();
+
+        // Each world axis extent is the sum of the absolute contributions of the
+        // local extents, which encloses all eight rotated corners.
+        let world_extents = cgmath::Vector3::new(
+            matrix.x.x.abs() * extents.x + matrix.y.x.abs() * extents.y + matrix.z.x.abs() * extents.z,
+            matrix.x.y.abs() * extents.x + matrix.y.y.abs() * extents.y + matrix.z.y.abs() * extents.z,
+            matrix.x.z.abs() * extents.x + matrix.y.z.abs() * extents.y + matrix.z.z.abs() * extents.z,
+        );
+
+        let center = cgmath::Point3::new(center.x, center.y, center.z);
+
+        Self {
+            min: center - world_extents,
+            max: center + world_extents,
+        }
+    }
+}
+
+/// A plane `normal · p + distance = 0`, with the normal pointing to the inside.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: cgmath::Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Normalises `(a, b, c, d)` so that `signed_distance` is in world units.
+    pub fn from_coefficients(coefficients: cgmath::Vector4<f32>) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.magnitude();
+
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: cgmath::Point3<f32>) -> f32 {
+        self.normal.dot(point.to_vec()) + self.distance
+    }
+}
+
+/// The six planes bounding what a camera can see.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far, in that order.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a combined view-projection matrix, which works
+    /// for perspective and orthographic projections alike.
+    ///
+    /// The near plane assumes OpenGL-style clip space (`-w <= z`), as produced
+    /// by `cgmath::perspective` and `cgmath::ortho`. For a `0..1` depth range
+    /// that plane lies slightly behind the real one, so culling stays conservative.
+    pub fn from_view_projection(view_projection: &cgmath::Matrix4<f32>) -> Self {
+        let row = |index: usize| cgmath::Vector4::new(
+            view_projection.x[index],
+            view_projection.y[index],
+            view_projection.z[index],
+            view_projection.w[index],
+        );
+
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z].map(Plane::from_coefficients),
+        }
+    }
+
+    /// False only if the box is entirely outside one of the planes. Boxes near
+    /// a frustum corner can be reported as intersecting when they are not,
+    /// which is the usual trade-off for culling.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let extents = aabb.extents();
+
+        self.planes.iter().all(|plane| {
+            let radius = extents.x * plane.normal.x.abs() + extents.y * plane.normal.y.abs() + extents.z * plane.normal.z.abs();
+
+            plane.signed_distance(center) >= -radius
+        })
+    }
+
+    pub fn contains_point(&self, point: cgmath::Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: usize = 0;
+    const RIGHT: usize = 1;
+    const BOTTOM: usize = 2;
+    const TOP: usize = 3;
+    const NEAR: usize = 4;
+    const FAR: usize = 5;
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> Aabb {
+        Aabb::new(min.into(), max.into())
+    }
+
+    fn view() -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(cgmath::Point3::new(0.0, 0.0, 5.0), cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Vector3::unit_y())
+    }
+
+    /// Sees `|x| < 5` and `|y| < 5` through the origin, from 0.1 to 100 units in front of the eye at `z = 5`.
+    fn perspective() -> Frustum {
+        Frustum::from_view_projection(&(cgmath::perspective(cgmath::Deg(90.0), 1.0, 0.1, 100.0) * view()))
+    }
+
+    /// Sees `|x| < 2`, `|y| < 1` and `-5 < z < 4.9`.
+    fn orthographic() -> Frustum {
+        Frustum::from_view_projection(&(cgmath::ortho(-2.0, 2.0, -1.0, 1.0, 0.1, 10.0) * view()))
+    }
+
+    fn corners(aabb: &Aabb) -> impl Iterator<Item = cgmath::Point3<f32>> + '_ {
+        (0..8).map(move |corner| cgmath::Point3::new(
+            if corner & 1 == 0 { aabb.min.x } else { aabb.max.x },
+            if corner & 2 == 0 { aabb.min.y } else { aabb.max.y },
+            if corner & 4 == 0 { aabb.min.z } else { aabb.max.z },
+        ))
+    }
+
+    /// The planes that have every corner of the box on their outside.
+    fn separating_planes(frustum: &Frustum, aabb: &Aabb) -> Vec<usize> {
+        (0..6)
+            .filter(|&index| corners(aabb).all(|corner| frustum.planes[index].signed_distance(corner) < 0.0))
+            .collect()
+    }
+
+    fn assert_outside(frustum: &Frustum, aabb: Aabb, plane: usize) {
+        assert!(!frustum.intersects_aabb(&aabb), "{:?} should be culled", aabb);
+        assert!(separating_planes(frustum, &aabb).contains(&plane), "{:?} should be outside plane {}", aabb, plane);
+    }
+
+    #[test]
+    fn planes_are_normalised() {
+        for frustum in [perspective(), orthographic()] {
+            for plane in frustum.planes {
+                assert!((plane.normal.magnitude() - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn perspective_inside() {
+        let frustum = perspective();
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.0, -1.0], [1.0, 1.0, 1.0])));
+        assert!(frustum.intersects_aabb(&aabb([-0.1, -0.1, -90.0], [0.1, 0.1, -80.0])));
+        assert!(frustum.contains_point(cgmath::Point3::new(0.0, 0.0, 0.0)));
+        assert!(frustum.contains_point(cgmath::Point3::new(4.9, -4.9, 0.0)));
+    }
+
+    #[test]
+    fn perspective_outside_each_plane() {
+        let frustum = perspective();
+        assert_outside(&frustum, aabb([-20.0, -1.0, -1.0], [-10.0, 1.0, 1.0]), LEFT);
+        assert_outside(&frustum, aabb([10.0, -1.0, -1.0], [20.0, 1.0, 1.0]), RIGHT);
+        assert_outside(&frustum, aabb([-1.0, -20.0, -1.0], [1.0, -10.0, 1.0]), BOTTOM);
+        assert_outside(&frustum, aabb([-1.0, 10.0, -1.0], [1.0, 20.0, 1.0]), TOP);
+        assert_outside(&frustum, aabb([-0.01, -0.01, 4.95], [0.01, 0.01, 4.99]), NEAR);
+        assert_outside(&frustum, aabb([-1.0, -1.0, -200.0], [1.0, 1.0, -150.0]), FAR);
+    }
+
+    #[test]
+    fn perspective_straddling_planes() {
+        let frustum = perspective();
+        assert!(frustum.intersects_aabb(&aabb([-6.0, -1.0, -1.0], [-4.0, 1.0, 1.0])));
+        assert!(frustum.intersects_aabb(&aabb([-1.0, 4.0, -1.0], [1.0, 6.0, 1.0])));
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.0, 4.0], [1.0, 1.0, 6.0])));
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.0, -100.0], [1.0, 1.0, -90.0])));
+        assert!(!frustum.contains_point(cgmath::Point3::new(-6.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn perspective_behind_camera() {
+        let frustum = perspective();
+        let behind = aabb([-1.0, -1.0, 6.0], [1.0, 1.0, 8.0]);
+        assert_outside(&frustum, behind, NEAR);
+        assert!(!frustum.contains_point(cgmath::Point3::new(0.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn orthographic_inside() {
+        let frustum = orthographic();
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -0.5, -1.0], [1.0, 0.5, 1.0])));
+        assert!(frustum.contains_point(cgmath::Point3::new(1.9, 0.9, -4.9)));
+    }
+
+    #[test]
+    fn orthographic_outside_each_plane() {
+        let frustum = orthographic();
+        assert_outside(&frustum, aabb([-4.0, -0.5, -1.0], [-2.5, 0.5, 1.0]), LEFT);
+        assert_outside(&frustum, aabb([2.5, -0.5, -1.0], [4.0, 0.5, 1.0]), RIGHT);
+        assert_outside(&frustum, aabb([-1.0, -3.0, -1.0], [1.0, -1.5, 1.0]), BOTTOM);
+        assert_outside(&frustum, aabb([-1.0, 1.5, -1.0], [1.0, 3.0, 1.0]), TOP);
+        assert_outside(&frustum, aabb([-1.0, -0.5, 4.95], [1.0, 0.5, 4.99]), NEAR);
+        assert_outside(&frustum, aabb([-1.0, -0.5, -20.0], [1.0, 0.5, -6.0]), FAR);
+    }
+
+    #[test]
+    fn orthographic_straddling_planes() {
+        let frustum = orthographic();
+        assert!(frustum.intersects_aabb(&aabb([1.5, -0.5, -1.0], [2.5, 0.5, 1.0])));
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -1.5, -1.0], [1.0, -0.5, 1.0])));
+        assert!(frustum.intersects_aabb(&aabb([-1.0, -0.5, -6.0], [1.0, 0.5, -4.0])));
+    }
+
+    #[test]
+    fn orthographic_behind_camera() {
+        let frustum = orthographic();
+        assert_outside(&frustum, aabb([-1.0, -0.5, 6.0], [1.0, 0.5, 7.0]), NEAR);
+    }
+}
@@ -1,15 +1,20 @@
 use std::rc::Rc;
 
-use cgmath::{Angle, EuclideanSpace};
+use cgmath::{Angle, EuclideanSpace, InnerSpace, SquareMatrix};
 use winit::event::MouseScrollDelta;
 
-use crate::graphics::{context::{BindGroup, BindGroupEntry, BindGroupLayoutEntry, Context}, renderer::TransformUniform};
+use crate::{world::{bounds::Frustum, raycast::Ray}, graphics::{context::{BindGroup, BindGroupEntry, BindGroupLayoutEntry, Context}, renderer::TransformUniform}};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CameraType {
     Perspective = 0,
+    /// Parallel projection sized so that the target appears at the same scale
+    /// as it would with the perspective projection at the current radius.
+    Orthographic = 1,
 }
 
 pub struct PerspectiveCamera {
+    camera_type: CameraType,
     position: cgmath::Point3<f32>,
     target: cgmath::Point3<f32>,
     up: cgmath::Vector3<f32>,
@@ -33,7 +38,41 @@ impl PerspectiveCamera {
     pub fn update_transform_matrices(&mut self) {
         self.view_matrix = cgmath::Matrix4::look_at_rh(self.position, self.target, self.up);
         
-        self.projection_matrix = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.near, self.far);
+        self.projection_matrix = projection_matrix(self.camera_type, self.fovy, self.aspect, self.near, self.far, self.radius);
+    }
+
+    pub fn camera_type(&self) -> CameraType {
+        self.camera_type
+    }
+
+    pub fn set_camera_type(&mut self, camera_type: CameraType) {
+        self.camera_type = camera_type;
+
+        self.update_transform_matrices();
+    }
+
+    /// The ray through a point of a viewport of `viewport_size` pixels, with
+    /// the origin at the top left, starting on the near plane.
+    pub fn screen_ray(&self, point: (f32, f32), viewport_size: (f32, f32)) -> Ray {
+        screen_ray(&self.view_projection_matrix(), point, viewport_size)
+    }
+
+    /// The fraction of the viewport height a sphere covers, for picking
+    /// levels of detail. Spheres around the camera cover all of it.
+    pub fn projected_size(&self, center: cgmath::Point3<f32>, radius: f32) -> f32 {
+        let half_fovy = cgmath::Deg(self.fovy * 0.5).tan();
+
+        let half_height = match self.camera_type {
+            CameraType::Perspective => (center - self.position).magnitude() * half_fovy,
+            CameraType::Orthographic => self.radius * half_fovy,
+        };
+
+        if half_height <= radius { 1.0 } else { radius / half_height }
+    }
+
+    /// The planes of the current view-projection, for culling.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(&self.view_projection_matrix())
     }
 
     pub fn translate(&mut self, translation: cgmath::Vector3<f32>) {
@@ -52,6 +91,18 @@ impl PerspectiveCamera {
         self.update_transform_matrices();
     }
 
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    pub fn position(&self) -> cgmath::Point3<f32> {
+        self.position
+    }
+
     pub fn view_matrix(&self) -> cgmath::Matrix4<f32> {
         cgmath::Matrix4::look_at_rh(self.position, self.target, self.up)
     }
@@ -97,6 +148,33 @@ impl PerspectiveCamera {
     }
 }
 
+fn screen_ray(view_projection: &cgmath::Matrix4<f32>, point: (f32, f32), viewport_size: (f32, f32)) -> Ray {
+    let ndc_x = point.0 / viewport_size.0 * 2.0 - 1.0;
+    let ndc_y = 1.0 - point.1 / viewport_size.1 * 2.0;
+
+    let inverse_view_projection = view_projection.invert().unwrap_or(cgmath::Matrix4::identity());
+
+    // OpenGL-style clip space, so the near plane is at -1.
+    let unproject = |z: f32| cgmath::Point3::from_homogeneous(inverse_view_projection * cgmath::Vector4::new(ndc_x, ndc_y, z, 1.0));
+
+    let near = unproject(-1.0);
+    let far = unproject(1.0);
+
+    Ray::new(near, far - near)
+}
+
+fn projection_matrix(camera_type: CameraType, fovy: f32, aspect: f32, near: f32, far: f32, radius: f32) -> cgmath::Matrix4<f32> {
+    match camera_type {
+        CameraType::Perspective => cgmath::perspective(cgmath::Deg(fovy), aspect, near, far),
+        CameraType::Orthographic => {
+            let half_height = radius * cgmath::Deg(fovy * 0.5).tan();
+            let half_width = half_height * aspect;
+
+            cgmath::ortho(-half_width, half_width, -half_height, half_height, near, far)
+        },
+    }
+}
+
 pub struct CameraBuilder<'a> {
     ctx: &'a mut Context,
     camera_type: Option<CameraType>,
@@ -171,7 +249,9 @@ impl<'a> CameraBuilder<'a> {
             _ => {
                 let view_matrix = cgmath::Matrix4::look_at_rh(self.position.unwrap(), self.target.unwrap(), self.up.unwrap());
         
-                let projection_matrix = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.near, self.far);
+                let camera_type = self.camera_type.unwrap_or(CameraType::Perspective);
+
+                let projection_matrix = projection_matrix(camera_type, self.fovy, self.aspect, self.near, self.far, self.radius);
 
                 let device = &self.ctx.device;
 
@@ -208,6 +288,7 @@ impl<'a> CameraBuilder<'a> {
                 );   
         
                 PerspectiveCamera {
+                    camera_type,
                     position: self.position.unwrap(),
                     target: self.target.unwrap(),
                     up: self.up.unwrap(),
@@ -229,3 +310,82 @@ impl<'a> CameraBuilder<'a> {
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: (f32, f32) = (800.0, 600.0);
+
+    fn view_projection(camera_type: CameraType) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_at_rh(cgmath::Point3::new(3.0, 4.0, 5.0), cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Vector3::unit_y());
+
+        projection_matrix(camera_type, 45.0, VIEWPORT.0 / VIEWPORT.1, 0.1, 100.0, 50.0f32.sqrt()) * view
+    }
+
+    fn project(view_projection: &cgmath::Matrix4<f32>, point: cgmath::Point3<f32>) -> cgmath::Point3<f32> {
+        cgmath::Point3::from_homogeneous(view_projection * point.to_homogeneous())
+    }
+
+    /// Screen points and the NDC they should map to.
+    const POINTS: [((f32, f32), (f32, f32)); 5] = [
+        ((400.0, 300.0), (0.0, 0.0)),
+        ((0.0, 0.0), (-1.0, 1.0)),
+        ((800.0, 0.0), (1.0, 1.0)),
+        ((0.0, 600.0), (-1.0, -1.0)),
+        ((800.0, 600.0), (1.0, -1.0)),
+    ];
+
+    fn assert_round_trip(camera_type: CameraType) {
+        let view_projection = view_projection(camera_type);
+
+        for (point, (ndc_x, ndc_y)) in POINTS {
+            let ray = screen_ray(&view_projection, point, VIEWPORT);
+
+            let near = project(&view_projection, ray.origin);
+            assert!((near.z + 1.0).abs() < 1e-3, "{:?} starts at depth {}, not on the near plane", point, near.z);
+
+            for distance in [0.0, 1.0, 10.0, 50.0] {
+                let projected = project(&view_projection, ray.at(distance));
+
+                assert!(
+                    (projected.x - ndc_x).abs() < 1e-3 && (projected.y - ndc_y).abs() < 1e-3,
+                    "{:?} at {} projects to {:?} instead of ({}, {})", point, distance, projected, ndc_x, ndc_y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn perspective_screen_ray_round_trips() {
+        assert_round_trip(CameraType::Perspective);
+    }
+
+    #[test]
+    fn orthographic_screen_ray_round_trips() {
+        assert_round_trip(CameraType::Orthographic);
+    }
+
+    #[test]
+    fn centre_ray_looks_at_the_target() {
+        let forward = -cgmath::Vector3::new(3.0, 4.0, 5.0).normalize();
+
+        for camera_type in [CameraType::Perspective, CameraType::Orthographic] {
+            let ray = screen_ray(&view_projection(camera_type), (400.0, 300.0), VIEWPORT);
+
+            assert!((ray.direction - forward).magnitude() < 1e-4, "{:?}: {:?}", camera_type, ray.direction);
+        }
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let view_projection = view_projection(CameraType::Orthographic);
+        let centre = screen_ray(&view_projection, (400.0, 300.0), VIEWPORT);
+
+        for (point, _) in POINTS {
+            let ray = screen_ray(&view_projection, point, VIEWPORT);
+
+            assert!((ray.direction - centre.direction).magnitude() < 1e-4, "{:?}: {:?}", point, ray.direction);
+        }
+    }
+}
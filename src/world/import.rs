@@ -0,0 +1,655 @@
+use std::{collections::HashMap, path::Path, rc::Rc};
+
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+use gltf::animation::util::ReadOutputs;
+
+use crate::{assets::source::AssetResolver, graphics::{context::Context, vertex_input::{MorphDelta, Vertex}}};
+
+use super::{animation::{AnimationClip, Channel, Interpolation, NodeTransform, Property, Skeleton, SkeletonNode, WeightChannel}, material::{AlphaMode, Material, MaterialBuilder}, obj, stl, world::{vertex_index, MeshData, MorphTargets, MAX_MORPH_TARGETS}};
+
+
+/// Material factors of an imported model, and the asset path of its base
+/// color texture. Other textures aren't imported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedMaterial {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub alpha_mode: AlphaMode,
+    pub base_color_texture: Option<String>,
+}
+
+impl ImportedMaterial {
+    pub fn build(&self, ctx: &mut Context, label: &str) -> Rc<Material> {
+        let mut builder = MaterialBuilder::new(ctx, label)
+            .with_base_color(self.base_color)
+            .with_metallic(self.metallic)
+            .with_roughness(self.roughness)
+            .with_alpha_mode(self.alpha_mode);
+
+        if let Some(texture) = &self.base_color_texture {
+            builder = builder.with_base_color_texture(texture);
+        }
+
+        builder.build()
+    }
+}
+
+/// Meshes read from a model file, in the model's space. Attributes the file
+/// lacks are left zeroed, for `mesh_processing::process` to fill in.
+#[derive(Debug, Clone, Default)]
+pub struct ImportedModel {
+    pub meshes: Vec<MeshData>,
+    pub material: Option<ImportedMaterial>,
+    /// The nodes the meshes' joints follow, for animated models.
+    pub skeleton: Option<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+    /// Asset paths of every file the model was read from, starting with the
+    /// model file itself.
+    pub dependencies: Vec<String>,
+}
+
+/// Imports the file at `path`, picking the format from its extension.
+pub fn import(assets: &AssetResolver, path: &str) -> Result<ImportedModel, String> {
+    let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_ascii_lowercase();
+
+    let mut model = match extension.as_str() {
+        "gltf" | "glb" => import_gltf(assets, path),
+        "obj" => obj::import_obj(assets, path),
+        "stl" => stl::import_stl(assets, path),
+        _ => Err(format!("Unsupported model format: {}", path)),
+    }?;
+
+    model.dependencies.insert(0, path.to_string());
+
+    Ok(model)
+}
+
+/// Every triangle primitive of the default scene, or of the first scene if
+/// there is no default. Buffers are resolved relative to the file.
+///
+/// Node transforms are baked in, unless the file has skins or animations. Its
+/// nodes then become the model's skeleton, skinned meshes follow their joints,
+/// and the other meshes follow their node as its only joint. Meshes with
+/// morph targets take their weights from their node, which animations of the
+/// weights then drive.
+pub fn import_gltf(assets: &AssetResolver, path: &str) -> Result<ImportedModel, String> {
+    let bytes = assets.read(path)?;
+
+    let gltf = gltf::Gltf::from_slice(&bytes).map_err(|error| format!("Failed to parse {}: {}", path, error))?;
+
+    let base = assets.file_path(path).and_then(|file| file.parent().map(Path::to_path_buf));
+    let buffers = gltf::import_buffers(&gltf.document, base.as_deref(), gltf.blob.clone())
+        .map_err(|error| format!("Failed to load the buffers of {}: {}", path, error))?;
+
+    let scene = gltf.document.default_scene()
+        .or_else(|| gltf.document.scenes().next())
+        .ok_or_else(|| format!("{} has no scene", path))?;
+
+    let mut model = ImportedModel::default();
+
+    model.dependencies.extend(gltf.document.buffers().filter_map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => Some(relative_path(path, uri)),
+        _ => None,
+    }));
+
+    let buffer_data = |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| &data.0[..]);
+
+    let nodes = scene_nodes(&scene);
+
+    let animated = gltf.document.skins().next().is_some() || gltf.document.animations().next().is_some();
+
+    let mut rig = animated.then(|| Rig::new(&nodes));
+
+    for (node, _, transform) in &nodes {
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("Skipping a {:?} primitive of {}: only triangles are supported", primitive.mode(), path);
+                continue;
+            }
+
+            let reader = primitive.reader(buffer_data);
+
+            let skinning = match rig.as_mut() {
+                None => Skinning::Static,
+                // Skinned meshes ignore their node's transform.
+                Some(rig) => match node.skin().filter(|_| reader.read_joints(0).is_some() && reader.read_weights(0).is_some()) {
+                    Some(skin) => Skinning::Joints(rig.skin_joints(skin, buffer_data).map_err(|error| format!("{}: {}", path, error))?),
+                    None => Skinning::Rigid(rig.node_joint(node.index(), transform).map_err(|error| format!("{}: {}", path, error))?),
+                },
+            };
+
+            let transform = match skinning {
+                Skinning::Joints(_) => cgmath::Matrix4::identity(),
+                _ => *transform,
+            };
+
+            let mut data = read_primitive(&reader, &transform, skinning).map_err(|error| format!("{}: {}", path, error))?;
+
+            if data.morph.targets.len() > MAX_MORPH_TARGETS {
+                log::warn!("Dropping all but {} of the {} morph targets of a primitive of {}", MAX_MORPH_TARGETS, data.morph.targets.len(), path);
+                data.morph.targets.truncate(MAX_MORPH_TARGETS);
+            }
+
+            if !data.morph.targets.is_empty() {
+                data.morph.weights = morph_weights(node, data.morph.targets.len());
+                data.morph.node = rig.as_ref().and_then(|rig| rig.node_indices.get(&node.index()).copied());
+            }
+
+            model.meshes.push(data);
+
+            if model.material.is_none() {
+                let material = primitive.material();
+                let pbr = material.pbr_metallic_roughness();
+
+                model.material = Some(ImportedMaterial {
+                    base_color: pbr.base_color_factor(),
+                    metallic: pbr.metallic_factor(),
+                    roughness: pbr.roughness_factor(),
+                    alpha_mode: AlphaMode::from_gltf(material.alpha_mode(), material.alpha_cutoff()),
+                    base_color_texture: pbr.base_color_texture().and_then(|info| match info.texture().source().source() {
+                        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(relative_path(path, uri)),
+                        _ => {
+                            log::warn!("Ignoring the base color texture of {}: only images in files of their own are supported", path);
+                            None
+                        },
+                    }),
+                });
+            }
+        }
+    }
+
+    if let Some(rig) = rig {
+        model.animations = gltf.document.animations().enumerate()
+            .filter_map(|(index, animation)| read_animation(&animation, index, &rig.node_indices, buffer_data, path))
+            .collect();
+
+        model.skeleton = Some(rig.skeleton);
+    }
+
+    Ok(model)
+}
+
+/// The nodes of a scene, parents before their children, with the index of
+/// their parent in the list and their transform in the scene.
+fn scene_nodes<'a>(scene: &gltf::Scene<'a>) -> Vec<(gltf::Node<'a>, Option<usize>, cgmath::Matrix4<f32>)> {
+    let mut nodes = Vec::new();
+
+    let mut pending: Vec<(gltf::Node, Option<usize>, cgmath::Matrix4<f32>)> = scene.nodes().map(|node| (node, None, cgmath::Matrix4::identity())).collect();
+
+    while let Some((node, parent, parent_transform)) = pending.pop() {
+        let transform = parent_transform * cgmath::Matrix4::from(node.transform().matrix());
+
+        let index = nodes.len();
+
+        pending.extend(node.children().map(|child| (child, Some(index), transform)));
+
+        nodes.push((node, parent, transform));
+    }
+
+    nodes
+}
+
+/// The weights of the morph targets of a node's mesh, those of the node if it
+/// has any, or else those of the mesh, padded with zeros to `targets`.
+fn morph_weights(node: &gltf::Node, targets: usize) -> Vec<f32> {
+    let mut weights = node.weights()
+        .map(<[f32]>::to_vec)
+        .or_else(|| node.mesh().and_then(|mesh| mesh.weights().map(<[f32]>::to_vec)))
+        .unwrap_or_default();
+
+    weights.resize(targets, 0.0);
+    weights
+}
+
+/// What the vertices of a primitive follow.
+#[derive(Debug, Clone, Copy)]
+enum Skinning {
+    /// Nothing, they are baked into the model's space.
+    Static,
+    /// The joint of their node.
+    Rigid(u16),
+    /// Their own joints, those of a skin starting at this joint.
+    Joints(u16),
+}
+
+/// Builds a skeleton from the nodes of a scene, adding joints as meshes need
+/// them.
+struct Rig {
+    skeleton: Skeleton,
+    /// Skeleton node of each glTF node in the scene.
+    node_indices: HashMap<usize, usize>,
+    /// First joint of each skin added so far.
+    skins: HashMap<usize, u16>,
+    /// Joint of each node whose meshes follow it.
+    node_joints: HashMap<usize, u16>,
+}
+
+impl Rig {
+    fn new(nodes: &[(gltf::Node, Option<usize>, cgmath::Matrix4<f32>)]) -> Self {
+        let skeleton = Skeleton {
+            nodes: nodes.iter().map(|(node, parent, _)| {
+                let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+
+                let targets = node.mesh()
+                    .and_then(|mesh| mesh.primitives().map(|primitive| primitive.morph_targets().count()).max())
+                    .unwrap_or(0)
+                    .min(MAX_MORPH_TARGETS);
+
+                SkeletonNode {
+                    name: node.name().map_or_else(|| format!("node {}", node.index()), str::to_string),
+                    parent: *parent,
+                    rest: NodeTransform {
+                        translation: translation.into(),
+                        rotation: cgmath::Quaternion::new(w, x, y, z),
+                        scale: scale.into(),
+                    },
+                    weights: morph_weights(node, targets),
+                }
+            }).collect(),
+            joints: Vec::new(),
+            inverse_bind_matrices: Vec::new(),
+        };
+
+        Self {
+            skeleton,
+            node_indices: nodes.iter().enumerate().map(|(index, (node, _, _))| (node.index(), index)).collect(),
+            skins: HashMap::new(),
+            node_joints: HashMap::new(),
+        }
+    }
+
+    fn add_joint(&mut self, node: usize, inverse_bind_matrix: cgmath::Matrix4<f32>) -> Result<u16, String> {
+        let joint = u16::try_from(self.skeleton.joints.len()).map_err(|_| "The skeleton has more than 65536 joints".to_string())?;
+
+        let node = *self.node_indices.get(&node).ok_or_else(|| format!("Joint node {} is not in the scene", node))?;
+
+        self.skeleton.joints.push(node);
+        self.skeleton.inverse_bind_matrices.push(inverse_bind_matrix);
+
+        Ok(joint)
+    }
+
+    /// The first joint of `skin`, adding its joints the first time.
+    fn skin_joints<'s, F>(&mut self, skin: gltf::Skin, buffer_data: F) -> Result<u16, String>
+    where
+        F: Clone + for<'a> Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+    {
+        if let Some(first) = self.skins.get(&skin.index()) {
+            return Ok(*first);
+        }
+
+        let inverse_bind_matrices: Vec<cgmath::Matrix4<f32>> = skin.reader(buffer_data)
+            .read_inverse_bind_matrices()
+            .map_or_else(Vec::new, |matrices| matrices.map(cgmath::Matrix4::from).collect());
+
+        let first = self.skeleton.joints.len() as u16;
+
+        for (index, joint) in skin.joints().enumerate() {
+            self.add_joint(joint.index(), inverse_bind_matrices.get(index).copied().unwrap_or(cgmath::Matrix4::identity()))?;
+        }
+
+        self.skins.insert(skin.index(), first);
+
+        Ok(first)
+    }
+
+    /// The joint of a node, adding it the first time. Its vertices are baked
+    /// into the scene with the node's rest transform, which the inverse bind
+    /// matrix undoes.
+    fn node_joint(&mut self, node: usize, transform: &cgmath::Matrix4<f32>) -> Result<u16, String> {
+        if let Some(joint) = self.node_joints.get(&node) {
+            return Ok(*joint);
+        }
+
+        let joint = self.add_joint(node, transform.invert().unwrap_or(cgmath::Matrix4::identity()))?;
+
+        self.node_joints.insert(node, joint);
+
+        Ok(joint)
+    }
+}
+
+/// The translation, rotation, scale and morph target weight channels of an
+/// animation that target nodes of the scene. Returns `None` if there are none.
+fn read_animation<'s, F>(animation: &gltf::Animation, index: usize, node_indices: &HashMap<usize, usize>, buffer_data: F, path: &str) -> Option<AnimationClip>
+where
+    F: Clone + for<'a> Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let name = animation.name().map_or_else(|| format!("animation {}", index), str::to_string);
+
+    let mut channels: Vec<Channel> = Vec::new();
+    let mut weight_channels: Vec<WeightChannel> = Vec::new();
+
+    for channel in animation.channels() {
+        let Some(node) = node_indices.get(&channel.target().node().index()).copied() else {
+            continue;
+        };
+
+        let reader = channel.reader(buffer_data.clone());
+
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+
+        let times: Vec<f32> = inputs.collect();
+
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+
+        let keys = times.len() * if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+
+        let (property, values): (Property, Vec<[f32; 4]>) = match outputs {
+            ReadOutputs::Translations(values) => (Property::Translation, values.map(|[x, y, z]| [x, y, z, 0.0]).collect()),
+            ReadOutputs::Rotations(values) => (Property::Rotation, values.into_f32().collect()),
+            ReadOutputs::Scales(values) => (Property::Scale, values.map(|[x, y, z]| [x, y, z, 0.0]).collect()),
+            ReadOutputs::MorphTargetWeights(values) => {
+                let values: Vec<f32> = values.into_f32().collect();
+
+                if keys == 0 || values.is_empty() || !values.len().is_multiple_of(keys) {
+                    log::warn!("Skipping a weight channel of {} in {}: {} keyframes but {} weights", name, path, times.len(), values.len());
+                    continue;
+                }
+
+                weight_channels.push(WeightChannel {
+                    node,
+                    interpolation,
+                    targets: values.len() / keys,
+                    times,
+                    values,
+                });
+
+                continue;
+            },
+        };
+
+        if times.is_empty() || values.len() != keys {
+            log::warn!("Skipping a channel of {} in {}: {} keyframes but {} values", name, path, times.len(), values.len());
+            continue;
+        }
+
+        channels.push(Channel {
+            node,
+            property,
+            interpolation,
+            times,
+            values,
+        });
+    }
+
+    if channels.is_empty() && weight_channels.is_empty() {
+        return None;
+    }
+
+    let duration = channels.iter().map(|channel| &channel.times)
+        .chain(weight_channels.iter().map(|channel| &channel.times))
+        .filter_map(|times| times.last())
+        .fold(0.0, |duration, time| f32::max(duration, *time));
+
+    Some(AnimationClip {
+        name,
+        duration,
+        channels,
+        weight_channels,
+    })
+}
+
+/// The asset path of `reference`, a path relative to the file at `path`.
+pub fn relative_path(path: &str, reference: &str) -> String {
+    Path::new(path).parent().unwrap_or(Path::new("")).join(reference).to_string_lossy().into_owned()
+}
+
+fn read_primitive<'a, 's, F>(reader: &gltf::mesh::Reader<'a, 's, F>, transform: &cgmath::Matrix4<f32>, skinning: Skinning) -> Result<MeshData, String>
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let positions: Vec<[f32; 3]> = reader.read_positions().ok_or("A primitive has no positions")?.collect();
+
+    if positions.len() > u16::MAX as usize + 1 {
+        return Err(format!("A primitive has {} vertices, more than 16-bit indices can address", positions.len()));
+    }
+
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+    let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect());
+    let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(Iterator::collect);
+
+    let (joints, weights): (Vec<[u16; 4]>, Vec<[f32; 4]>) = match skinning {
+        Skinning::Static => (Vec::new(), Vec::new()),
+        Skinning::Rigid(joint) => (vec![[joint, 0, 0, 0]; positions.len()], vec![[1.0, 0.0, 0.0, 0.0]; positions.len()]),
+        Skinning::Joints(first) => {
+            let joints = reader.read_joints(0).ok_or("A skinned primitive has no joints")?.into_u16().map(|joints| {
+                let mut skeleton_joints = [0; 4];
+
+                for (skeleton_joint, joint) in skeleton_joints.iter_mut().zip(joints) {
+                    *skeleton_joint = joint.checked_add(first).ok_or("The skeleton has more than 65536 joints")?;
+                }
+
+                Ok(skeleton_joints)
+            }).collect::<Result<_, &str>>()?;
+
+            // Exporters don't always normalise the weights.
+            let weights = reader.read_weights(0).ok_or("A skinned primitive has no weights")?.into_f32().map(|weights| {
+                let total: f32 = weights.iter().sum();
+
+                if total > 0.0 { weights.map(|weight| weight / total) } else { [1.0, 0.0, 0.0, 0.0] }
+            }).collect();
+
+            (joints, weights)
+        },
+    };
+
+    if !joints.is_empty() && (joints.len() != positions.len() || weights.len() != positions.len()) {
+        return Err("A primitive has a different number of joints or weights than positions".to_string());
+    }
+
+    // Normals take the inverse transpose, so they stay perpendicular under
+    // non-uniform scale, and mirroring flips the tangent's handedness.
+    let linear = cgmath::Matrix3::new(
+        transform.x.x, transform.x.y, transform.x.z,
+        transform.y.x, transform.y.y, transform.y.z,
+        transform.z.x, transform.z.y, transform.z.z,
+    );
+    let normal_matrix = linear.invert().map(|inverse| inverse.transpose()).unwrap_or(linear);
+    let handedness = linear.determinant().signum();
+
+    let normalized = |vector: cgmath::Vector3<f32>| if vector.magnitude2() > 0.0 { vector.normalize() } else { vector };
+
+    let vertices = positions.iter().enumerate().map(|(index, position)| {
+        let position = transform * cgmath::Vector3::from(*position).extend(1.0);
+
+        let normal = normals.as_ref().map_or([0.0; 3], |normals| normalized(normal_matrix * cgmath::Vector3::from(normals[index])).into());
+
+        let tangent = tangents.as_ref().map_or([0.0; 4], |tangents| {
+            let [x, y, z, w] = tangents[index];
+
+            normalized(linear * cgmath::Vector3::new(x, y, z)).extend(w * handedness).into()
+        });
+
+        Vertex {
+            position: position.truncate().into(),
+            normal,
+            uv: uvs.as_ref().map_or([0.0; 2], |uvs| uvs[index]),
+            tangent,
+            joints: joints.get(index).copied().unwrap_or_default(),
+            weights: weights.get(index).copied().unwrap_or_default(),
+        }
+    }).collect();
+
+    // Deltas are directions, so they take the transform without its
+    // translation, normals again by the inverse transpose.
+    let targets = reader.read_morph_targets().map(|(position_deltas, normal_deltas, tangent_deltas)| {
+        let read = |deltas: Option<Vec<[f32; 3]>>, matrix: &cgmath::Matrix3<f32>| match deltas {
+            Some(deltas) if deltas.len() != positions.len() => Err("A morph target has a different number of deltas than positions"),
+            Some(deltas) => Ok(deltas.into_iter().map(|delta| (matrix * cgmath::Vector3::from(delta)).extend(0.0).into()).collect()),
+            None => Ok(vec![[0.0; 4]; positions.len()]),
+        };
+
+        let position_deltas: Vec<[f32; 4]> = read(position_deltas.map(Iterator::collect), &linear)?;
+        let normal_deltas: Vec<[f32; 4]> = read(normal_deltas.map(Iterator::collect), &normal_matrix)?;
+        let tangent_deltas: Vec<[f32; 4]> = read(tangent_deltas.map(Iterator::collect), &linear)?;
+
+        Ok(position_deltas.into_iter().zip(normal_deltas).zip(tangent_deltas)
+            .map(|((position, normal), tangent)| MorphDelta { position, normal, tangent })
+            .collect())
+    }).collect::<Result<Vec<Vec<MorphDelta>>, &str>>()?;
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices
+            .into_u32()
+            .map(|index| {
+                u16::try_from(index)
+                    .ok()
+                    .filter(|index| (*index as usize) < positions.len())
+                    .ok_or_else(|| format!("A primitive has index {}, but only {} vertices", index, positions.len()))
+            })
+            .collect::<Result<Vec<u16>, String>>()?,
+        // At most 65536 vertices, checked above, so every index fits.
+        None => (0..positions.len()).map(|index| index as u16).collect(),
+    };
+
+    let mut data = MeshData {
+        vertices,
+        indices,
+        morph: MorphTargets {
+            targets,
+            ..MorphTargets::default()
+        },
+    };
+
+    // Mirroring also flips the winding.
+    if handedness < 0.0 {
+        data.indices.chunks_exact_mut(3).for_each(|triangle| triangle.swap(1, 2));
+    }
+
+    Ok(data)
+}
+
+/// Collects triangles into meshes, merging corners with identical attributes
+/// into one vertex and starting a new mesh when one runs out of 16-bit
+/// indices.
+#[derive(Default)]
+pub struct MeshAssembler {
+    meshes: Vec<MeshData>,
+    current: MeshData,
+    vertices: HashMap<[u32; 20], u16>,
+}
+
+impl MeshAssembler {
+    pub fn push_triangle(&mut self, corners: [Vertex; 3]) {
+        let new_vertices = corners.iter().filter(|corner| !self.vertices.contains_key(&vertex_key(corner))).count();
+
+        if self.current.vertices.len() + new_vertices > u16::MAX as usize + 1 {
+            self.meshes.push(std::mem::take(&mut self.current));
+            self.vertices.clear();
+        }
+
+        corners.iter().for_each(|corner| {
+            let current = &mut self.current;
+
+            let index = *self.vertices.entry(vertex_key(corner)).or_insert_with(|| {
+                current.vertices.push(*corner);
+                vertex_index(current.vertices.len() - 1)
+            });
+
+            current.indices.push(index);
+        });
+    }
+
+    pub fn finish(mut self) -> Vec<MeshData> {
+        if !self.current.indices.is_empty() {
+            self.meshes.push(self.current);
+        }
+
+        self.meshes
+    }
+}
+
+fn vertex_key(vertex: &Vertex) -> [u32; 20] {
+    let mut key = [0; 20];
+
+    key[0..3].copy_from_slice(&vertex.position.map(float_key));
+    key[3..6].copy_from_slice(&vertex.normal.map(float_key));
+    key[6..8].copy_from_slice(&vertex.uv.map(float_key));
+    key[8..12].copy_from_slice(&vertex.tangent.map(float_key));
+    key[12..16].copy_from_slice(&vertex.joints.map(u32::from));
+    key[16..20].copy_from_slice(&vertex.weights.map(float_key));
+
+    key
+}
+
+/// The bits of `value`, with -0 as 0 so that computed normals match.
+fn float_key(value: f32) -> u32 {
+    (value + 0.0).to_bits()
+}
+
+/// Splits a polygon into triangles with the same winding, by ear clipping in
+/// the plane of its Newell normal so that concave polygons come out right.
+/// Self-intersecting polygons, which have no ears left at some point, are
+/// finished as a fan.
+pub fn triangulate(points: &[cgmath::Point3<f32>]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+
+    let normal = (0..points.len()).fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |normal, index| {
+        let (current, next) = (points[index], points[(index + 1) % points.len()]);
+
+        normal + cgmath::Vector3::new(
+            (current.y - next.y) * (current.z + next.z),
+            (current.z - next.z) * (current.x + next.x),
+            (current.x - next.x) * (current.y + next.y),
+        )
+    });
+
+    // Projected along the normal's largest axis, with the other two ordered
+    // so that the polygon winds counter-clockwise.
+    let axis = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() { 0 } else if normal.y.abs() >= normal.z.abs() { 1 } else { 2 };
+    let (u, v) = [(1, 2), (2, 0), (0, 1)][axis];
+    let (u, v) = if normal[axis] >= 0.0 { (u, v) } else { (v, u) };
+
+    let projected: Vec<cgmath::Vector2<f32>> = points.iter().map(|point| cgmath::Vector2::new(point[u], point[v])).collect();
+
+    let cross = |a: usize, b: usize, c: usize| {
+        let (ab, ac) = (projected[b] - projected[a], projected[c] - projected[a]);
+        ab.x * ac.y - ab.y * ac.x
+    };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+
+        let ear = (0..count).find(|index| {
+            let [previous, current, next] = [(index + count - 1) % count, *index, (index + 1) % count].map(|corner| remaining[corner]);
+
+            cross(previous, current, next) > 0.0 && remaining.iter().all(|other| {
+                [previous, current, next].contains(other)
+                    || projected[*other] == projected[previous]
+                    || projected[*other] == projected[current]
+                    || projected[*other] == projected[next]
+                    || cross(previous, current, *other) < 0.0
+                    || cross(current, next, *other) < 0.0
+                    || cross(next, previous, *other) < 0.0
+            })
+        });
+
+        let Some(ear) = ear else {
+            break;
+        };
+
+        triangles.push([(ear + count - 1) % count, ear, (ear + 1) % count].map(|corner| remaining[corner]));
+        remaining.remove(ear);
+    }
+
+    triangles.extend((1..remaining.len() - 1).map(|index| [remaining[0], remaining[index], remaining[index + 1]]));
+
+    triangles
+}
@@ -0,0 +1,40 @@
+
+pub struct PointLight {
+    pub position: cgmath::Point3<f32>,
+    pub color: cgmath::Vector3<f32>,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(position: cgmath::Point3<f32>, color: cgmath::Vector3<f32>, intensity: f32, range: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            range
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightUniform {
+    pub position: [f32; 3],
+    pub range: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+crate::uniform_layout!(PointLightUniform { position, range, color, intensity });
+
+impl From<&PointLight> for PointLightUniform {
+    fn from(light: &PointLight) -> Self {
+        Self {
+            position: light.position.into(),
+            range: light.range,
+            color: light.color.into(),
+            intensity: light.intensity,
+        }
+    }
+}
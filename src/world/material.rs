@@ -0,0 +1,353 @@
+use std::rc::Rc;
+
+use crate::{assets::server::{upload_texture, AssetServer, Handle, LoadState, TextureAsset}, graphics::context::{BindGroup, BindGroupEntry, BindGroupLayoutEntry, Buffer, Context, Sampler, TextureView}};
+
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub alpha_cutoff: f32,
+    pub alpha_mode: u32,
+}
+
+crate::uniform_layout!(MaterialUniform { base_color, metallic, roughness, alpha_cutoff, alpha_mode });
+
+/// How a material's alpha is interpreted, as glTF's `alphaMode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Fragments with alpha below `cutoff` are discarded, the rest are opaque.
+    Mask { cutoff: f32 },
+    /// Alpha blended in the transparent pass, after all opaque geometry.
+    Blend,
+}
+
+impl AlphaMode {
+    /// glTF's default `alphaCutoff`.
+    pub const DEFAULT_CUTOFF: f32 = 0.5;
+
+    /// Parses glTF's `alphaMode` and `alphaCutoff`.
+    pub fn from_gltf(mode: gltf::material::AlphaMode, cutoff: Option<f32>) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask { cutoff: cutoff.unwrap_or(Self::DEFAULT_CUTOFF) },
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        *self == AlphaMode::Blend
+    }
+
+    fn shader_mode(&self) -> u32 {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask { .. } => 1,
+            AlphaMode::Blend => 2,
+        }
+    }
+
+    fn cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Mask { cutoff } => *cutoff,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Bound by materials without a base color texture, which multiplies the
+/// base color by one.
+const WHITE_TEXTURE: &str = "material_white_texture";
+
+const MATERIAL_SAMPLER: &str = "material_sampler";
+
+/// The base color texture of a material, loaded by the `AssetServer`.
+/// `Material::bind_texture` asks the server for it and binds every version
+/// that loads. Until the first does, the material is drawn untextured.
+#[derive(Debug, Clone)]
+pub struct MaterialTexture {
+    /// Asset path of the image.
+    pub path: String,
+    pub handle: Option<Handle<TextureAsset>>,
+    /// Version of the texture bound, `None` until one is.
+    pub version: Option<u32>,
+}
+
+impl MaterialTexture {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            handle: None,
+            version: None,
+        }
+    }
+
+    /// Whether the server's texture, at `version`, replaces the bound one. A
+    /// failed reload keeps the version that loaded before it bound.
+    pub fn needs_binding(&self, state: &LoadState, version: u32) -> bool {
+        *state == LoadState::Loaded && self.version != Some(version)
+    }
+}
+
+pub struct Material {
+    pub label: String,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub alpha_mode: AlphaMode,
+    pub base_color_texture: Option<MaterialTexture>,
+    pub uniform_buffer: Rc<Buffer>,
+    pub bind_group: Rc<BindGroup>,
+}
+
+impl Material {
+    pub fn uniform(&self) -> MaterialUniform {
+        MaterialUniform {
+            base_color: self.base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            alpha_cutoff: self.alpha_mode.cutoff(),
+            alpha_mode: self.alpha_mode.shader_mode(),
+        }
+    }
+
+    pub fn update_uniforms(&self, ctx: &Context) {
+        ctx.device.queue.write_buffer(&self.uniform_buffer.gpu_buffer, 0, bytemuck::cast_slice(&[self.uniform()]));
+    }
+
+    /// Asks the server for the base color texture, and returns a copy of the
+    /// material, sharing its uniform buffer, once that has to change: to hold
+    /// on to the texture's handle, or to bind a version of the texture that
+    /// finished loading. `None` while nothing changed.
+    pub fn bind_texture(&self, ctx: &mut Context, server: &mut AssetServer) -> Option<Rc<Material>> {
+        let texture = self.base_color_texture.as_ref()?;
+
+        let handle = match &texture.handle {
+            Some(handle) => handle.clone(),
+            None => server.load_texture(&texture.path),
+        };
+
+        let version = server.version(&handle);
+
+        if texture.needs_binding(&server.state(&handle), version) {
+            let asset = server.get(&handle)?;
+
+            let bind_group = material_bind_group(ctx, &self.label, &self.uniform_buffer, &asset.view);
+
+            return Some(self.with_texture(MaterialTexture {
+                handle: Some(handle),
+                version: Some(version),
+                ..texture.clone()
+            }, bind_group));
+        }
+
+        texture.handle.is_none().then(|| self.with_texture(MaterialTexture {
+            handle: Some(handle),
+            ..texture.clone()
+        }, self.bind_group.clone()))
+    }
+
+    fn with_texture(&self, texture: MaterialTexture, bind_group: Rc<BindGroup>) -> Rc<Material> {
+        Rc::new(Material {
+            label: self.label.clone(),
+            base_color: self.base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            alpha_mode: self.alpha_mode,
+            base_color_texture: Some(texture),
+            uniform_buffer: self.uniform_buffer.clone(),
+            bind_group,
+        })
+    }
+}
+
+pub struct MaterialBuilder<'a> {
+    ctx: &'a mut Context,
+    label: &'a str,
+    base_color: [f32; 4],
+    metallic: f32,
+    roughness: f32,
+    alpha_mode: AlphaMode,
+    base_color_texture: Option<String>,
+}
+
+impl<'a> MaterialBuilder<'a> {
+    pub fn new(ctx: &'a mut Context, label: &'a str) -> Self {
+        Self {
+            ctx,
+            label,
+            base_color: [0.8, 0.8, 0.8, 1.0],
+            metallic: 0.0,
+            roughness: 0.5,
+            alpha_mode: AlphaMode::Opaque,
+            base_color_texture: None,
+        }
+    }
+
+    pub fn with_base_color(mut self, base_color: [f32; 4]) -> Self {
+        self.base_color = base_color;
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Multiplies the base color by the image at asset path `path`, once
+    /// `Material::bind_texture` has loaded it.
+    pub fn with_base_color_texture(mut self, path: &str) -> Self {
+        self.base_color_texture = Some(path.to_string());
+        self
+    }
+
+    pub fn build(&mut self) -> Rc<Material> {
+        let uniform = MaterialUniform {
+            base_color: self.base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            alpha_cutoff: self.alpha_mode.cutoff(),
+            alpha_mode: self.alpha_mode.shader_mode(),
+        };
+
+        let uniform_buffer = self.ctx.create_buffer(
+            &format!("{}_material_uniform_buffer", self.label),
+            bytemuck::cast_slice(&[uniform]),
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST
+        );
+
+        let white_texture = white_texture(self.ctx);
+
+        let bind_group = material_bind_group(self.ctx, self.label, &uniform_buffer, &white_texture);
+
+        Rc::new(Material {
+            label: self.label.to_string(),
+            base_color: self.base_color,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            alpha_mode: self.alpha_mode,
+            base_color_texture: self.base_color_texture.as_deref().map(MaterialTexture::new),
+            uniform_buffer,
+            bind_group,
+        })
+    }
+}
+
+fn material_bind_group(ctx: &mut Context, label: &str, uniform_buffer: &Buffer, base_color_texture: &TextureView) -> Rc<BindGroup> {
+    let bind_group_layout = ctx.create_bind_group_layout(
+        "material_bind_group_layout",
+        vec![
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                }
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false
+                }
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+            },
+        ]
+    );
+
+    let sampler = material_sampler(ctx);
+
+    ctx.create_bind_group(
+        &format!("{}_material_bind_group", label),
+        &bind_group_layout.gpu_bind_group_layout,
+        vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.gpu_buffer.as_entire_binding()
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&base_color_texture.gpu_texture_view)
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&sampler.gpu_sampler)
+            },
+        ]
+    )
+}
+
+fn white_texture(ctx: &mut Context) -> Rc<TextureView> {
+    if let Some(view) = ctx.texture_views.get(WHITE_TEXTURE) {
+        return view.clone();
+    }
+
+    let white = upload_texture(ctx, WHITE_TEXTURE, &image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])));
+
+    ctx.textures.insert(WHITE_TEXTURE.to_string(), white.texture);
+    ctx.texture_views.insert(WHITE_TEXTURE.to_string(), white.view.clone());
+
+    white.view
+}
+
+fn material_sampler(ctx: &mut Context) -> Rc<Sampler> {
+    if let Some(sampler) = ctx.samplers.get(MATERIAL_SAMPLER) {
+        return sampler.clone();
+    }
+
+    ctx.create_sampler(
+        MATERIAL_SAMPLER,
+        wgpu::AddressMode::Repeat,
+        wgpu::AddressMode::Repeat,
+        wgpu::AddressMode::Repeat,
+        wgpu::FilterMode::Linear,
+        wgpu::FilterMode::Linear,
+        wgpu::FilterMode::Nearest,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_loaded_version_of_a_texture_is_bound_once() {
+        let mut texture = MaterialTexture::new("textures/checker.png");
+
+        assert!(!texture.needs_binding(&LoadState::Loading, 0));
+        assert!(!texture.needs_binding(&LoadState::Failed("missing".to_string()), 0));
+
+        assert!(texture.needs_binding(&LoadState::Loaded, 1));
+        texture.version = Some(1);
+        assert!(!texture.needs_binding(&LoadState::Loaded, 1));
+
+        // The file changed and the new image loaded.
+        assert!(texture.needs_binding(&LoadState::Loaded, 2));
+        texture.version = Some(2);
+
+        // A reload that fails keeps version 2 bound.
+        assert!(!texture.needs_binding(&LoadState::Failed("corrupt".to_string()), 2));
+    }
+}
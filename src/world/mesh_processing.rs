@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use crate::graphics::vertex_input::Vertex;
+
+use super::world::{vertex_index, MeshData, MorphTargets};
+
+
+/// Entries of the simulated post-transform vertex cache the optimisations
+/// target. Real caches vary, but orders tuned for 32 entries do well on
+/// smaller and larger ones too.
+const VERTEX_CACHE_SIZE: usize = 32;
+
+/// Fills in what `ModelBuilder` needs but the data lacks, then reorders it
+/// for rendering: smooth normals where any normal is zero, tangents where any
+/// tangent is, and vertex cache, overdraw and vertex fetch optimisation.
+pub fn process(data: &mut MeshData) {
+    if !has_normals(data) {
+        smooth_normals(data);
+    }
+
+    if !has_tangents(data) {
+        generate_tangents(data);
+    }
+
+    optimize_vertex_cache(data);
+    optimize_overdraw(data);
+    optimize_vertex_fetch(data);
+}
+
+pub fn has_normals(data: &MeshData) -> bool {
+    data.vertices.iter().all(|vertex| vertex.normal != [0.0; 3])
+}
+
+pub fn has_tangents(data: &MeshData) -> bool {
+    data.vertices.iter().all(|vertex| vertex.tangent[..3] != [0.0; 3])
+}
+
+fn position(vertex: &Vertex) -> cgmath::Vector3<f32> {
+    vertex.position.into()
+}
+
+/// Normal of a triangle, with a length of twice its area.
+fn face_normal(data: &MeshData, triangle: &[u16]) -> cgmath::Vector3<f32> {
+    let [a, b, c] = [0, 1, 2].map(|corner| position(&data.vertices[triangle[corner] as usize]));
+
+    (b - a).cross(c - a)
+}
+
+/// Averages the normals of the triangles around each position, weighted by
+/// their area. Vertices split along UV seams share the same position, so
+/// they get the same normal and the seam doesn't show.
+pub fn smooth_normals(data: &mut MeshData) {
+    let key = |vertex: &Vertex| vertex.position.map(f32::to_bits);
+
+    let mut normals: HashMap<[u32; 3], cgmath::Vector3<f32>> = HashMap::new();
+
+    data.indices.chunks_exact(3).for_each(|triangle| {
+        let normal = face_normal(data, triangle);
+
+        triangle.iter().for_each(|index| {
+            *normals.entry(key(&data.vertices[*index as usize])).or_insert(cgmath::Vector3::new(0.0, 0.0, 0.0)) += normal;
+        });
+    });
+
+    data.vertices.iter_mut().for_each(|vertex| {
+        let normal = normals.get(&key(vertex)).copied().unwrap_or(cgmath::Vector3::new(0.0, 0.0, 0.0));
+
+        vertex.normal = if normal.magnitude2() > 0.0 { normal.normalize().into() } else { [0.0, 1.0, 0.0] };
+    });
+}
+
+/// Gives every triangle its own vertices with the triangle's normal, for a
+/// faceted look. Tangents are cleared, since they no longer match. Morph
+/// targets keep their normal deltas, which are then only approximate.
+pub fn flat_normals(data: &MeshData) -> MeshData {
+    let vertices: Vec<Vertex> = data.indices.chunks_exact(3).flat_map(|triangle| {
+        let normal = face_normal(data, triangle);
+        let normal = if normal.magnitude2() > 0.0 { normal.normalize().into() } else { [0.0, 1.0, 0.0] };
+
+        triangle.iter().map(move |index| Vertex {
+            normal,
+            tangent: [0.0; 4],
+            ..data.vertices[*index as usize]
+        })
+    }).collect();
+
+    let indices = (0..vertices.len()).map(vertex_index).collect();
+
+    let corners: Vec<usize> = data.indices.iter().map(|index| *index as usize).collect();
+
+    MeshData {
+        vertices,
+        indices,
+        morph: data.morph.gather(&corners),
+    }
+}
+
+/// Adapts unindexed triangles to `bevy_mikktspace`.
+struct TangentGeometry<'a> {
+    vertices: &'a mut [Vertex],
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertices[face * 3 + vert].position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertices[face * 3 + vert].normal
+    }
+
+    // MikkTSpace's bitangent follows increasing v, while ours points towards
+    // decreasing v, so it gets flipped UVs.
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = self.vertices[face * 3 + vert].uv;
+
+        [uv[0], 1.0 - uv[1]]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.vertices[face * 3 + vert].tangent = tangent;
+    }
+}
+
+/// Computes MikkTSpace tangents, the convention most normal map bakers use,
+/// so baked maps shade without seams. Normals and UVs must already be set.
+///
+/// MikkTSpace works per triangle corner, so corners that share a vertex but
+/// end up with different tangents are split, and the rest merged again.
+/// Where no tangent can be derived, e.g. without UVs, any vector
+/// perpendicular to the normal is used.
+pub fn generate_tangents(data: &mut MeshData) {
+    let mut vertices: Vec<Vertex> = data.indices.iter().map(|index| data.vertices[*index as usize]).collect();
+
+    let generated = bevy_mikktspace::generate_tangents(&mut TangentGeometry { vertices: &mut vertices });
+
+    vertices.iter_mut().for_each(|vertex| {
+        let tangent = cgmath::Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]);
+
+        if !generated || !tangent.x.is_finite() || tangent.magnitude2() == 0.0 {
+            vertex.tangent = perpendicular(vertex.normal.into()).extend(1.0).into();
+        }
+    });
+
+    let corners: Vec<usize> = data.indices.iter().map(|index| *index as usize).collect();
+
+    *data = MeshData {
+        indices: (0..vertices.len()).map(vertex_index).collect(),
+        vertices,
+        morph: data.morph.gather(&corners),
+    };
+
+    weld(data, 0.0);
+}
+
+fn perpendicular(normal: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+    let axis = if normal.x.abs() < 0.9 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() };
+    let tangent = axis - normal * normal.dot(axis);
+
+    if tangent.magnitude2() > 0.0 { tangent.normalize() } else { axis }
+}
+
+fn attributes(vertex: &Vertex) -> [f32; 20] {
+    let mut attributes = [0.0; 20];
+
+    attributes[0..3].copy_from_slice(&vertex.position);
+    attributes[3..6].copy_from_slice(&vertex.normal);
+    attributes[6..8].copy_from_slice(&vertex.uv);
+    attributes[8..12].copy_from_slice(&vertex.tangent);
+    attributes[12..16].copy_from_slice(&vertex.joints.map(f32::from));
+    attributes[16..20].copy_from_slice(&vertex.weights);
+
+    attributes
+}
+
+/// The deltas of every morph target for the vertex at `index`.
+fn morph_attributes(morph: &MorphTargets, index: usize) -> Vec<f32> {
+    morph.targets.iter().flat_map(|deltas| {
+        let delta = deltas[index];
+
+        [&delta.position[..3], &delta.normal[..3], &delta.tangent[..3]].concat()
+    }).collect()
+}
+
+/// Merges vertices whose attributes, morph target deltas included, all differ
+/// by at most `tolerance`, keeping the first of each group, and drops the
+/// triangles that collapse. The tolerance applies to each component, in the
+/// units of the attribute, so positions are matched within `tolerance` along
+/// each axis.
+pub fn weld(data: &mut MeshData, tolerance: f32) {
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(data.vertices.len());
+    let mut kept: Vec<usize> = Vec::with_capacity(data.vertices.len());
+    let mut remap = Vec::with_capacity(data.vertices.len());
+
+    if tolerance <= 0.0 {
+        let mut welded: HashMap<([u32; 20], Vec<u32>), usize> = HashMap::new();
+
+        data.vertices.iter().enumerate().for_each(|(index, vertex)| {
+            let key = (attributes(vertex).map(f32::to_bits), morph_attributes(&data.morph, index).into_iter().map(f32::to_bits).collect());
+
+            remap.push(*welded.entry(key).or_insert_with(|| {
+                vertices.push(*vertex);
+                kept.push(index);
+                vertices.len() - 1
+            }));
+        });
+    } else {
+        // Vertices within the tolerance are at most one cell apart.
+        let cell = |position: [f32; 3]| position.map(|coordinate| (coordinate / tolerance).floor() as i32);
+
+        let mut cells: HashMap<[i32; 3], Vec<usize>> = HashMap::new();
+
+        data.vertices.iter().enumerate().for_each(|(index, vertex)| {
+            let vertex_attributes = attributes(vertex);
+            let vertex_morph_attributes = morph_attributes(&data.morph, index);
+            let [x, y, z] = cell(vertex.position);
+
+            let within_tolerance = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tolerance);
+
+            let existing = (-1..=1).flat_map(|dx| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [x + dx, y + dy, z + dz])))
+                .filter_map(|neighbour| cells.get(&neighbour))
+                .flatten()
+                .find(|candidate| {
+                    within_tolerance(&attributes(&vertices[**candidate]), &vertex_attributes)
+                        && within_tolerance(&morph_attributes(&data.morph, kept[**candidate]), &vertex_morph_attributes)
+                })
+                .copied();
+
+            remap.push(existing.unwrap_or_else(|| {
+                vertices.push(*vertex);
+                kept.push(index);
+                cells.entry([x, y, z]).or_default().push(vertices.len() - 1);
+                vertices.len() - 1
+            }));
+        });
+    }
+
+    data.indices = data.indices.chunks_exact(3)
+        .map(|triangle| [0, 1, 2].map(|corner| remap[triangle[corner] as usize]))
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .flatten()
+        .map(vertex_index)
+        .collect();
+
+    data.vertices = vertices;
+    data.morph = data.morph.gather(&kept);
+}
+
+/// Score of a vertex for the cache optimisation, from Tom Forsyth's "Linear-
+/// Speed Vertex Cache Optimisation". Vertices recently used and those with
+/// few triangles left score high, so their triangles get drawn first.
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // The last triangle's vertices, which it is best not to favour over
+        // the rest of the cache, so that strips don't form.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+
+    cache_score + 2.0 * (remaining_triangles as f32).powf(-0.5)
+}
+
+/// Reorders triangles so that vertices are reused while still in the
+/// post-transform cache, using Forsyth's algorithm.
+pub fn optimize_vertex_cache(data: &mut MeshData) {
+    let triangle_count = data.indices.len() / 3;
+    let triangles: Vec<[usize; 3]> = data.indices.chunks_exact(3).map(|triangle| [0, 1, 2].map(|corner| triangle[corner] as usize)).collect();
+
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); data.vertices.len()];
+
+    triangles.iter().enumerate().for_each(|(triangle, corners)| {
+        corners.iter().for_each(|vertex| vertex_triangles[*vertex].push(triangle));
+    });
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; data.vertices.len()];
+    let mut vertex_scores: Vec<f32> = vertex_triangles.iter().map(|triangles| vertex_score(None, triangles.len())).collect();
+
+    let triangle_score = |triangle: usize, vertex_scores: &[f32]| triangles[triangle].iter().map(|vertex| vertex_scores[*vertex]).sum::<f32>();
+
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<usize> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut indices = Vec::with_capacity(data.indices.len());
+
+    let mut best = (0..triangle_count).max_by(|a, b| triangle_score(*a, &vertex_scores).total_cmp(&triangle_score(*b, &vertex_scores)));
+    let mut next_unemitted = 0;
+
+    while let Some(triangle) = best {
+        emitted[triangle] = true;
+
+        let corners = triangles[triangle];
+
+        corners.iter().for_each(|vertex| {
+            indices.push(*vertex);
+
+            let remaining = &mut vertex_triangles[*vertex];
+            remaining.retain(|other| *other != triangle);
+        });
+
+        // The triangle's vertices move to the front, pushing the least
+        // recently used out of the back.
+        let mut new_cache: Vec<usize> = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|vertex| !corners.contains(vertex)));
+
+        new_cache.iter().enumerate().for_each(|(position, vertex)| {
+            cache_positions[*vertex] = (position < VERTEX_CACHE_SIZE).then_some(position);
+            vertex_scores[*vertex] = vertex_score(cache_positions[*vertex], vertex_triangles[*vertex].len());
+        });
+
+        // Only triangles around cached vertices changed score, so the next
+        // one is looked for among them.
+        best = new_cache.iter()
+            .take(VERTEX_CACHE_SIZE)
+            .flat_map(|vertex| vertex_triangles[*vertex].iter())
+            .map(|triangle| (*triangle, triangle_score(*triangle, &vertex_scores)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(triangle, _)| triangle);
+
+        new_cache.truncate(VERTEX_CACHE_SIZE);
+        cache = new_cache;
+
+        // Nothing left around the cache: carry on from any triangle.
+        if best.is_none() {
+            while next_unemitted < triangle_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+
+            best = (next_unemitted < triangle_count).then_some(next_unemitted);
+        }
+    }
+
+    data.indices = indices.into_iter().map(vertex_index).collect();
+}
+
+/// Reorders clusters of triangles, keeping the order within each, so that
+/// those facing outwards from the centre of the mesh are drawn first and
+/// tend to hide the rest, after Sander et al.'s "Fast Triangle Reordering for
+/// Vertex Locality and Reduced Overdraw". Clusters end where the cache order
+/// jumps, i.e. at triangles none of whose vertices are in the cache, so the
+/// cache efficiency of `optimize_vertex_cache` is mostly kept.
+pub fn optimize_overdraw(data: &mut MeshData) {
+    let triangles: Vec<&[u16]> = data.indices.chunks_exact(3).collect();
+
+    if triangles.is_empty() {
+        return;
+    }
+
+    let mut clusters: Vec<Vec<&[u16]>> = Vec::new();
+    let mut cache: Vec<u16> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+
+    triangles.iter().for_each(|triangle| {
+        let misses = triangle.iter().filter(|vertex| !cache.contains(vertex)).count();
+
+        if misses == 3 || clusters.is_empty() {
+            clusters.push(Vec::new());
+        }
+
+        clusters.last_mut().expect("A cluster was just added").push(triangle);
+
+        let mut new_cache = triangle.to_vec();
+        new_cache.extend(cache.iter().filter(|vertex| !triangle.contains(vertex)));
+        new_cache.truncate(VERTEX_CACHE_SIZE);
+        cache = new_cache;
+    });
+
+    let centroid = |triangle: &[u16]| {
+        triangle.iter().map(|index| position(&data.vertices[*index as usize])).sum::<cgmath::Vector3<f32>>() / 3.0
+    };
+
+    let mesh_centroid = triangles.iter().map(|triangle| centroid(triangle)).sum::<cgmath::Vector3<f32>>() / triangles.len() as f32;
+
+    let mut keyed_clusters: Vec<(f32, Vec<&[u16]>)> = clusters.into_iter().map(|cluster| {
+        let (weighted_centroid, normal, area) = cluster.iter().fold(
+            (cgmath::Vector3::new(0.0, 0.0, 0.0), cgmath::Vector3::new(0.0, 0.0, 0.0), 0.0),
+            |(weighted_centroid, normal, area), triangle| {
+                let face_normal = face_normal(data, triangle);
+                let triangle_area = face_normal.magnitude();
+
+                (weighted_centroid + centroid(triangle) * triangle_area, normal + face_normal, area + triangle_area)
+            },
+        );
+
+        let cluster_centroid = if area > 0.0 { weighted_centroid / area } else { centroid(cluster[0]) };
+        let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { normal };
+
+        ((cluster_centroid - mesh_centroid).dot(normal), cluster)
+    }).collect();
+
+    keyed_clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    data.indices = keyed_clusters.into_iter().flat_map(|(_, cluster)| cluster.into_iter().flatten().copied()).collect();
+}
+
+/// Reorders vertices by first use in the index buffer, so that vertex fetches
+/// walk memory in order, and drops unused vertices.
+pub fn optimize_vertex_fetch(data: &mut MeshData) {
+    let mut remap: Vec<Option<u16>> = vec![None; data.vertices.len()];
+    let mut vertices = Vec::with_capacity(data.vertices.len());
+    let mut kept: Vec<usize> = Vec::with_capacity(data.vertices.len());
+
+    data.indices.iter_mut().for_each(|index| {
+        let old = *index as usize;
+
+        *index = *remap[old].get_or_insert_with(|| {
+            vertices.push(data.vertices[old]);
+            kept.push(old);
+            vertex_index(vertices.len() - 1)
+        });
+    });
+
+    data.vertices = vertices;
+    data.morph = data.morph.gather(&kept);
+}
+
+/// A sphere enclosing every vertex, by Ritter's method: not the smallest,
+/// but within a few percent of it.
+pub fn bounding_sphere(vertices: &[Vertex]) -> (cgmath::Point3<f32>, f32) {
+    let Some(first) = vertices.first() else {
+        return (cgmath::Point3::origin(), 0.0);
+    };
+
+    let farthest_from = |point: cgmath::Vector3<f32>| vertices.iter()
+        .map(position)
+        .max_by(|a, b| (a - point).magnitude2().total_cmp(&(b - point).magnitude2()))
+        .expect("There is at least one vertex");
+
+    let a = farthest_from(position(first));
+    let b = farthest_from(a);
+
+    let mut center = (a + b) / 2.0;
+    let mut radius = (b - a).magnitude() / 2.0;
+
+    // Grows the sphere just enough to take in each point left outside.
+    vertices.iter().map(position).for_each(|point| {
+        let distance = (point - center).magnitude();
+
+        if distance > radius {
+            let new_radius = (radius + distance) / 2.0;
+            center += (point - center) * ((new_radius - radius) / distance);
+            radius = new_radius;
+        }
+    });
+
+    (cgmath::Point3::from_vec(center), radius)
+}
@@ -1,3 +1,14 @@
 pub mod world;
 pub mod camera;
-pub mod model;
\ No newline at end of file
+pub mod model;
+pub mod material;
+pub mod light;
+pub mod bounds;
+pub mod raycast;
+pub mod primitives;
+pub mod mesh_processing;
+pub mod import;
+pub mod simplify;
+pub mod obj;
+pub mod stl;
+pub mod animation;
@@ -1,11 +1,12 @@
-use std::{io::{BufReader, Cursor}, rc::Rc};
+use std::rc::Rc;
 
-use crate::graphics::{context::{BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutEntry, Context, RenderPipeline}, renderer::TransformUniform, vertex_input::{Vertex, CUBE_INDICES, CUBE_VERTICES}};
+use crate::assets::{server::{Handle, ModelAsset}, source::AssetResolver};
 
-use super::world::{Mesh, Transform};
+use crate::graphics::{lighting::SceneLightingUniform, lod::MAX_LOD_LEVELS, pipeline::{BlendPreset, RenderPipelineBuilder}, context::{BindGroup, BindGroupEntry, BindGroupLayout, Buffer, Context, RenderPipeline}, deferred::gbuffer_color_targets, graphics::{WORLD_COLOR_FORMAT, WORLD_DEPTH_FORMAT}, picking::PICKING_ID_FORMAT, preprocessor::Defines, renderer::TransformUniform, transparency::oit_color_targets, vertex_input::{MorphDelta, Vertex}};
+
+use super::{animation::{AnimationClip, AnimationPlayer, Skeleton}, bounds::Aabb, light::PointLightUniform, material::{Material, MaterialBuilder, MaterialUniform}, import::{self, ImportedMaterial}, primitives, world::{Mesh, MeshData, PreparedMesh, Transform, MAX_MORPH_TARGETS}};
 
 use cgmath::{EuclideanSpace, SquareMatrix};
-use gltf::Gltf;
 
 
 
@@ -14,7 +15,133 @@ pub struct Model {
     pub meshes: Vec<Mesh>,
     pub bind_group_layout: Rc<BindGroupLayout>,
     pub bind_group: Rc<BindGroup>,
-    pub pipeline: Rc<RenderPipeline>
+    pub material: Rc<Material>,
+    pub pipeline: Rc<RenderPipeline>,
+    pub gbuffer_pipeline: Rc<RenderPipeline>,
+    /// Used instead of `pipeline` when the material blends, drawn back to front.
+    pub transparent_pipeline: Rc<RenderPipeline>,
+    /// Used instead of `transparent_pipeline` for weighted blended transparency.
+    pub oit_pipeline: Rc<RenderPipeline>,
+    /// Level of detail its meshes are drawn at, picked each frame by
+    /// `lod::select_lods`.
+    pub lod: usize,
+    pub source: Option<ModelSource>,
+    pub material_source: Option<MaterialSource>,
+    /// Poses the meshes of animated models, which draw with skinned pipelines.
+    pub skin: Option<Skin>,
+    /// Weights of the morph targets of models with a mesh that has any, which
+    /// draw with morphed pipelines.
+    pub morph: Option<Morph>,
+    pub transform_buffer: Rc<Buffer>,
+}
+
+/// The skeleton and animations of a model, and the joint matrices they put
+/// its vertices in place with.
+pub struct Skin {
+    pub skeleton: Rc<Skeleton>,
+    pub animations: Rc<Vec<AnimationClip>>,
+    pub player: AnimationPlayer,
+    /// As of the last `Model::animate`.
+    pub joint_matrices: Vec<cgmath::Matrix4<f32>>,
+    pub joint_buffer: Rc<Buffer>,
+    /// The model's transform and joint matrices, bound by the skinned
+    /// pipelines instead of `Model::bind_group`, unless the model also has
+    /// morph targets.
+    pub bind_group: Rc<BindGroup>,
+}
+
+/// The morph target weights of each mesh of a model, taken from the skeleton
+/// node of the mesh while the model is animated.
+pub struct Morph {
+    /// As of the last `Model::animate`, empty for meshes without targets.
+    pub weights: Vec<Vec<f32>>,
+    pub weight_buffers: Vec<Rc<Buffer>>,
+    /// The model's transform, joint matrices if skinned, and each mesh's
+    /// deltas and weights, bound by the morphed pipelines.
+    pub bind_groups: Vec<Rc<BindGroup>>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MorphWeightsUniform {
+    pub weights: [[f32; 4]; MAX_MORPH_TARGETS / 4],
+    pub target_count: u32,
+    pub vertex_count: u32,
+    pub _padding: [u32; 2],
+}
+
+crate::uniform_layout!(MorphWeightsUniform { weights, target_count, vertex_count, _padding });
+
+impl MorphWeightsUniform {
+    fn new(mesh: &Mesh, weights: &[f32]) -> Self {
+        let mut uniform = Self {
+            weights: [[0.0; 4]; MAX_MORPH_TARGETS / 4],
+            target_count: mesh.morph.as_ref().map_or(0, |morph| morph.count),
+            vertex_count: mesh.vertices.len() as u32,
+            _padding: [0; 2],
+        };
+
+        weights.iter().take(MAX_MORPH_TARGETS).enumerate().for_each(|(target, weight)| uniform.weights[target / 4][target % 4] = *weight);
+
+        uniform
+    }
+}
+
+/// The file a model's meshes come from. Models built `with_asset` are drawn as
+/// a placeholder until the `AssetServer` has loaded it. Those that imported it
+/// in `ModelBuilder::build` are handed to the server once the file changes,
+/// which then reloads it.
+pub struct ModelSource {
+    pub handle: Option<Handle<ModelAsset>>,
+    /// The file imported by `ModelBuilder::build`, and every file it read.
+    /// Empty for models built `with_asset`.
+    pub file_path: String,
+    pub dependencies: Vec<String>,
+    /// Version of the asset last applied, `None` until it has loaded or
+    /// failed to.
+    pub version: Option<u32>,
+    /// Whether the file's material replaces the model's, which is the case
+    /// unless the model was built with one.
+    pub use_material: bool,
+    /// Whether the server watches `dependencies` yet, which
+    /// `World::update_assets` has it do once.
+    pub watched: bool,
+}
+
+/// The material file a model is drawn with. Until the `AssetServer` has
+/// loaded it the model keeps the material it was built with, and it picks up
+/// every version that loads after.
+pub struct MaterialSource {
+    pub handle: Handle<Material>,
+    /// Version of the asset last applied, `None` until one has loaded.
+    pub version: Option<u32>,
+}
+
+/// A model file imported and prepared on the CPU, ready to upload.
+pub struct PreparedModel {
+    pub meshes: Vec<PreparedMesh>,
+    pub material: Option<ImportedMaterial>,
+    pub skeleton: Option<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+    pub dependencies: Vec<String>,
+}
+
+impl PreparedModel {
+    pub fn load(assets: &AssetResolver, path: &str, lod_levels: usize) -> Result<Self, String> {
+        let imported = import::import(assets, path)?;
+
+        if imported.meshes.is_empty() {
+            return Err(format!("{} has no triangles", path));
+        }
+
+        Ok(Self {
+            meshes: imported.meshes.into_iter().map(|mesh| PreparedMesh::new(mesh, lod_levels)).collect(),
+            material: imported.material,
+            skeleton: imported.skeleton,
+            animations: imported.animations,
+            dependencies: imported.dependencies,
+        })
+    }
 }
 
 impl Model {
@@ -29,12 +156,261 @@ impl Model {
 
         scaled_model
     }
+
+    /// The world-space box enclosing every mesh of the model.
+    pub fn world_bounds(&self) -> Option<Aabb> {
+        let model_matrix = self.model_matrix();
+
+        let Some(skin) = self.skin.as_ref() else {
+            return self.meshes
+                .iter()
+                .map(|mesh| mesh.bounds.transformed(&model_matrix))
+                .reduce(|bounds, mesh_bounds| bounds.union(&mesh_bounds));
+        };
+
+        // A skinned vertex is a weighted average of its position moved by
+        // each of its joints, so it stays within the bounds moved by every
+        // joint. Vertices without joints don't move.
+        let bounds = self.meshes.iter().map(|mesh| mesh.bounds).reduce(|bounds, mesh_bounds| bounds.union(&mesh_bounds))?;
+
+        skin.joint_matrices
+            .iter()
+            .map(|joint_matrix| bounds.transformed(&(model_matrix * joint_matrix)))
+            .fold(bounds.transformed(&model_matrix), |posed, joint_bounds| posed.union(&joint_bounds))
+            .into()
+    }
+
+    /// Which shader permutation the model's pipelines use. Passes drawing the
+    /// model with shaders of their own need the same one to bind
+    /// `draw_bind_group` and the vertex buffers.
+    pub fn permutation(&self) -> ModelPermutation {
+        ModelPermutation {
+            skinned: self.skin.is_some(),
+            morphed: self.morph.is_some(),
+        }
+    }
+
+    /// The group 1 bind group of the model's pipelines for `mesh`, one of its
+    /// meshes.
+    pub fn draw_bind_group(&self, mesh: &Mesh) -> &BindGroup {
+        let morph_bind_group = self.morph.as_ref().and_then(|morph| {
+            self.meshes.iter().position(|other| std::ptr::eq(other, mesh)).map(|index| morph.bind_groups[index].as_ref())
+        });
+
+        morph_bind_group.unwrap_or_else(|| self.skin.as_ref().map_or(&self.bind_group, |skin| &skin.bind_group))
+    }
+
+    /// Advances the animation of a skinned model by `delta` seconds and
+    /// uploads the joint matrices and morph target weights of its new pose.
+    /// Models with morph targets but no animation upload their weights as
+    /// they are.
+    pub fn animate(&mut self, ctx: &Context, delta: f32) {
+        let mut node_weights = None;
+
+        if let Some(skin) = self.skin.as_mut() {
+            skin.player.advance(&skin.animations, delta);
+
+            let pose = skin.player.pose(&skin.skeleton, &skin.animations);
+
+            skin.joint_matrices = skin.skeleton.joint_matrices(&pose);
+
+            let joint_matrices: Vec<[[f32; 4]; 4]> = skin.joint_matrices.iter().map(|matrix| (*matrix).into()).collect();
+
+            ctx.device.queue.write_buffer(&skin.joint_buffer.gpu_buffer, 0, bytemuck::cast_slice(&joint_matrices));
+
+            if self.morph.is_some() {
+                node_weights = Some(skin.player.morph_weights(&skin.skeleton, &skin.animations));
+            }
+        }
+
+        let Some(morph) = self.morph.as_mut() else {
+            return;
+        };
+
+        self.meshes.iter().zip(&mut morph.weights).zip(&morph.weight_buffers).for_each(|((mesh, weights), weight_buffer)| {
+            let animated = mesh.morph.as_ref()
+                .and_then(|targets| targets.node)
+                .and_then(|node| node_weights.as_ref()?.get(node))
+                .filter(|animated| !animated.is_empty());
+
+            if let Some(animated) = animated {
+                weights.clone_from(animated);
+            }
+
+            ctx.device.queue.write_buffer(&weight_buffer.gpu_buffer, 0, bytemuck::cast_slice(&[MorphWeightsUniform::new(mesh, weights)]));
+        });
+    }
+
+    /// Animates the model with `skeleton`, or makes it static with `None`,
+    /// switching to the matching pipelines. The animation player is kept if
+    /// the clips it plays still exist, and otherwise starts on the first one.
+    pub fn set_skeleton(&mut self, ctx: &mut Context, skeleton: Option<Rc<Skeleton>>, animations: Rc<Vec<AnimationClip>>) {
+        let previous = self.skin.take();
+
+        let Some(skeleton) = skeleton else {
+            self.update_morph(ctx);
+            return;
+        };
+
+        let pipelines = ModelPipelines::new(ctx, ModelPermutation { skinned: true, morphed: false });
+
+        let joint_matrices = skeleton.joint_matrices(&skeleton.rest_pose());
+
+        // Storage buffers can't be empty.
+        let mut joint_data: Vec<[[f32; 4]; 4]> = joint_matrices.iter().map(|matrix| (*matrix).into()).collect();
+
+        if joint_data.is_empty() {
+            joint_data.push(cgmath::Matrix4::identity().into());
+        }
+
+        let joint_buffer = ctx.create_storage_buffer("model_joint_buffer", bytemuck::cast_slice(&joint_data), wgpu::BufferUsages::empty());
+
+        let bind_group = ctx.create_bind_group(
+            "model_skin_bind_group",
+            &pipelines.pipeline.bind_group_layouts[1].gpu_bind_group_layout,
+            vec![
+                BindGroupEntry {
+                    binding: 0,
+                    resource: self.transform_buffer.gpu_buffer.as_entire_binding()
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: joint_buffer.gpu_buffer.as_entire_binding()
+                },
+            ]
+        );
+
+        let player = previous
+            .map(|skin| skin.player)
+            .filter(|player| [player.current, player.blend].into_iter().flatten().all(|playback| playback.clip < animations.len()))
+            .unwrap_or_else(|| {
+                let mut player = AnimationPlayer::default();
+
+                if !animations.is_empty() {
+                    player.play(0);
+                }
+
+                player
+            });
+
+        self.skin = Some(Skin {
+            skeleton,
+            animations,
+            player,
+            joint_matrices,
+            joint_buffer,
+            bind_group,
+        });
+
+        self.update_morph(ctx);
+    }
+
+    /// Sets up the morph target weights of the meshes, starting from their
+    /// defaults, and switches to the pipelines that match the meshes and the
+    /// skin.
+    fn update_morph(&mut self, ctx: &mut Context) {
+        let morphed = self.meshes.iter().any(|mesh| mesh.morph.is_some());
+
+        let pipelines = ModelPipelines::new(ctx, ModelPermutation { skinned: self.skin.is_some(), morphed });
+
+        self.morph = None;
+
+        if morphed {
+            // Meshes without targets bind a delta they never read.
+            let empty_deltas = ctx.create_storage_buffer("model_empty_morph_delta_buffer", bytemuck::cast_slice(&[MorphDelta::default()]), wgpu::BufferUsages::empty());
+
+            let weights: Vec<Vec<f32>> = self.meshes.iter().map(|mesh| mesh.morph.as_ref().map_or_else(Vec::new, |morph| morph.weights.clone())).collect();
+
+            let weight_buffers: Vec<Rc<Buffer>> = self.meshes.iter().zip(&weights).map(|(mesh, weights)| {
+                ctx.create_buffer("model_morph_weights_buffer", bytemuck::cast_slice(&[MorphWeightsUniform::new(mesh, weights)]), wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST)
+            }).collect();
+
+            let bind_groups = self.meshes.iter().zip(&weight_buffers).map(|(mesh, weight_buffer)| {
+                let mut entries = vec![
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: self.transform_buffer.gpu_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: mesh.morph.as_ref().map_or(&empty_deltas, |morph| &morph.delta_buffer).gpu_buffer.as_entire_binding()
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: weight_buffer.gpu_buffer.as_entire_binding()
+                    },
+                ];
+
+                if let Some(skin) = self.skin.as_ref() {
+                    entries.push(BindGroupEntry {
+                        binding: 1,
+                        resource: skin.joint_buffer.gpu_buffer.as_entire_binding()
+                    });
+                }
+
+                ctx.create_bind_group("model_morph_bind_group", &pipelines.pipeline.bind_group_layouts[1].gpu_bind_group_layout, entries)
+            }).collect();
+
+            self.morph = Some(Morph {
+                weights,
+                weight_buffers,
+                bind_groups,
+            });
+        }
+
+        self.set_pipelines(pipelines);
+    }
+
+    fn set_pipelines(&mut self, pipelines: ModelPipelines) {
+        self.pipeline = pipelines.pipeline;
+        self.gbuffer_pipeline = pipelines.gbuffer_pipeline;
+        self.transparent_pipeline = pipelines.transparent_pipeline;
+        self.oit_pipeline = pipelines.oit_pipeline;
+    }
+
+    /// Swaps in the meshes, skeleton and animations, and the material if the
+    /// model uses it, of its loaded source. Morph target weights go back to
+    /// the new meshes' defaults.
+    pub fn apply_asset(&mut self, ctx: &mut Context, asset: &ModelAsset, version: u32) {
+        let Some(source) = self.source.as_mut() else {
+            return;
+        };
+
+        source.version = Some(version);
+
+        if source.use_material {
+            if let Some(material) = &asset.material {
+                self.material = material.clone();
+            }
+        }
+
+        self.meshes = asset.meshes.clone();
+
+        self.set_skeleton(ctx, asset.skeleton.clone(), asset.animations.clone());
+    }
+
+    /// Picks up pipelines that were rebuilt in the context under the same label,
+    /// e.g. after a shader hot reload.
+    pub fn refresh_pipelines(&mut self, ctx: &Context) {
+        [&mut self.pipeline, &mut self.gbuffer_pipeline, &mut self.transparent_pipeline, &mut self.oit_pipeline].into_iter().for_each(|pipeline| {
+            if let Some(current) = ctx.render_pipelines.get(&pipeline.label) {
+                if !Rc::ptr_eq(current, pipeline) {
+                    *pipeline = current.clone();
+                }
+            }
+        });
+    }
 }
 
 pub struct ModelBuilder<'a> {  
     ctx: &'a mut Context,
     file_path: &'a str,
     transform: Transform,
+    material: Option<Rc<Material>>,
+    meshes: Vec<MeshData>,
+    lod_levels: usize,
+    asset: Option<Handle<ModelAsset>>,
+    material_asset: Option<Handle<Material>>,
 }
 
 impl<'a> ModelBuilder<'a> {
@@ -42,7 +418,12 @@ impl<'a> ModelBuilder<'a> {
         Self {
             ctx,
             file_path,
-            transform: Transform::default()
+            transform: Transform::default(),
+            material: None,
+            meshes: Vec::new(),
+            lod_levels: MAX_LOD_LEVELS,
+            asset: None,
+            material_asset: None,
         }
     }
 
@@ -65,93 +446,65 @@ impl<'a> ModelBuilder<'a> {
         self
     } 
 
+    pub fn with_material(mut self, material: Rc<Material>) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    /// Adds a mesh built on the CPU, e.g. by `primitives`. Models given no
+    /// meshes and no file are a cube.
+    pub fn with_mesh(mut self, mesh: MeshData) -> Self {
+        self.meshes.push(mesh);
+        self
+    }
+
+    /// How many simplified levels of detail to generate for each mesh, at
+    /// most. Zero draws every mesh at full detail.
+    pub fn with_lod_levels(mut self, levels: usize) -> Self {
+        self.lod_levels = levels;
+        self
+    }
+
+    /// Takes the meshes, and the material unless one is given, from a model
+    /// file loading in the background. Until it loads, the model is drawn
+    /// with the meshes it was given, or as a cube.
+    pub fn with_asset(mut self, handle: Handle<ModelAsset>) -> Self {
+        self.asset = Some(handle);
+        self
+    }
+
+    /// Draws the model with a material file loading in the background, in
+    /// place of the file's material.
+    pub fn with_material_asset(mut self, handle: Handle<Material>) -> Self {
+        self.material_asset = Some(handle);
+        self
+    }
+
+    /// Imports `file_path`, if any, on the calling thread. Use `with_asset`
+    /// to load it in the background instead.
     pub fn build(&mut self) -> Model {
-        // let gltf_string = std::fs::read_to_string(self.file_path).expect("Failed to read gltf string");
-        // let gltf_cursor = Cursor::new(gltf_string);
-        // let gltf_reader = BufReader::new(gltf_cursor);
-        // let gltf = Gltf::from_reader(gltf_reader).expect("Failed to read gltf!");
-
-        // let mut buffer_data = Vec::new();
-        // gltf.buffers().into_iter().for_each(|buffer| {
-        //     match buffer.source() {
-        //         gltf::buffer::Source::Bin => {
-
-        //         },
-        //         gltf::buffer::Source::Uri(uri) => {
-        //             let bin = std::fs::read(uri).expect("Failed to read uri");
-        //             buffer_data.push(bin);
-        //         }
-        //     }
-        // });
-
-
-        let mut meshes = Vec::new();
-
-        // for scene in gltf.scenes() {
-        //     for node in scene.nodes() {
-        //         let mut vertices = Vec::new();
-        //         let mut indices = Vec::new();
-
-        //         let mesh = node.mesh().expect("Failed to find mesh in node!");
-
-        //         let primitives = mesh.primitives();
-
-        //         for primitive in primitives {
-        //             let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
-
-        //             if let Some(vertex_attribute) = reader.read_positions() {
-        //                 vertex_attribute.for_each(|position| {
-        //                     vertices.push(Vertex {
-        //                         position: position,
-        //                         normal: Default::default(),
-        //                         uv: Default::default(),
-        //                     })
-        //                 })
-        //             }
-
-        //             if let Some(vertex_attribute) = reader.read_normals() {
-        //                 let mut normal_index = 0;
-        //                 vertex_attribute.for_each(|normal| {
-        //                     vertices.push(Vertex {
-        //                         position: vertices[normal_index].position,
-        //                         normal: normal,
-        //                         uv: vertices[normal_index].uv,
-        //                     });
-
-        //                     normal_index += 1;
-        //                 })
-        //             }
-
-        //             if let Some(vertex_attribute) = reader.read_tex_coords(0).map(|v| v.into_f32()) {
-        //                 let mut uv_index = 0;
-        //                 vertex_attribute.for_each(|uv| {
-        //                     vertices.push(Vertex {
-        //                         position: vertices[uv_index].position,
-        //                         normal: vertices[uv_index].normal,
-        //                         uv: uv,
-        //                     });
-
-        //                     uv_index += 1;
-        //                 })
-        //             }
-    
-        //             if let Some(indices_raw) = reader.read_indices() {
-        //                 indices.append(&mut indices_raw.into_u32().collect::<Vec<u32>>());
-        //             }
-        //         }
-
-        //     }
-        // }
-
-        let vertex_buffer = self.ctx.create_buffer("mesh_vertex_buffer", bytemuck::cast_slice(&CUBE_VERTICES), wgpu::BufferUsages::VERTEX);
-
-        let index_buffer = self.ctx.create_buffer("mesh_vertex_buffer", bytemuck::cast_slice(&CUBE_INDICES), wgpu::BufferUsages::INDEX);
-
-        meshes.push(Mesh {
-            vertex_buffer,
-            index_buffer,
-            num_elements: CUBE_INDICES.len() as u32
-        });
+        let mut imported = None;
+
+        if !self.file_path.is_empty() {
+            imported = Some(PreparedModel::load(&self.ctx.assets, self.file_path, self.lod_levels)
+                .unwrap_or_else(|error| panic!("Failed to import {}: {}", self.file_path, error)));
+        }
+
+        if self.meshes.is_empty() && imported.is_none() {
+            self.meshes.push(primitives::cube(2.0));
+        }
+
+        let mut prepared: Vec<PreparedMesh> = self.meshes.drain(..).map(|data| PreparedMesh::new(data, self.lod_levels)).collect();
+
+        let (imported_material, dependencies, skeleton, animations) = match imported {
+            Some(imported) => {
+                prepared.extend(imported.meshes);
+                (imported.material, imported.dependencies, imported.skeleton, imported.animations)
+            },
+            None => (None, Vec::new(), None, Vec::new()),
+        };
+
+        let meshes = prepared.into_iter().map(|mesh| Mesh::from_prepared(self.ctx, mesh)).collect();
 
         let model = cgmath::Matrix4::<f32>::identity();
 
@@ -165,22 +518,11 @@ impl<'a> ModelBuilder<'a> {
             transform: scaled_model.into()
         }; 
 
-        let cube_transform_buffer = self.ctx.create_buffer("model_transform_buffer", bytemuck::cast_slice(&[cube_uniform]), wgpu::BufferUsages::UNIFORM  | wgpu::BufferUsages::COPY_DST);
+        let transform_buffer = self.ctx.create_buffer("model_transform_buffer", bytemuck::cast_slice(&[cube_uniform]), wgpu::BufferUsages::UNIFORM  | wgpu::BufferUsages::COPY_DST);
 
-        let bind_group_layout = self.ctx.create_bind_group_layout(
-            "cube_transform_bind_group_layout", 
-            vec![
-                BindGroupLayoutEntry {
-                    binding: 0, 
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer { 
-                        ty: wgpu::BufferBindingType::Uniform, 
-                        has_dynamic_offset: false, 
-                        min_binding_size: None
-                    }
-                },
-            ]
-        );
+        let pipelines = ModelPipelines::new(self.ctx, ModelPermutation::default());
+
+        let bind_group_layout = pipelines.pipeline.bind_group_layouts[1].clone();
 
         let bind_group = self.ctx.create_bind_group(
             "cube_transform_bind_group",
@@ -188,51 +530,198 @@ impl<'a> ModelBuilder<'a> {
             vec![
                 BindGroupEntry {
                     binding: 0,
-                    resource: cube_transform_buffer.gpu_buffer.as_entire_binding()
+                    resource: transform_buffer.gpu_buffer.as_entire_binding()
                 },
             ]
         );        
 
-        let cube_shader = self.ctx.create_shader("cube_shader", "./src/assets/shaders/player.wgsl");
-
-        let camera_layout = self.ctx.get_bind_group_layout("camera_bind_group_layout");
-
-        let pipeline_layout = self.ctx.device.logical_device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("cube_pipeline_layout"),
-            bind_group_layouts: &[
-                &camera_layout.gpu_bind_group_layout,
-                &bind_group_layout.gpu_bind_group_layout,
-            ],
-            push_constant_ranges: &[]
-        }); 
-
-        let pipeline = self.ctx.create_render_pipeline(
-            "cube_pipeline",
-            pipeline_layout,
-            &cube_shader.shader,
-            &[Vertex::buffer_layout()],
-            Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL
-            }),
-            Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_compare: wgpu::CompareFunction::Less,
-                depth_write_enabled: true,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            wgpu::PrimitiveTopology::TriangleList,
-            wgpu::PolygonMode::Fill
-        );
+        let material = match &self.material {
+            Some(material) => material.clone(),
+            None => match imported_material {
+                Some(imported) => imported.build(self.ctx, self.file_path),
+                None => MaterialBuilder::new(self.ctx, "default").build(),
+            },
+        };
 
-        Model {
+        let mut model = Model {
             transform: self.transform,
             meshes,
             bind_group_layout,
             bind_group,
-            pipeline
+            transform_buffer,
+            material,
+            pipeline: pipelines.pipeline,
+            gbuffer_pipeline: pipelines.gbuffer_pipeline,
+            transparent_pipeline: pipelines.transparent_pipeline,
+            oit_pipeline: pipelines.oit_pipeline,
+            lod: 0,
+            skin: None,
+            morph: None,
+            source: match self.asset.take() {
+                Some(handle) => Some(ModelSource {
+                    handle: Some(handle),
+                    file_path: String::new(),
+                    dependencies: Vec::new(),
+                    version: None,
+                    use_material: self.material.is_none() && self.material_asset.is_none(),
+                    watched: false,
+                }),
+                // What was imported is the server's version 0, which a failed
+                // reload leaves in place.
+                None if !self.file_path.is_empty() => Some(ModelSource {
+                    handle: None,
+                    file_path: self.file_path.to_string(),
+                    dependencies,
+                    version: Some(0),
+                    use_material: self.material.is_none() && self.material_asset.is_none(),
+                    watched: false,
+                }),
+                None => None,
+            },
+            material_source: self.material_asset.take().map(|handle| MaterialSource {
+                handle,
+                version: None,
+            }),
+        };
+
+        // Also sets up the morph targets of static models.
+        model.set_skeleton(self.ctx, skeleton.map(Rc::new), Rc::new(animations));
+
+        model
+    }
+}
+
+/// The skinned and morphed variants of a shader that draws models. Shaders
+/// including common/skinning.wgsl and common/morph.wgsl read the joints at
+/// group 1 binding 1 when built with `SKINNED`, and the morph target deltas
+/// and weights at bindings 2 and 3 with `MORPHED`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ModelPermutation {
+    pub skinned: bool,
+    pub morphed: bool,
+}
+
+impl ModelPermutation {
+    pub const ALL: [ModelPermutation; 4] = [
+        ModelPermutation { skinned: false, morphed: false },
+        ModelPermutation { skinned: true, morphed: false },
+        ModelPermutation { skinned: false, morphed: true },
+        ModelPermutation { skinned: true, morphed: true },
+    ];
+
+    pub fn defines(&self) -> Defines {
+        let mut defines = Defines::new();
+
+        if self.skinned {
+            defines.insert("SKINNED".to_string(), String::new());
+        }
+
+        if self.morphed {
+            defines.insert("MORPHED".to_string(), String::new());
+        }
+
+        defines
+    }
+
+    pub fn vertex_layout(&self) -> wgpu::VertexBufferLayout<'static> {
+        if self.skinned { Vertex::skinned_buffer_layout() } else { Vertex::buffer_layout() }
+    }
+
+    /// The label of the permutation of pipeline `name`, e.g.
+    /// `cube_skinned_morphed_pipeline`.
+    pub fn pipeline_label(&self, name: &str) -> String {
+        format!(
+            "{}{}{}_pipeline",
+            name,
+            if self.skinned { "_skinned" } else { "" },
+            if self.morphed { "_morphed" } else { "" }
+        )
+    }
+}
+
+/// The pipelines a model draws with in each pass.
+struct ModelPipelines {
+    pipeline: Rc<RenderPipeline>,
+    gbuffer_pipeline: Rc<RenderPipeline>,
+    transparent_pipeline: Rc<RenderPipeline>,
+    oit_pipeline: Rc<RenderPipeline>,
+}
+
+impl ModelPipelines {
+    /// Skinned pipelines read the joints and weights of the vertices, and the
+    /// joint matrices at group 1. Morphed pipelines read the morph target
+    /// deltas and weights of each mesh at group 1.
+    fn new(ctx: &mut Context, permutation: ModelPermutation) -> Self {
+        let defines = permutation.defines();
+
+        let vertex_layout = permutation.vertex_layout();
+
+        let cube_shader = ctx.create_shader_permutation("cube_shader", "shaders/player.wgsl", &defines);
+
+        let gbuffer_shader = ctx.create_shader_permutation("gbuffer_shader", "shaders/gbuffer.wgsl", &defines);
+
+        [&cube_shader, &gbuffer_shader].into_iter().for_each(|shader| {
+            let validation = shader.reflection.validate_vertex_layout("vs_main", std::slice::from_ref(&vertex_layout))
+                .and_then(|_| shader.reflection.validate_uniform::<TransformUniform>(1, 0))
+                .and_then(|_| shader.reflection.validate_uniform::<MaterialUniform>(2, 0));
+
+            if let Err(error) = validation {
+                panic!("{} does not match the model's vertex or uniform layouts: {}", shader.code_path, error);
+            }
+
+            if permutation.morphed {
+                let morph_validation = shader.reflection.validate_uniform::<MorphDelta>(1, 2)
+                    .and_then(|_| shader.reflection.validate_uniform::<MorphWeightsUniform>(1, 3));
+
+                if let Err(error) = morph_validation {
+                    panic!("{} does not match the morph target layouts: {}", shader.code_path, error);
+                }
+            }
+        });
+
+        let lights_validation = cube_shader.reflection.validate_uniform::<SceneLightingUniform>(3, 0)
+            .and_then(|_| cube_shader.reflection.validate_uniform::<PointLightUniform>(3, 1));
+
+        if let Err(error) = lights_validation {
+            panic!("{} does not match the lighting layouts: {}", cube_shader.code_path, error);
+        }
+
+        let pipeline = RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("cube"), &cube_shader)
+            .with_vertex_buffers(std::slice::from_ref(&vertex_layout))
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Replace)
+            .with_color_target(PICKING_ID_FORMAT, BlendPreset::Disabled)
+            .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::Less, true)
+            .build();
+
+        let gbuffer_pipeline = RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("cube_gbuffer"), &gbuffer_shader)
+            .with_vertex_buffers(std::slice::from_ref(&vertex_layout))
+            .with_color_target_states(&gbuffer_color_targets())
+            .with_color_target(PICKING_ID_FORMAT, BlendPreset::Disabled)
+            .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::Less, true)
+            .build();
+
+        let transparent_pipeline = RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("cube_transparent"), &cube_shader)
+            .with_vertex_buffers(std::slice::from_ref(&vertex_layout))
+            .with_color_target(WORLD_COLOR_FORMAT, BlendPreset::Alpha)
+            .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::Less, false)
+            .build();
+
+        let mut oit_defines = defines.clone();
+        oit_defines.insert("OIT".to_string(), String::new());
+
+        let oit_shader = ctx.create_shader_permutation("cube_shader", "shaders/player.wgsl", &oit_defines);
+
+        let oit_pipeline = RenderPipelineBuilder::new(ctx, &permutation.pipeline_label("cube_oit"), &oit_shader)
+            .with_vertex_buffers(std::slice::from_ref(&vertex_layout))
+            .with_color_target_states(&oit_color_targets())
+            .with_depth(WORLD_DEPTH_FORMAT, wgpu::CompareFunction::Less, false)
+            .build();
+
+        Self {
+            pipeline,
+            gbuffer_pipeline,
+            transparent_pipeline,
+            oit_pipeline,
         }
     }
 }
@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use crate::{assets::source::AssetResolver, graphics::vertex_input::Vertex};
+
+use super::{import::{relative_path, triangulate, ImportedMaterial, ImportedModel, MeshAssembler}, material::AlphaMode};
+
+
+/// A corner of a face: indices into the positions, UVs and normals, which OBJ
+/// keeps separate.
+#[derive(Debug, Clone, Copy)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Imports a Wavefront OBJ file and the materials of its `mtllib`s.
+///
+/// Polygons are triangulated and every material used gets its own meshes.
+/// Models have a single material, so that of the first face is returned.
+/// Missing normals are left zeroed, for `mesh_processing::process` to
+/// generate.
+pub fn import_obj(assets: &AssetResolver, path: &str) -> Result<ImportedModel, String> {
+    let source = assets.read_to_string(path)?;
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    let mut materials: HashMap<String, ImportedMaterial> = HashMap::new();
+    let mut libraries: Vec<String> = Vec::new();
+    let mut first_material: Option<String> = None;
+
+    // Faces by material, in the order the materials are first used.
+    let mut assemblers: Vec<(Option<String>, MeshAssembler)> = Vec::new();
+    let mut current_material: Option<String> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message: String| format!("{}:{}: {}", path, line_index + 1, message);
+
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_floats(&arguments, 3).map_err(error)?),
+            // V runs up the image in OBJ and down in ours.
+            "vt" => {
+                let [u, v] = parse_floats(&arguments, 1).map_err(error)?;
+                uvs.push([u, 1.0 - v]);
+            },
+            "vn" => normals.push(parse_floats(&arguments, 3).map_err(error)?),
+            "f" => {
+                let corners = arguments.iter()
+                    .map(|corner| parse_face_vertex(corner, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                if corners.len() < 3 {
+                    return Err(error(format!("A face needs at least 3 vertices, not {}", corners.len())));
+                }
+
+                if first_material.is_none() {
+                    first_material = current_material.clone();
+                }
+
+                let assembler = match assemblers.iter().position(|(material, _)| *material == current_material) {
+                    Some(index) => &mut assemblers[index].1,
+                    None => {
+                        assemblers.push((current_material.clone(), MeshAssembler::default()));
+                        &mut assemblers.last_mut().expect("An assembler was just added").1
+                    }
+                };
+
+                let points: Vec<cgmath::Point3<f32>> = corners.iter().map(|corner| positions[corner.position].into()).collect();
+
+                triangulate(&points).into_iter().for_each(|triangle| {
+                    assembler.push_triangle(triangle.map(|corner| {
+                        let corner = corners[corner];
+
+                        Vertex {
+                            position: positions[corner.position],
+                            normal: corner.normal.map_or([0.0; 3], |normal| normals[normal]),
+                            uv: corner.uv.map_or([0.0; 2], |uv| uvs[uv]),
+                            tangent: [0.0; 4],
+                            joints: [0; 4],
+                            weights: [0.0; 4],
+                        }
+                    }));
+                });
+            },
+            "usemtl" => current_material = arguments.first().map(|name| name.to_string()),
+            "mtllib" => for name in arguments {
+                let library = relative_path(path, name);
+
+                match assets.read_to_string(&library) {
+                    Ok(source) => materials.extend(parse_mtl(&library, &source).map_err(|message| format!("{}: {}", library, message))?),
+                    Err(message) => log::warn!("Ignoring the materials of {}: {}", path, message),
+                }
+
+                // Missing ones too, so that creating them reloads the model.
+                libraries.push(library);
+            },
+            // Groups, objects, smoothing groups and the rest don't change
+            // what is drawn.
+            _ => {},
+        }
+    }
+
+    let material = first_material.and_then(|name| {
+        let material = materials.get(&name).cloned();
+
+        if material.is_none() {
+            log::warn!("{} uses the undefined material {}", path, name);
+        }
+
+        material
+    });
+
+    Ok(ImportedModel {
+        meshes: assemblers.into_iter().flat_map(|(_, assembler)| assembler.finish()).collect(),
+        material,
+        skeleton: None,
+        animations: Vec::new(),
+        dependencies: libraries,
+    })
+}
+
+/// The first material of the MTL library at `path`, which is how material
+/// files are loaded on their own.
+pub fn import_material(assets: &AssetResolver, path: &str) -> Result<ImportedMaterial, String> {
+    let source = assets.read_to_string(path)?;
+
+    let mut materials = parse_mtl(path, &source).map_err(|message| format!("{}: {}", path, message))?;
+
+    let first = source.lines().find_map(|line| {
+        let mut tokens = line.split('#').next().unwrap_or_default().split_whitespace();
+
+        (tokens.next() == Some("newmtl")).then(|| tokens.collect::<Vec<&str>>().join(" "))
+    });
+
+    first.and_then(|name| materials.remove(&name)).ok_or_else(|| format!("{} defines no material", path))
+}
+
+/// Parses the first `N` arguments, of which at least `required` must be
+/// given. Missing ones are zero.
+fn parse_floats<const N: usize>(arguments: &[&str], required: usize) -> Result<[f32; N], String> {
+    if arguments.len() < required {
+        return Err(format!("Expected {} numbers, found {}", required, arguments.len()));
+    }
+
+    let mut values = [0.0; N];
+
+    for (value, argument) in values.iter_mut().zip(arguments) {
+        *value = argument.parse().map_err(|_| format!("{} is not a number", argument))?;
+    }
+
+    Ok(values)
+}
+
+/// Parses `p`, `p/t`, `p//n` or `p/t/n`. Indices start at 1, and negative ones
+/// count back from the last element defined so far.
+fn parse_face_vertex(corner: &str, position_count: usize, uv_count: usize, normal_count: usize) -> Result<FaceVertex, String> {
+    let mut parts = corner.split('/');
+
+    let resolve = |part: Option<&str>, count: usize, kind: &str| -> Result<Option<usize>, String> {
+        let Some(part) = part.filter(|part| !part.is_empty()) else {
+            return Ok(None);
+        };
+
+        let index: i64 = part.parse().map_err(|_| format!("{} is not a {} index", part, kind))?;
+
+        let resolved = match index {
+            index if index > 0 => index - 1,
+            index if index < 0 => count as i64 + index,
+            _ => return Err(format!("{} indices start at 1", kind)),
+        };
+
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!("{} index {} is out of range, there are {}", kind, index, count));
+        }
+
+        Ok(Some(resolved as usize))
+    };
+
+    Ok(FaceVertex {
+        position: resolve(parts.next(), position_count, "Position")?.ok_or("A face vertex has no position")?,
+        uv: resolve(parts.next(), uv_count, "UV")?,
+        normal: resolve(parts.next(), normal_count, "Normal")?,
+    })
+}
+
+/// Maps the Phong-style parameters of an MTL file onto our metallic-roughness
+/// materials. The PBR extension's `Pr` and `Pm` are used when present, and
+/// otherwise roughness comes from the specular exponent `Ns`. Of the texture
+/// maps, only `map_Kd` is kept, as the base color texture, relative to the
+/// library at `path`.
+fn parse_mtl(path: &str, source: &str) -> Result<HashMap<String, ImportedMaterial>, String> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, ImportedMaterial)> = None;
+    let mut has_pbr_roughness = false;
+
+    let default_material = ImportedMaterial {
+        base_color: [1.0; 4],
+        metallic: 0.0,
+        roughness: 1.0,
+        alpha_mode: AlphaMode::Opaque,
+        base_color_texture: None,
+    };
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message: String| format!("line {}: {}", line_index + 1, message);
+
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            materials.extend(current.take());
+            current = Some((arguments.join(" "), default_material.clone()));
+            has_pbr_roughness = false;
+            continue;
+        }
+
+        let Some((_, material)) = current.as_mut() else {
+            continue;
+        };
+
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = parse_floats(&arguments, 3).map_err(error)?;
+                material.base_color = [r, g, b, material.base_color[3]];
+            },
+            "d" => {
+                let [opacity] = parse_floats(&arguments, 1).map_err(error)?;
+                material.base_color[3] = opacity;
+            },
+            "Tr" => {
+                let [transparency] = parse_floats(&arguments, 1).map_err(error)?;
+                material.base_color[3] = 1.0 - transparency;
+            },
+            // The Blinn-Phong exponent for the same highlight width as a GGX
+            // roughness r is about 2 / r^4 - 2.
+            "Ns" if !has_pbr_roughness => {
+                let [exponent] = parse_floats(&arguments, 1).map_err(error)?;
+                material.roughness = (2.0 / (exponent.max(0.0) + 2.0)).powf(0.25);
+            },
+            "Pr" => {
+                material.roughness = parse_floats::<1>(&arguments, 1).map_err(error)?[0];
+                has_pbr_roughness = true;
+            },
+            "Pm" => material.metallic = parse_floats::<1>(&arguments, 1).map_err(error)?[0],
+            // Options such as -s come before the file name.
+            "map_Kd" => material.base_color_texture = arguments.last().map(|name| relative_path(path, name)),
+            _ => {},
+        }
+    }
+
+    materials.extend(current);
+
+    materials.values_mut().for_each(|material| {
+        if material.base_color[3] < 1.0 {
+            material.alpha_mode = AlphaMode::Blend;
+        }
+    });
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use cgmath::InnerSpace;
+
+    use crate::world::mesh_processing;
+
+    use super::*;
+
+    fn fixtures() -> AssetResolver {
+        AssetResolver::embedded().with_root_directory(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"))
+    }
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).expect("The fixture exists")
+    }
+
+    /// Imports `model.obj` from a directory of its own holding `files`.
+    fn import_files(name: &str, files: &[(&str, &str)]) -> Result<ImportedModel, String> {
+        let directory: PathBuf = std::env::temp_dir().join(format!("phoenix_obj_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&directory).expect("Failed to create the test directory");
+
+        for (file, source) in files {
+            std::fs::write(directory.join(file), source).expect("Failed to write the test file");
+        }
+
+        let result = import_obj(&AssetResolver::embedded().with_root_directory(&directory), "model.obj");
+
+        std::fs::remove_dir_all(&directory).ok();
+
+        result
+    }
+
+    #[test]
+    fn polygons_are_triangulated_per_material() {
+        let model = import_obj(&fixtures(), "quad.obj").expect("The fixture imports");
+
+        let index_counts: Vec<usize> = model.meshes.iter().map(|mesh| mesh.indices.len()).collect();
+        assert_eq!(index_counts, vec![6, 9]);
+
+        assert_eq!(model.meshes[0].vertices.len(), 4);
+        assert_eq!(model.meshes[1].vertices.len(), 5);
+        assert!(model.meshes.iter().flat_map(|mesh| &mesh.vertices).all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+
+        assert_eq!(model.dependencies, vec!["quad.mtl".to_string()]);
+    }
+
+    #[test]
+    fn first_material_comes_from_the_mtl() {
+        let model = import_obj(&fixtures(), "quad.obj").expect("The fixture imports");
+
+        assert_eq!(model.material, Some(ImportedMaterial {
+            base_color: [0.8, 0.2, 0.1, 0.5],
+            metallic: 0.9,
+            // Pr wins over the Ns that follows it.
+            roughness: 0.3,
+            alpha_mode: AlphaMode::Blend,
+            base_color_texture: Some("textures/painted.png".to_string()),
+        }));
+    }
+
+    #[test]
+    fn mtl_roughness_from_exponent() {
+        let materials = parse_mtl("quad.mtl", &fixture("quad.mtl")).expect("The fixture parses");
+        let shiny = &materials["shiny"];
+
+        assert_eq!(shiny.base_color, [1.0; 4]);
+        assert_eq!(shiny.metallic, 0.0);
+        assert!((shiny.roughness - (2.0f32 / 1002.0).powf(0.25)).abs() < 1e-6);
+        assert_eq!(shiny.alpha_mode, AlphaMode::Opaque);
+        assert_eq!(shiny.base_color_texture, None);
+    }
+
+    #[test]
+    fn material_files_give_their_first_material() {
+        let material = import_material(&fixtures(), "quad.mtl").expect("The fixture imports");
+
+        assert_eq!(material.base_color, [0.8, 0.2, 0.1, 0.5]);
+        assert!(import_material(&fixtures(), "missing.mtl").is_err());
+    }
+
+    #[test]
+    fn mtl_textures_are_relative_to_the_library() {
+        let materials = parse_mtl("models/materials/library.mtl", "newmtl a\nmap_Kd -s 2 2 1 ../textures/a.png\n").expect("The library parses");
+
+        assert_eq!(materials["a"].base_color_texture.as_deref().map(Path::new), Some(Path::new("models/materials/../textures/a.png")));
+    }
+
+    #[test]
+    fn split_corners_are_separate_vertices() {
+        let mut model = import_obj(&fixtures(), "seams.obj").expect("The fixture imports");
+
+        assert_eq!(model.meshes.len(), 1);
+
+        let mesh = &mut model.meshes[0];
+
+        assert_eq!(mesh.indices.len(), 18);
+        // The edge shared with different UVs is split, the one with the same
+        // UVs isn't.
+        assert_eq!(mesh.vertices.len(), 10);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == [0.0; 3]));
+
+        mesh_processing::process(mesh);
+
+        assert!(mesh.vertices.iter().all(|vertex| (cgmath::Vector3::from(vertex.normal) - cgmath::Vector3::unit_z()).magnitude() < 1e-5));
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let vertex = parse_face_vertex("-1/-2/-3", 4, 3, 3).expect("The indices are in range");
+
+        assert_eq!((vertex.position, vertex.uv, vertex.normal), (3, Some(1), Some(0)));
+        assert_eq!(parse_face_vertex("2//1", 4, 0, 1).map(|vertex| vertex.uv), Ok(None));
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+        let cases = [
+            ("short_position", "v 1 2\n".to_string()),
+            ("bad_number", "v 1 x 2\n".to_string()),
+            ("bad_uv", "vt x\n".to_string()),
+            ("two_corners", format!("{}f 1 2\n", triangle)),
+            ("out_of_range", format!("{}f 1 2 4\n", triangle)),
+            ("zero_index", format!("{}f 0 1 2\n", triangle)),
+            ("negative_out_of_range", format!("{}f -1 -2 -4\n", triangle)),
+            ("missing_uv", format!("{}f 1/1 2/1 3/1\n", triangle)),
+            ("missing_normal", format!("{}f 1//1 2//1 3//1\n", triangle)),
+            ("bad_index", format!("{}f 1 2 a\n", triangle)),
+            ("no_position", format!("{}f /1 2 3\n", triangle)),
+        ];
+
+        for (name, source) in cases {
+            assert!(import_files(name, &[("model.obj", &source)]).is_err(), "{} imported", name);
+        }
+
+        let library = format!("mtllib model.mtl\n{}", triangle);
+        assert!(import_files("bad_mtl", &[("model.obj", &library), ("model.mtl", "newmtl a\nKd 1 x 1\n")]).is_err());
+    }
+
+    #[test]
+    fn truncated_input_does_not_panic() {
+        let model = fixture("quad.obj");
+        let library = fixture("quad.mtl");
+
+        for length in 0..model.len() {
+            let _ = import_files(&format!("obj_{}", length), &[("model.obj", &model[..length]), ("quad.mtl", &library)]);
+        }
+
+        for length in 0..library.len() {
+            let _ = import_files(&format!("mtl_{}", length), &[("model.obj", &model), ("quad.mtl", &library[..length])]);
+        }
+    }
+}
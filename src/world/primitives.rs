@@ -0,0 +1,479 @@
+use std::f32::consts::{PI, TAU};
+
+use cgmath::InnerSpace;
+
+use crate::graphics::vertex_input::Vertex;
+
+use super::world::{vertex_index, MeshData, MorphTargets};
+
+
+// Generated meshes are centred on the origin with y up. Their UVs are laid out
+// so that a texture reads upright from outside, u to the right and v down,
+// and front faces wind counter-clockwise.
+
+/// The most vertices 16-bit indices can address.
+const MAX_VERTICES: u64 = u16::MAX as u64 + 1;
+
+/// Lowers `columns` and `rows`, the cells along each side of a grid, so that
+/// its `(columns + 1) * (rows + 1)` vertices fit in 16-bit indices. The larger
+/// side is lowered first, down to the smaller one, then both evenly.
+fn fit_grid(columns: u32, rows: u32) -> (u32, u32) {
+    let (width, height) = (columns as u64 + 1, rows as u64 + 1);
+
+    if width.saturating_mul(height) <= MAX_VERTICES {
+        return (columns, rows);
+    }
+
+    let square = MAX_VERTICES.isqrt();
+    let small = width.min(height).min(square);
+    let large = MAX_VERTICES / small;
+
+    let (width, height) = if width <= height { (small, large) } else { (large, small) };
+
+    (width as u32 - 1, height as u32 - 1)
+}
+
+/// A point of the profile a surface of revolution is swept from.
+#[derive(Debug, Clone, Copy)]
+struct ProfilePoint {
+    /// Distance from the y axis.
+    radius: f32,
+    y: f32,
+    /// Outward normal in the (radius, y) plane.
+    normal: cgmath::Vector2<f32>,
+    v: f32,
+}
+
+/// Sweeps `profile`, ordered from top to bottom as seen from outside, around
+/// the y axis. The seam is at -z, so u is 0.5 on the +z side.
+fn revolve(profile: &[ProfilePoint], segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let columns = segments + 1;
+
+    // Poles computed as sin(PI) land a hair off the axis. Snapping them onto
+    // it makes their triangles collapse, so `grid_indices` drops them.
+    let max_radius = profile.iter().fold(0.0f32, |max, point| max.max(point.radius.abs()));
+
+    let vertices = profile.iter().flat_map(|point| {
+        let point = ProfilePoint {
+            radius: if point.radius.abs() <= max_radius * 1e-6 { 0.0 } else { point.radius },
+            ..*point
+        };
+
+        (0..columns).map(move |column| {
+            let u = column as f32 / segments as f32;
+            let (sin, cos) = ((u - 0.5) * TAU).sin_cos();
+
+            Vertex {
+                position: [point.radius * sin, point.y, point.radius * cos],
+                normal: [point.normal.x * sin, point.normal.y, point.normal.x * cos],
+                uv: [u, point.v],
+                tangent: [cos, 0.0, -sin, 1.0],
+                joints: [0; 4],
+                weights: [0.0; 4],
+            }
+        })
+    }).collect();
+
+    let mut data = MeshData {
+        vertices,
+        indices: Vec::new(),
+        morph: MorphTargets::default(),
+    };
+
+    data.indices = grid_indices(&data.vertices, columns, profile.len() as u32);
+
+    data
+}
+
+/// Two triangles per cell of a grid of `columns` by `rows` vertices, stored
+/// row by row with u increasing to the right and v downwards. Triangles that
+/// collapse to a point or a line, such as those at the poles of a sphere, are
+/// left out.
+fn grid_indices(vertices: &[Vertex], columns: u32, rows: u32) -> Vec<u16> {
+    let index = |column: u32, row: u32| (row * columns + column) as usize;
+
+    (0..rows.saturating_sub(1)).flat_map(|row| {
+        (0..columns - 1).flat_map(move |column| {
+            let top_left = index(column, row);
+            let top_right = index(column + 1, row);
+            let bottom_left = index(column, row + 1);
+            let bottom_right = index(column + 1, row + 1);
+
+            [[top_left, bottom_left, top_right], [top_right, bottom_left, bottom_right]]
+        })
+    })
+    .filter(|triangle| {
+        let [a, b, c] = triangle.map(|index| cgmath::Vector3::from(vertices[index].position));
+
+        (b - a).cross(c - a).magnitude2() > 0.0
+    })
+    .flatten()
+    .map(vertex_index)
+    .collect()
+}
+
+/// A grid of quads from `origin` along `right` and `down`, which span the
+/// whole face, with UVs covering 0..1.
+fn quad_grid(origin: cgmath::Vector3<f32>, right: cgmath::Vector3<f32>, down: cgmath::Vector3<f32>, columns: u32, rows: u32) -> MeshData {
+    let (columns, rows) = fit_grid(columns.max(1), rows.max(1));
+    let (columns, rows) = (columns + 1, rows + 1);
+    let normal = down.cross(right).normalize();
+    let tangent = right.normalize();
+
+    let vertices = (0..rows).flat_map(|row| {
+        (0..columns).map(move |column| {
+            let u = column as f32 / (columns - 1) as f32;
+            let v = row as f32 / (rows - 1) as f32;
+
+            Vertex {
+                position: (origin + right * u + down * v).into(),
+                normal: normal.into(),
+                uv: [u, v],
+                tangent: tangent.extend(1.0).into(),
+                joints: [0; 4],
+                weights: [0.0; 4],
+            }
+        })
+    }).collect();
+
+    let mut data = MeshData {
+        vertices,
+        indices: Vec::new(),
+        morph: MorphTargets::default(),
+    };
+
+    data.indices = grid_indices(&data.vertices, columns, rows);
+
+    data
+}
+
+/// A disk in the y = `y` plane facing up or down, mapped to the square
+/// around it the way a plane facing the same way would be.
+fn disk(radius: f32, y: f32, segments: u32, up: bool) -> MeshData {
+    let segments = segments.max(3);
+    let facing = if up { 1.0 } else { -1.0 };
+
+    // Seen from above, +z points down the texture; from below, up.
+    let vertex = |x: f32, z: f32| Vertex {
+        position: [x, y, z],
+        normal: [0.0, facing, 0.0],
+        uv: [0.5 + x / (2.0 * radius), 0.5 + facing * z / (2.0 * radius)],
+        tangent: [1.0, 0.0, 0.0, 1.0],
+        joints: [0; 4],
+        weights: [0.0; 4],
+    };
+
+    let mut vertices = vec![vertex(0.0, 0.0)];
+
+    vertices.extend((0..segments).map(|segment| {
+        let (sin, cos) = ((segment as f32 / segments as f32 - 0.5) * TAU).sin_cos();
+
+        vertex(radius * sin, radius * cos)
+    }));
+
+    let indices = (0..segments).flat_map(|segment| {
+        let current = 1 + segment as usize;
+        let next = 1 + ((segment + 1) % segments) as usize;
+
+        if up { [0, current, next] } else { [0, next, current] }
+    })
+    .map(vertex_index)
+    .collect();
+
+    MeshData {
+        vertices,
+        indices,
+        morph: MorphTargets::default(),
+    }
+}
+
+/// A sphere of `rings` bands of latitude and `segments` of longitude.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    let (segments, rings) = fit_grid(segments.max(3), rings.max(2));
+
+    let profile: Vec<_> = (0..=rings).map(|ring| {
+        let v = ring as f32 / rings as f32;
+        let (sin, cos) = (v * PI).sin_cos();
+
+        ProfilePoint {
+            radius: radius * sin,
+            y: radius * cos,
+            normal: cgmath::Vector2::new(sin, cos),
+            v,
+        }
+    }).collect();
+
+    revolve(&profile, segments)
+}
+
+/// A sphere made by splitting each face of an icosahedron into four,
+/// `subdivisions` times, for evenly sized triangles. UVs are spherical like
+/// those of `uv_sphere`, with vertices duplicated along the seam.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    // Each subdivision quadruples the faces; six keeps within 16-bit indices.
+    let subdivisions = subdivisions.min(6);
+
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+
+    let mut positions: Vec<cgmath::Vector3<f32>> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ].into_iter().map(|position| cgmath::Vector3::from(position).normalize()).collect();
+
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = std::collections::HashMap::new();
+
+        let mut midpoint = |a: usize, b: usize, positions: &mut Vec<cgmath::Vector3<f32>>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a] + positions[b]).normalize());
+                positions.len() - 1
+            })
+        };
+
+        faces = faces.into_iter().flat_map(|[a, b, c]| {
+            let ab = midpoint(a, b, &mut positions);
+            let bc = midpoint(b, c, &mut positions);
+            let ca = midpoint(c, a, &mut positions);
+
+            [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+
+    let vertex = |position: cgmath::Vector3<f32>, u: f32| {
+        let (sin, cos) = ((u - 0.5) * TAU).sin_cos();
+
+        Vertex {
+            position: (position * radius).into(),
+            normal: position.into(),
+            uv: [u, position.y.clamp(-1.0, 1.0).acos() / PI],
+            tangent: [cos, 0.0, -sin, 1.0],
+            joints: [0; 4],
+            weights: [0.0; 4],
+        }
+    };
+
+    let longitude = |position: cgmath::Vector3<f32>| 0.5 + position.x.atan2(position.z) / TAU;
+    let is_pole = |position: cgmath::Vector3<f32>| position.x.abs() < 1e-6 && position.z.abs() < 1e-6;
+
+    let mut data = MeshData {
+        vertices: positions.iter().map(|position| vertex(*position, longitude(*position))).collect(),
+        indices: Vec::new(),
+        morph: MorphTargets::default(),
+    };
+
+    faces.into_iter().for_each(|face| {
+        let mut us = face.map(|index| longitude(positions[index]));
+
+        // Faces across the seam get copies of their vertices on the low side
+        // moved past u = 1, so the texture doesn't wrap back over the face.
+        let crosses_seam = face.iter().any(|index| !is_pole(positions[*index]))
+            && us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min) > 0.5;
+
+        if crosses_seam {
+            us.iter_mut().for_each(|u| if *u < 0.5 { *u += 1.0 });
+        }
+
+        // The u of a pole is arbitrary, so each face gets its own copy in
+        // the middle of the other two, which keeps the texture from twisting.
+        let pole_u = face.iter().zip(us).filter(|(index, _)| !is_pole(positions[**index])).map(|(_, u)| u).sum::<f32>() / 2.0;
+
+        face.iter().zip(us).for_each(|(index, u)| {
+            let position = positions[*index];
+
+            let index = if is_pole(position) {
+                data.vertices.push(vertex(position, pole_u));
+                data.vertices.len() - 1
+            } else if crosses_seam && u > 1.0 {
+                data.vertices.push(vertex(position, u));
+                data.vertices.len() - 1
+            } else {
+                *index
+            };
+
+            data.indices.push(vertex_index(index));
+        });
+    });
+
+    data
+}
+
+/// A `width` by `depth` plane in the y = 0 plane facing up, split into
+/// `subdivisions_x` by `subdivisions_z` quads. From above, u runs along +x
+/// and v along +z.
+pub fn plane(width: f32, depth: f32, subdivisions_x: u32, subdivisions_z: u32) -> MeshData {
+    quad_grid(
+        cgmath::Vector3::new(-width / 2.0, 0.0, -depth / 2.0),
+        cgmath::Vector3::new(width, 0.0, 0.0),
+        cgmath::Vector3::new(0.0, 0.0, depth),
+        subdivisions_x,
+        subdivisions_z,
+    )
+}
+
+/// A cube with each face mapped to the whole texture, upright on the sides.
+pub fn cube(size: f32) -> MeshData {
+    let half = size / 2.0;
+
+    // Top left corner, right and down of each face, seen from outside.
+    let faces = [
+        ((-half, half, half), (size, 0.0, 0.0), (0.0, -size, 0.0)),
+        ((half, half, -half), (-size, 0.0, 0.0), (0.0, -size, 0.0)),
+        ((half, half, half), (0.0, 0.0, -size), (0.0, -size, 0.0)),
+        ((-half, half, -half), (0.0, 0.0, size), (0.0, -size, 0.0)),
+        ((-half, half, -half), (size, 0.0, 0.0), (0.0, 0.0, size)),
+        ((-half, -half, half), (size, 0.0, 0.0), (0.0, 0.0, -size)),
+    ];
+
+    faces.into_iter().fold(MeshData::default(), |mut data, (origin, right, down)| {
+        data.extend(&quad_grid(origin.into(), right.into(), down.into(), 1, 1));
+        data
+    })
+}
+
+/// A capped cylinder of `height` along y.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    // The side and caps each have two rings of vertices.
+    let (segments, _) = fit_grid(segments.max(3), 3);
+    let half = height / 2.0;
+    let side = cgmath::Vector2::new(1.0, 0.0);
+
+    let mut data = revolve(&[
+        ProfilePoint { radius, y: half, normal: side, v: 0.0 },
+        ProfilePoint { radius, y: -half, normal: side, v: 1.0 },
+    ], segments);
+
+    data.extend(&disk(radius, half, segments, true));
+    data.extend(&disk(radius, -half, segments, false));
+
+    data
+}
+
+/// A cone of `height` along y with its tip up and its base capped.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    // The side has two rings of vertices and the base one.
+    let (segments, _) = fit_grid(segments.max(3), 2);
+    let half = height / 2.0;
+    let normal = cgmath::Vector2::new(height, radius).normalize();
+
+    let mut data = revolve(&[
+        ProfilePoint { radius: 0.0, y: half, normal, v: 0.0 },
+        ProfilePoint { radius, y: -half, normal, v: 1.0 },
+    ], segments);
+
+    data.extend(&disk(radius, -half, segments, false));
+
+    data
+}
+
+/// A torus around the y axis, `major_radius` from its centre to the middle
+/// of the tube. v runs around the tube, starting on the outside.
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, tube_segments: u32) -> MeshData {
+    let (segments, tube_segments) = fit_grid(segments.max(3), tube_segments.max(3));
+
+    let profile: Vec<_> = (0..=tube_segments).map(|segment| {
+        let v = segment as f32 / tube_segments as f32;
+        let (sin, cos) = (v * TAU).sin_cos();
+
+        ProfilePoint {
+            radius: major_radius + minor_radius * cos,
+            y: -minor_radius * sin,
+            normal: cgmath::Vector2::new(cos, -sin),
+            v,
+        }
+    }).collect();
+
+    revolve(&profile, segments)
+}
+
+/// A cylinder of `height` along y with hemispheres of `radius` on both ends,
+/// so `height + 2 * radius` tall. v is proportional to the distance along
+/// the surface, so the texture isn't stretched over the caps.
+pub fn capsule(radius: f32, height: f32, segments: u32, cap_rings: u32) -> MeshData {
+    // Each cap has `cap_rings + 1` rings of vertices.
+    let (segments, rows) = fit_grid(segments.max(3), cap_rings.max(1).saturating_mul(2).saturating_add(1));
+    let cap_rings = rows.div_ceil(2) - 1;
+    let half = height / 2.0;
+    let length = PI * radius + height;
+
+    let cap = |from: u32, to: u32, center: f32, arc_start: f32| (from..=to).map(move |ring| {
+        let angle = ring as f32 / cap_rings as f32 * PI / 2.0;
+        let (sin, cos) = angle.sin_cos();
+
+        ProfilePoint {
+            radius: radius * sin,
+            y: center + radius * cos,
+            normal: cgmath::Vector2::new(sin, cos),
+            v: (arc_start + radius * angle) / length,
+        }
+    });
+
+    // Angles of the bottom cap carry on from the equator, so its arc length
+    // starts from where the top cap's ends, plus the straight part.
+    let profile: Vec<_> = cap(0, cap_rings, half, 0.0)
+        .chain(cap(cap_rings, 2 * cap_rings, -half, height))
+        .collect();
+
+    revolve(&profile, segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_addressable(data: &MeshData) {
+        assert!(data.vertices.len() as u64 <= MAX_VERTICES, "{} vertices", data.vertices.len());
+        assert!(data.indices.iter().all(|index| (*index as usize) < data.vertices.len()));
+    }
+
+    #[test]
+    fn grids_within_the_limit_are_kept() {
+        assert_eq!(fit_grid(255, 255), (255, 255));
+        assert_eq!(fit_grid(3, 16383), (3, 16383));
+        assert_eq!(fit_grid(1, 1), (1, 1));
+    }
+
+    #[test]
+    fn grids_over_the_limit_are_fitted() {
+        // The larger side goes first.
+        assert_eq!(fit_grid(3, 20000), (3, 16383));
+        assert_eq!(fit_grid(40000, 1), (32767, 1));
+        assert_eq!(fit_grid(256, 256), (255, 255));
+        assert_eq!(fit_grid(u32::MAX, u32::MAX), (255, 255));
+    }
+
+    #[test]
+    fn primitives_at_the_limit() {
+        let sphere = uv_sphere(1.0, 255, 255);
+        assert_eq!(sphere.vertices.len(), 65536);
+        assert_addressable(&sphere);
+
+        let plane = plane(1.0, 1.0, 255, 255);
+        assert_eq!(plane.vertices.len(), 65536);
+        assert_addressable(&plane);
+    }
+
+    #[test]
+    fn primitives_over_the_limit() {
+        [
+            uv_sphere(1.0, 256, 256),
+            uv_sphere(1.0, u32::MAX, u32::MAX),
+            plane(1.0, 1.0, 1000, 1000),
+            plane(1.0, 1.0, 1, u32::MAX),
+            torus(1.0, 0.25, 1000, 1000),
+            capsule(0.5, 1.0, 1000, 1000),
+            capsule(0.5, 1.0, 3, u32::MAX),
+            cylinder(0.5, 1.0, u32::MAX),
+            cone(0.5, 1.0, u32::MAX),
+            icosphere(1.0, u32::MAX),
+        ].iter().for_each(assert_addressable);
+    }
+}
@@ -0,0 +1,671 @@
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix};
+
+use crate::graphics::vertex_input::Vertex;
+
+use super::bounds::Aabb;
+
+
+/// Triangles per BVH leaf. Smaller leaves mean more nodes to visit, larger
+/// ones more triangles to test.
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// A half-line from `origin` along `direction`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: cgmath::Point3<f32>,
+    pub direction: cgmath::Vector3<f32>,
+}
+
+impl Ray {
+    /// Normalises `direction`, so distances along the ray are in world units.
+    pub fn new(origin: cgmath::Point3<f32>, direction: cgmath::Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> cgmath::Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in the space `matrix` maps to. The direction is left
+    /// unnormalised, so that distances along the transformed ray are the same
+    /// as along this one.
+    pub fn transformed(&self, matrix: &cgmath::Matrix4<f32>) -> Self {
+        Self {
+            origin: cgmath::Point3::from_homogeneous(matrix * self.origin.to_homogeneous()),
+            direction: (matrix * self.direction.extend(0.0)).truncate(),
+        }
+    }
+
+    /// The distances at which the ray enters and leaves the box, or `None` if
+    /// it misses it or the box is behind the origin.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<(f32, f32)> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+
+        for axis in 0..3 {
+            // Parallel to the slab: the ray is inside it everywhere or nowhere.
+            if self.direction[axis] == 0.0 {
+                if self.origin[axis] < aabb.min[axis] || self.origin[axis] > aabb.max[axis] {
+                    return None;
+                }
+
+                continue;
+            }
+
+            let inverse = 1.0 / self.direction[axis];
+
+            let t0 = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let t1 = (aabb.max[axis] - self.origin[axis]) * inverse;
+
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+
+            // The slack keeps rounding from missing flat boxes, such as those
+            // of axis-aligned triangles, at their edges.
+            if near > far * (1.0 + 4.0 * f32::EPSILON) {
+                return None;
+            }
+        }
+
+        Some((near, far))
+    }
+
+    /// Möller–Trumbore intersection with the triangle `a b c`, from either
+    /// side. Returns the distance and the barycentric weights of `b` and `c`.
+    pub fn intersect_triangle(&self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, c: cgmath::Point3<f32>) -> Option<(f32, f32, f32)> {
+        let edge_1 = b - a;
+        let edge_2 = c - a;
+
+        let p = self.direction.cross(edge_2);
+        let determinant = edge_1.dot(p);
+
+        if determinant.abs() < f32::EPSILON * edge_1.magnitude2().max(edge_2.magnitude2()) {
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse_determinant;
+
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge_1);
+        let v = self.direction.dot(q) * inverse_determinant;
+
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge_2.dot(q) * inverse_determinant;
+
+        (distance >= 0.0).then_some((distance, u, v))
+    }
+}
+
+/// The closest intersection of a ray with the world.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    /// Index into `World::models`.
+    pub model: usize,
+    /// Index into that model's `meshes`.
+    pub mesh: usize,
+    /// Index of the triangle in the mesh, i.e. its first index is at `3 * triangle`.
+    pub triangle: usize,
+    /// Along the ray, in world units.
+    pub distance: f32,
+    pub position: cgmath::Point3<f32>,
+    /// Weights of the triangle's three vertices at the hit.
+    pub barycentrics: cgmath::Vector3<f32>,
+    /// World-space geometric normal of the triangle, facing the side its
+    /// vertices wind counter-clockwise from.
+    pub normal: cgmath::Vector3<f32>,
+}
+
+/// A hit in a single mesh, in the mesh's own space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshHit {
+    pub triangle: usize,
+    pub distance: f32,
+    pub barycentrics: cgmath::Vector3<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    /// Into `Bvh::triangles` for leaves, or the index of the first of two
+    /// adjacent children otherwise.
+    first: u32,
+    /// Number of triangles, zero for interior nodes.
+    count: u32,
+}
+
+/// Bounding volume hierarchy over the triangles of a mesh, built once when
+/// the mesh is loaded so that rays only test the triangles near them.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Triangle indices, ordered so that every leaf covers a contiguous range.
+    triangles: Vec<u32>,
+}
+
+impl Bvh {
+    /// Splits the triangles at the median of their centroids along the
+    /// longest axis of the node, until leaves are small enough.
+    pub fn build(vertices: &[Vertex], indices: &[u16]) -> Self {
+        let triangle_count = indices.len() / 3;
+
+        let bounds: Vec<Aabb> = (0..triangle_count)
+            .map(|triangle| {
+                Aabb::from_points(triangle_points(vertices, indices, triangle)).expect("A triangle has three points")
+            })
+            .collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(triangle_count.max(1) * 2),
+            triangles: (0..triangle_count as u32).collect(),
+        };
+
+        if triangle_count > 0 {
+            bvh.nodes.push(BvhNode { bounds: bounds[0], first: 0, count: triangle_count as u32 });
+
+            bvh.subdivide(0, &bounds);
+        }
+
+        bvh
+    }
+
+    fn subdivide(&mut self, node_index: usize, bounds: &[Aabb]) {
+        let node = self.nodes[node_index];
+        let range = node.first as usize..(node.first + node.count) as usize;
+
+        let node_bounds = self.triangles[range.clone()]
+            .iter()
+            .map(|triangle| bounds[*triangle as usize])
+            .reduce(|node_bounds, triangle_bounds| node_bounds.union(&triangle_bounds))
+            .expect("BVH nodes are never empty");
+
+        self.nodes[node_index].bounds = node_bounds;
+
+        if range.len() <= MAX_LEAF_TRIANGLES {
+            return;
+        }
+
+        let centroids = Aabb::from_points(self.triangles[range.clone()].iter().map(|triangle| bounds[*triangle as usize].center()))
+            .expect("BVH nodes are never empty");
+
+        let extents = centroids.extents();
+        let axis = if extents.x >= extents.y && extents.x >= extents.z { 0 } else if extents.y >= extents.z { 1 } else { 2 };
+
+        // Every centroid in the same place, e.g. duplicated triangles: no split helps.
+        if extents[axis] <= 0.0 {
+            return;
+        }
+
+        let middle = range.len() / 2;
+
+        self.triangles[range.clone()].select_nth_unstable_by(middle, |a, b| {
+            bounds[*a as usize].center()[axis].total_cmp(&bounds[*b as usize].center()[axis])
+        });
+
+        let first_child = self.nodes.len();
+
+        self.nodes.push(BvhNode { bounds: node_bounds, first: node.first, count: middle as u32 });
+        self.nodes.push(BvhNode { bounds: node_bounds, first: node.first + middle as u32, count: node.count - middle as u32 });
+
+        self.nodes[node_index].first = first_child as u32;
+        self.nodes[node_index].count = 0;
+
+        self.subdivide(first_child, bounds);
+        self.subdivide(first_child + 1, bounds);
+    }
+
+    /// The closest triangle the ray hits within `max_distance`, which must be
+    /// in the ray's units.
+    pub fn intersect(&self, ray: &Ray, vertices: &[Vertex], indices: &[u16], max_distance: f32) -> Option<MeshHit> {
+        let mut closest: Option<MeshHit> = None;
+        let mut closest_distance = max_distance;
+
+        let root = self.nodes.first()?;
+
+        if ray.intersect_aabb(&root.bounds).is_none_or(|(near, _)| near > closest_distance) {
+            return None;
+        }
+
+        let mut stack = vec![0usize];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            if node.count > 0 {
+                let range = node.first as usize..(node.first + node.count) as usize;
+
+                self.triangles[range].iter().for_each(|triangle| {
+                    let triangle = *triangle as usize;
+                    let [a, b, c] = triangle_points(vertices, indices, triangle);
+
+                    if let Some((distance, u, v)) = ray.intersect_triangle(a, b, c) {
+                        if distance < closest_distance {
+                            closest_distance = distance;
+                            closest = Some(MeshHit {
+                                triangle,
+                                distance,
+                                barycentrics: cgmath::Vector3::new(1.0 - u - v, u, v),
+                            });
+                        }
+                    }
+                });
+
+                continue;
+            }
+
+            let children = [node.first as usize, node.first as usize + 1].map(|child| {
+                (child, ray.intersect_aabb(&self.nodes[child].bounds).map(|(near, _)| near))
+            });
+
+            // Pushed farthest first, so the nearer child is visited first and
+            // shrinks `closest_distance` for the other.
+            let [nearer, farther] = match (children[0].1, children[1].1) {
+                (Some(first), Some(second)) if second < first => [children[1], children[0]],
+                _ => children,
+            };
+
+            [farther, nearer].into_iter().for_each(|(child, near)| {
+                if near.is_some_and(|near| near <= closest_distance) {
+                    stack.push(child);
+                }
+            });
+        }
+
+        closest
+    }
+}
+
+/// The geometry of one model for `closest_hit`: where it is drawn and the CPU
+/// copies of its meshes.
+pub struct RaycastModel<'a> {
+    pub model_matrix: cgmath::Matrix4<f32>,
+    /// World-space bounds of the meshes, so that models the ray misses are
+    /// skipped. `None` tests every mesh.
+    pub bounds: Option<Aabb>,
+    pub meshes: Vec<RaycastMesh<'a>>,
+}
+
+pub struct RaycastMesh<'a> {
+    pub vertices: &'a [Vertex],
+    pub indices: &'a [u16],
+    pub bvh: &'a Bvh,
+}
+
+/// The closest triangle of any of `models` the ray hits, with `Hit::model`
+/// and `Hit::mesh` indexing into `models` and their meshes.
+pub fn closest_hit<'a>(ray: Ray, models: impl IntoIterator<Item = RaycastModel<'a>>) -> Option<Hit> {
+    let mut closest: Option<Hit> = None;
+
+    models.into_iter().enumerate().for_each(|(model_index, model)| {
+        let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+
+        if model.bounds.is_some_and(|bounds| ray.intersect_aabb(&bounds).is_none_or(|(near, _)| near > max_distance)) {
+            return;
+        }
+
+        let Some(inverse_model_matrix) = model.model_matrix.invert() else {
+            return;
+        };
+
+        let local_ray = ray.transformed(&inverse_model_matrix);
+
+        model.meshes.iter().enumerate().for_each(|(mesh_index, mesh)| {
+            let max_distance = closest.map_or(f32::INFINITY, |hit| hit.distance);
+
+            if let Some(hit) = mesh.bvh.intersect(&local_ray, mesh.vertices, mesh.indices, max_distance) {
+                closest = Some(Hit {
+                    model: model_index,
+                    mesh: mesh_index,
+                    triangle: hit.triangle,
+                    distance: hit.distance,
+                    position: ray.at(hit.distance),
+                    barycentrics: hit.barycentrics,
+                    normal: triangle_normal(mesh.vertices, mesh.indices, hit.triangle, &model.model_matrix),
+                });
+            }
+        });
+    });
+
+    closest
+}
+
+fn triangle_points(vertices: &[Vertex], indices: &[u16], triangle: usize) -> [cgmath::Point3<f32>; 3] {
+    [0, 1, 2].map(|corner| vertices[indices[triangle * 3 + corner] as usize].position.into())
+}
+
+/// The world-space normal of a triangle of a mesh drawn with `model_matrix`.
+pub fn triangle_normal(vertices: &[Vertex], indices: &[u16], triangle: usize, model_matrix: &cgmath::Matrix4<f32>) -> cgmath::Vector3<f32> {
+    let [a, b, c] = triangle_points(vertices, indices, triangle);
+    let normal = (b - a).cross(c - a);
+
+    // Normals transform by the inverse transpose, which keeps them
+    // perpendicular under non-uniform scale.
+    let normal_matrix = model_matrix.invert().unwrap_or(cgmath::Matrix4::identity());
+    let normal = cgmath::Vector3::new(
+        normal_matrix.x.dot(normal.extend(0.0)),
+        normal_matrix.y.dot(normal.extend(0.0)),
+        normal_matrix.z.dot(normal.extend(0.0)),
+    );
+
+    if normal.magnitude2() > 0.0 { normal.normalize() } else { normal }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    fn point(x: f32, y: f32, z: f32) -> cgmath::Point3<f32> {
+        cgmath::Point3::new(x, y, z)
+    }
+
+    fn vertices(points: &[[f32; 3]]) -> Vec<Vertex> {
+        points.iter().map(|position| Vertex { position: *position, ..Vertex::zeroed() }).collect()
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{} is not {}", actual, expected);
+    }
+
+    const A: [f32; 3] = [0.0, 0.0, 0.0];
+    const B: [f32; 3] = [1.0, 0.0, 0.0];
+    const C: [f32; 3] = [0.0, 1.0, 0.0];
+
+    fn triangle(ray: Ray) -> Option<(f32, f32, f32)> {
+        ray.intersect_triangle(A.into(), B.into(), C.into())
+    }
+
+    #[test]
+    fn triangle_hit_and_barycentrics() {
+        let (distance, u, v) = triangle(Ray::new(point(0.25, 0.5, 2.0), -cgmath::Vector3::unit_z())).unwrap();
+
+        assert_close(distance, 2.0);
+        assert_close(u, 0.25);
+        assert_close(v, 0.5);
+    }
+
+    #[test]
+    fn triangle_corners_have_unit_barycentrics() {
+        let (_, u, v) = triangle(Ray::new(point(1.0, 0.0, 1.0), -cgmath::Vector3::unit_z())).unwrap();
+        assert_close(u, 1.0);
+        assert_close(v, 0.0);
+
+        let (_, u, v) = triangle(Ray::new(point(0.0, 1.0, 1.0), -cgmath::Vector3::unit_z())).unwrap();
+        assert_close(u, 0.0);
+        assert_close(v, 1.0);
+    }
+
+    #[test]
+    fn triangle_misses() {
+        assert_eq!(triangle(Ray::new(point(0.75, 0.75, 1.0), -cgmath::Vector3::unit_z())), None);
+        assert_eq!(triangle(Ray::new(point(-0.1, 0.5, 1.0), -cgmath::Vector3::unit_z())), None);
+        assert_eq!(triangle(Ray::new(point(0.25, 0.25, 1.0), cgmath::Vector3::new(1.0, 1.0, -0.01))), None);
+    }
+
+    #[test]
+    fn triangle_parallel_ray_misses() {
+        assert_eq!(triangle(Ray::new(point(-1.0, 0.25, 0.0), cgmath::Vector3::unit_x())), None);
+        assert_eq!(triangle(Ray::new(point(-1.0, 0.25, 1.0), cgmath::Vector3::unit_x())), None);
+    }
+
+    #[test]
+    fn triangle_is_hit_from_the_back() {
+        let (distance, u, v) = triangle(Ray::new(point(0.25, 0.25, -3.0), cgmath::Vector3::unit_z())).unwrap();
+
+        assert_close(distance, 3.0);
+        assert_close(u, 0.25);
+        assert_close(v, 0.25);
+    }
+
+    #[test]
+    fn triangle_behind_the_origin_misses() {
+        assert_eq!(triangle(Ray::new(point(0.25, 0.25, -1.0), -cgmath::Vector3::unit_z())), None);
+    }
+
+    #[test]
+    fn triangle_normals_follow_winding_and_transform() {
+        let vertices = vertices(&[A, B, C]);
+        let identity = cgmath::Matrix4::identity();
+
+        let normal = triangle_normal(&vertices, &[0, 1, 2], 0, &identity);
+        assert_eq!(normal, cgmath::Vector3::unit_z());
+
+        let normal = triangle_normal(&vertices, &[0, 2, 1], 0, &identity);
+        assert_eq!(normal, -cgmath::Vector3::unit_z());
+
+        let rotated = triangle_normal(&vertices, &[0, 1, 2], 0, &cgmath::Matrix4::from_angle_x(cgmath::Deg(90.0)));
+        // +z turns towards -y about +x.
+        assert!((rotated + cgmath::Vector3::unit_y()).magnitude() < 1e-5, "{:?}", rotated);
+    }
+
+    #[test]
+    fn triangle_normals_stay_perpendicular_under_non_uniform_scale() {
+        let vertices = vertices(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        let scale = cgmath::Matrix4::from_nonuniform_scale(2.0, 1.0, 1.0);
+
+        let normal = triangle_normal(&vertices, &[0, 1, 2], 0, &scale);
+
+        // The scaled triangle is (2, 0, 0), (0, 1, 0), (0, 0, 1).
+        let expected = cgmath::Vector3::new(1.0, 2.0, 2.0).normalize();
+        assert!((normal - expected).magnitude() < 1e-5, "{:?}", normal);
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb::new(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn aabb_entry_and_exit() {
+        let (near, far) = Ray::new(point(-5.0, 0.0, 0.0), cgmath::Vector3::unit_x()).intersect_aabb(&unit_box()).unwrap();
+        assert_close(near, 4.0);
+        assert_close(far, 6.0);
+
+        let (near, far) = Ray::new(point(-5.0, -5.0, 0.0), cgmath::Vector3::new(1.0, 1.0, 0.0)).intersect_aabb(&unit_box()).unwrap();
+        assert_close(near, 4.0 * 2.0f32.sqrt());
+        assert_close(far, 6.0 * 2.0f32.sqrt());
+    }
+
+    #[test]
+    fn aabb_ray_starting_inside() {
+        let (near, far) = Ray::new(point(0.0, 0.5, 0.0), cgmath::Vector3::unit_y()).intersect_aabb(&unit_box()).unwrap();
+        assert_close(near, 0.0);
+        assert_close(far, 0.5);
+    }
+
+    #[test]
+    fn aabb_misses() {
+        let aabb = unit_box();
+
+        assert_eq!(Ray::new(point(-5.0, 2.0, 0.0), cgmath::Vector3::unit_x()).intersect_aabb(&aabb), None);
+        assert_eq!(Ray::new(point(-5.0, 0.0, 0.0), cgmath::Vector3::new(1.0, 1.0, 0.0)).intersect_aabb(&aabb), None);
+        assert_eq!(Ray::new(point(5.0, 0.0, 0.0), cgmath::Vector3::unit_x()).intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn aabb_parallel_to_a_slab() {
+        let aabb = unit_box();
+
+        assert!(Ray::new(point(-5.0, 0.5, 0.5), cgmath::Vector3::unit_x()).intersect_aabb(&aabb).is_some());
+        assert_eq!(Ray::new(point(-5.0, 0.5, 1.5), cgmath::Vector3::unit_x()).intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn aabb_flat_box_is_hit() {
+        let flat = Aabb::new(point(-1.0, -1.0, 0.0), point(1.0, 1.0, 0.0));
+        let (near, far) = Ray::new(point(0.5, 0.5, 1.0), -cgmath::Vector3::unit_z()).intersect_aabb(&flat).unwrap();
+
+        assert_close(near, 1.0);
+        assert_close(far, 1.0);
+    }
+
+    /// Deterministic numbers in `0..1`.
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+            (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * self.next()
+        }
+    }
+
+    fn triangle_soup(random: &mut Lcg, count: usize) -> (Vec<Vertex>, Vec<u16>) {
+        let points: Vec<[f32; 3]> = (0..count).flat_map(|_| {
+            let center = [random.range(-10.0, 10.0), random.range(-10.0, 10.0), random.range(-10.0, 10.0)];
+            let mut corner = || [0, 1, 2].map(|axis| center[axis] + random.range(-2.0, 2.0));
+            [corner(), corner(), corner()]
+        }).collect();
+
+        (vertices(&points), (0..points.len() as u16).collect())
+    }
+
+    fn brute_force(ray: &Ray, vertices: &[Vertex], indices: &[u16], max_distance: f32) -> Option<(usize, f32)> {
+        (0..indices.len() / 3)
+            .filter_map(|triangle| {
+                let [a, b, c] = triangle_points(vertices, indices, triangle);
+                ray.intersect_triangle(a, b, c).map(|(distance, _, _)| (triangle, distance))
+            })
+            .filter(|(_, distance)| *distance < max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mut random = Lcg(7);
+        let (vertices, indices) = triangle_soup(&mut random, 300);
+        let bvh = Bvh::build(&vertices, &indices);
+
+        let mut hits = 0;
+
+        for _ in 0..500 {
+            let origin = point(random.range(-15.0, 15.0), random.range(-15.0, 15.0), random.range(-15.0, 15.0));
+            let target = point(random.range(-8.0, 8.0), random.range(-8.0, 8.0), random.range(-8.0, 8.0));
+            let ray = Ray::new(origin, target - origin);
+            let max_distance = if random.next() < 0.25 { random.range(0.0, 20.0) } else { f32::INFINITY };
+
+            let expected = brute_force(&ray, &vertices, &indices, max_distance);
+            let actual = bvh.intersect(&ray, &vertices, &indices, max_distance).map(|hit| (hit.triangle, hit.distance));
+
+            assert_eq!(actual, expected, "ray {:?} within {}", ray, max_distance);
+
+            hits += expected.is_some() as u32;
+        }
+
+        assert!(hits > 100, "only {} rays hit, the test is not exercising much", hits);
+    }
+
+    #[test]
+    fn bvh_reports_barycentrics_of_the_hit() {
+        let vertices = vertices(&[A, B, C]);
+        let bvh = Bvh::build(&vertices, &[0, 1, 2]);
+
+        let hit = bvh.intersect(&Ray::new(point(0.25, 0.5, 1.0), -cgmath::Vector3::unit_z()), &vertices, &[0, 1, 2], f32::INFINITY).unwrap();
+
+        assert_eq!(hit.triangle, 0);
+        assert!((hit.barycentrics - cgmath::Vector3::new(0.25, 0.25, 0.5)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn bvh_of_coincident_triangles_and_of_nothing() {
+        let vertices = vertices(&[A, B, C]);
+        let indices: Vec<u16> = [0, 1, 2].repeat(20);
+        let bvh = Bvh::build(&vertices, &indices);
+        let ray = Ray::new(point(0.25, 0.25, 1.0), -cgmath::Vector3::unit_z());
+
+        assert!(bvh.intersect(&ray, &vertices, &indices, f32::INFINITY).is_some());
+        assert!(Bvh::build(&vertices, &[]).intersect(&ray, &vertices, &[], f32::INFINITY).is_none());
+    }
+
+    /// A square from -1 to 1 in the z = 0 plane, facing +z, split along x = y.
+    fn quad() -> (Vec<Vertex>, Vec<u16>, Bvh) {
+        let vertices = vertices(&[[-1.0, -1.0, 0.0], [1.0, -1.0, 0.0], [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0]]);
+        let indices = vec![0, 1, 2, 2, 3, 0];
+        let bvh = Bvh::build(&vertices, &indices);
+
+        (vertices, indices, bvh)
+    }
+
+    #[test]
+    fn closest_hit_is_the_nearest_across_models() {
+        let (vertices, indices, bvh) = quad();
+        let quad_bounds = Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into())).unwrap();
+
+        let model = |model_matrix: cgmath::Matrix4<f32>, with_bounds: bool| RaycastModel {
+            model_matrix,
+            bounds: with_bounds.then(|| quad_bounds.transformed(&model_matrix)),
+            meshes: vec![RaycastMesh { vertices: &vertices, indices: &indices, bvh: &bvh }],
+        };
+
+        let translation = |z: f32| cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 0.0, z));
+
+        let models = vec![
+            model(translation(-5.0), true),
+            // Behind the ray.
+            model(translation(3.0), false),
+            // Off to the side, and nearer.
+            model(cgmath::Matrix4::from_translation(cgmath::Vector3::new(10.0, 0.0, -1.0)), true),
+            // Scaled, so the hit is further along the ray in local units.
+            model(translation(-2.0) * cgmath::Matrix4::from_scale(3.0), false),
+            model(translation(-8.0), true),
+        ];
+
+        let ray = Ray::new(point(0.5, -0.25, 0.0), -cgmath::Vector3::unit_z());
+
+        let hit = closest_hit(ray, models).unwrap();
+
+        assert_eq!((hit.model, hit.mesh), (3, 0));
+        assert_eq!(hit.triangle, 0);
+        assert_close(hit.distance, 2.0);
+        assert!((hit.position - point(0.5, -0.25, -2.0)).magnitude() < 1e-5, "{:?}", hit.position);
+        assert!((hit.normal - cgmath::Vector3::unit_z()).magnitude() < 1e-5, "{:?}", hit.normal);
+        assert_close(hit.barycentrics.x + hit.barycentrics.y + hit.barycentrics.z, 1.0);
+    }
+
+    #[test]
+    fn closest_hit_picks_the_nearest_mesh_of_a_model() {
+        let (far_vertices, indices, far_bvh) = quad();
+        let near_vertices: Vec<Vertex> = far_vertices.iter().map(|vertex| Vertex { position: [vertex.position[0], vertex.position[1], 1.0], ..*vertex }).collect();
+        let near_bvh = Bvh::build(&near_vertices, &indices);
+
+        let models = [RaycastModel {
+            model_matrix: cgmath::Matrix4::identity(),
+            bounds: None,
+            meshes: vec![
+                RaycastMesh { vertices: &far_vertices, indices: &indices, bvh: &far_bvh },
+                RaycastMesh { vertices: &near_vertices, indices: &indices, bvh: &near_bvh },
+            ],
+        }];
+
+        let hit = closest_hit(Ray::new(point(0.0, 0.5, 4.0), -cgmath::Vector3::unit_z()), models).unwrap();
+
+        assert_eq!((hit.model, hit.mesh), (0, 1));
+        assert_close(hit.distance, 3.0);
+    }
+
+    #[test]
+    fn closest_hit_misses_everything() {
+        let (vertices, indices, bvh) = quad();
+
+        let models = [RaycastModel {
+            model_matrix: cgmath::Matrix4::identity(),
+            bounds: None,
+            meshes: vec![RaycastMesh { vertices: &vertices, indices: &indices, bvh: &bvh }],
+        }];
+
+        assert_eq!(closest_hit(Ray::new(point(2.0, 0.0, 1.0), -cgmath::Vector3::unit_z()), models), None);
+    }
+}
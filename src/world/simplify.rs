@@ -0,0 +1,436 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap}};
+
+use cgmath::InnerSpace;
+
+use super::{mesh_processing, world::{MeshData, MorphTargets}};
+
+
+/// How much more a border edge resists moving than a face of the same size,
+/// so that holes and open edges keep their outline.
+const BORDER_WEIGHT: f64 = 10.0;
+
+/// Collapses that turn a triangle further than this, as the cosine of the
+/// angle, are rejected, since they tend to fold the surface over.
+const MIN_NORMAL_COSINE: f64 = 0.25;
+
+/// Each LOD aims for this fraction of the triangles of the previous one.
+const LOD_REDUCTION: f32 = 0.5;
+
+/// Meshes, or LODs, with fewer triangles aren't simplified further.
+const MIN_LOD_TRIANGLES: usize = 32;
+
+/// A coarser version of a mesh, drawn with the same vertices.
+#[derive(Debug, Clone, Default)]
+pub struct LodData {
+    pub indices: Vec<u16>,
+    /// Largest distance from the original surface the simplification
+    /// introduced, approximately, in the mesh's units.
+    pub error: f32,
+}
+
+/// Sum of squared distances to a set of planes, as a symmetric 4x4 matrix
+/// stored as its upper triangle, from Garland and Heckbert's "Surface
+/// Simplification Using Quadric Error Metrics".
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    matrix: [f64; 10],
+    /// Total weight of the planes, to turn the error into an average
+    /// squared distance.
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(normal: cgmath::Vector3<f64>, point: cgmath::Point3<f64>, weight: f64) -> Self {
+        let [a, b, c] = [normal.x, normal.y, normal.z];
+        let d = -normal.dot(cgmath::Vector3::new(point.x, point.y, point.z));
+
+        Self {
+            matrix: [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|value| value * weight),
+            weight,
+        }
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = *self;
+
+        sum.matrix.iter_mut().zip(other.matrix).for_each(|(value, other)| *value += other);
+        sum.weight += other.weight;
+
+        sum
+    }
+
+    /// Average squared distance of `point` to the planes.
+    fn error(&self, point: cgmath::Point3<f64>) -> f64 {
+        let [aa, ab, ac, ad, bb, bc, bd, cc, cd, dd] = self.matrix;
+        let (x, y, z) = (point.x, point.y, point.z);
+
+        let error = aa * x * x + 2.0 * ab * x * y + 2.0 * ac * x * z + 2.0 * ad * x
+            + bb * y * y + 2.0 * bc * y * z + 2.0 * bd * y
+            + cc * z * z + 2.0 * cd * z
+            + dd;
+
+        if self.weight > 0.0 { error.max(0.0) / self.weight } else { 0.0 }
+    }
+}
+
+/// Moving the vertex at position `from` onto position `to`.
+#[derive(Debug, Clone, Copy)]
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    /// Versions of both positions when the collapse was costed, to skip it if
+    /// either has changed since.
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed, so the cheapest collapse is on top of `BinaryHeap`.
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Simplifier {
+    /// Vertices at the same place share a position, so that the surface
+    /// stays closed where attributes are split.
+    position_of: Vec<usize>,
+    positions: Vec<cgmath::Point3<f64>>,
+    /// Positions with several vertices, along UV seams and hard edges, which
+    /// can't be moved without tearing the seam.
+    locked: Vec<bool>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>,
+    triangles: Vec<[u16; 3]>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    /// Triangles around each position, possibly including dead ones.
+    position_triangles: Vec<Vec<usize>>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl Simplifier {
+    fn new(data: &MeshData, indices: &[u16]) -> Self {
+        let mut position_ids: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut first_vertex: Vec<u16> = Vec::new();
+        let mut locked = Vec::new();
+
+        let position_of: Vec<usize> = data.vertices.iter().enumerate().map(|(index, vertex)| {
+            let id = *position_ids.entry(vertex.position.map(f32::to_bits)).or_insert_with(|| {
+                positions.push(cgmath::Point3::from(vertex.position.map(f64::from)));
+                first_vertex.push(index as u16);
+                locked.push(false);
+                positions.len() - 1
+            });
+
+            if first_vertex[id] as usize != index {
+                locked[id] = true;
+            }
+
+            id
+        }).collect();
+
+        // Degenerate triangles are invisible, so they are dropped up front.
+        let triangles: Vec<[u16; 3]> = indices.chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .filter(|triangle| {
+                let [a, b, c] = triangle.map(|vertex| position_of[vertex as usize]);
+                a != b && b != c && c != a
+            })
+            .collect();
+
+        let mut simplifier = Self {
+            locked,
+            quadrics: vec![Quadric::default(); positions.len()],
+            versions: vec![0; positions.len()],
+            position_triangles: vec![Vec::new(); positions.len()],
+            positions,
+            position_of,
+            alive: vec![true; triangles.len()],
+            alive_count: triangles.len(),
+            triangles,
+            heap: BinaryHeap::new(),
+        };
+
+        simplifier.accumulate_quadrics();
+
+        (0..simplifier.triangles.len()).for_each(|triangle| {
+            simplifier.triangle_positions(triangle).iter().for_each(|position| simplifier.position_triangles[*position].push(triangle));
+        });
+
+        (0..simplifier.triangles.len()).for_each(|triangle| {
+            let [a, b, c] = simplifier.triangle_positions(triangle);
+
+            [(a, b), (b, c), (c, a)].into_iter().for_each(|(from, to)| simplifier.push_edge(from, to));
+        });
+
+        simplifier
+    }
+
+    fn triangle_positions(&self, triangle: usize) -> [usize; 3] {
+        self.triangles[triangle].map(|vertex| self.position_of[vertex as usize])
+    }
+
+    /// Plane quadrics of the faces, weighted by area, plus planes through
+    /// border edges perpendicular to their face.
+    fn accumulate_quadrics(&mut self) {
+        let mut edge_count: HashMap<(usize, usize), u32> = HashMap::new();
+
+        (0..self.triangles.len()).for_each(|triangle| {
+            let [a, b, c] = self.triangle_positions(triangle);
+
+            [(a, b), (b, c), (c, a)].into_iter().for_each(|(from, to)| {
+                *edge_count.entry((from.min(to), from.max(to))).or_default() += 1;
+            });
+        });
+
+        (0..self.triangles.len()).for_each(|triangle| {
+            let ids = self.triangle_positions(triangle);
+            let [a, b, c] = ids.map(|id| self.positions[id]);
+
+            let cross = (b - a).cross(c - a);
+            let area = cross.magnitude() / 2.0;
+
+            if area <= 0.0 {
+                return;
+            }
+
+            let normal = cross.normalize();
+            let quadric = Quadric::from_plane(normal, a, area);
+
+            ids.iter().for_each(|id| self.quadrics[*id] = self.quadrics[*id].add(&quadric));
+
+            [(0, 1), (1, 2), (2, 0)].into_iter().for_each(|(from, to)| {
+                let (from, to) = (ids[from], ids[to]);
+
+                if edge_count[&(from.min(to), from.max(to))] != 1 {
+                    return;
+                }
+
+                let edge = self.positions[to] - self.positions[from];
+                let border_normal = edge.cross(normal);
+
+                if border_normal.magnitude2() <= 0.0 {
+                    return;
+                }
+
+                let quadric = Quadric::from_plane(border_normal.normalize(), self.positions[from], edge.magnitude2() * BORDER_WEIGHT);
+
+                self.quadrics[from] = self.quadrics[from].add(&quadric);
+                self.quadrics[to] = self.quadrics[to].add(&quadric);
+            });
+        });
+    }
+
+    /// Queues both directions of the edge that can move.
+    fn push_edge(&mut self, a: usize, b: usize) {
+        [(a, b), (b, a)].into_iter().for_each(|(from, to)| {
+            if self.locked[from] {
+                return;
+            }
+
+            let cost = self.quadrics[from].add(&self.quadrics[to]).error(self.positions[to]);
+
+            self.heap.push(Collapse {
+                cost,
+                from,
+                to,
+                versions: (self.versions[from], self.versions[to]),
+            });
+        });
+    }
+
+    fn alive_triangles(&self, position: usize) -> impl Iterator<Item = usize> + '_ {
+        self.position_triangles[position].iter().copied().filter(|triangle| self.alive[*triangle])
+    }
+
+    fn neighbours(&self, position: usize) -> Vec<usize> {
+        let mut neighbours: Vec<usize> = self.alive_triangles(position)
+            .flat_map(|triangle| self.triangle_positions(triangle))
+            .filter(|neighbour| *neighbour != position)
+            .collect();
+
+        neighbours.sort_unstable();
+        neighbours.dedup();
+
+        neighbours
+    }
+
+    /// The vertex of `to` that replaces the one of `from`, if the collapse
+    /// keeps the mesh manifold and doesn't flip any triangle.
+    fn check(&self, from: usize, to: usize) -> Option<u16> {
+        let shared: Vec<usize> = self.alive_triangles(from)
+            .filter(|triangle| self.triangle_positions(*triangle).contains(&to))
+            .collect();
+
+        // Triangles on either side of the edge must use the same vertex at
+        // `to`, or the collapse would move attributes across a seam.
+        let mut replacements = shared.iter().map(|triangle| {
+            let corner = self.triangle_positions(*triangle).iter().position(|position| *position == to).expect("The triangle contains the edge");
+            self.triangles[*triangle][corner]
+        });
+
+        let replacement = replacements.next()?;
+
+        if replacements.any(|other| other != replacement) {
+            return None;
+        }
+
+        // Positions around both ends, other than those across the edge's own
+        // triangles, would end up joined by two separate edges.
+        let from_neighbours = self.neighbours(from);
+        let common = self.neighbours(to).into_iter().filter(|neighbour| from_neighbours.binary_search(neighbour).is_ok()).count();
+
+        if common > shared.len() {
+            return None;
+        }
+
+        let flips = self.alive_triangles(from).filter(|triangle| !shared.contains(triangle)).any(|triangle| {
+            let ids = self.triangle_positions(triangle);
+            let points = ids.map(|id| self.positions[id]);
+            let moved = ids.map(|id| if id == from { self.positions[to] } else { self.positions[id] });
+
+            let before = (points[1] - points[0]).cross(points[2] - points[0]);
+            let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+
+            after.dot(before) <= MIN_NORMAL_COSINE * after.magnitude() * before.magnitude()
+        });
+
+        (!flips).then_some(replacement)
+    }
+
+    fn collapse(&mut self, from: usize, to: usize, replacement: u16) {
+        let triangles: Vec<usize> = self.alive_triangles(from).collect();
+
+        triangles.into_iter().for_each(|triangle| {
+            let positions = self.triangle_positions(triangle);
+
+            if positions.contains(&to) {
+                self.alive[triangle] = false;
+                self.alive_count -= 1;
+            } else {
+                let corner = positions.iter().position(|position| *position == from).expect("The triangle is around the position");
+
+                self.triangles[triangle][corner] = replacement;
+                self.position_triangles[to].push(triangle);
+            }
+        });
+
+        self.position_triangles[from].clear();
+
+        let alive = &self.alive;
+        self.position_triangles[to].retain(|triangle| alive[*triangle]);
+
+        self.quadrics[to] = self.quadrics[to].add(&self.quadrics[from]);
+        self.versions[to] += 1;
+        self.versions[from] += 1;
+
+        self.neighbours(to).into_iter().for_each(|neighbour| self.push_edge(to, neighbour));
+    }
+
+    /// Collapses the cheapest edges until `target_triangles` remain or the
+    /// next collapse would move the surface by more than `max_error`.
+    fn run(&mut self, target_triangles: usize, max_error: f32) -> f32 {
+        let max_cost = (max_error as f64).powi(2);
+        let mut error: f64 = 0.0;
+
+        while self.alive_count > target_triangles {
+            let Some(collapse) = self.heap.pop() else {
+                break;
+            };
+
+            if collapse.versions != (self.versions[collapse.from], self.versions[collapse.to]) {
+                continue;
+            }
+
+            if collapse.cost > max_cost {
+                break;
+            }
+
+            let Some(replacement) = self.check(collapse.from, collapse.to) else {
+                continue;
+            };
+
+            self.collapse(collapse.from, collapse.to, replacement);
+
+            error = error.max(collapse.cost);
+        }
+
+        error.sqrt() as f32
+    }
+
+    fn indices(&self) -> Vec<u16> {
+        self.triangles.iter().zip(&self.alive).filter(|(_, alive)| **alive).flat_map(|(triangle, _)| *triangle).collect()
+    }
+}
+
+/// Simplifies the triangles `indices` of `data` down to about
+/// `target_triangles`, without moving the surface by more than `max_error`.
+/// Vertices are only ever merged into others, never moved, so the result
+/// indexes the same vertices.
+pub fn simplify(data: &MeshData, indices: &[u16], target_triangles: usize, max_error: f32) -> LodData {
+    let mut simplifier = Simplifier::new(data, indices);
+
+    let error = simplifier.run(target_triangles, max_error);
+
+    LodData {
+        indices: simplifier.indices(),
+        error,
+    }
+}
+
+/// Successively coarser versions of `data`, each with about half the
+/// triangles of the previous, up to `max_levels`. The chain ends early once
+/// simplification stops making progress, e.g. when seams are all that's
+/// left.
+pub fn lod_chain(data: &MeshData, max_levels: usize) -> Vec<LodData> {
+    let mut lods: Vec<LodData> = Vec::new();
+
+    while lods.len() < max_levels {
+        let (indices, error) = lods.last().map_or((&data.indices, 0.0), |lod| (&lod.indices, lod.error));
+        let triangles = indices.len() / 3;
+
+        if triangles < MIN_LOD_TRIANGLES * 2 {
+            break;
+        }
+
+        let target = ((triangles as f32 * LOD_REDUCTION) as usize).max(MIN_LOD_TRIANGLES);
+        let mut lod = simplify(data, indices, target, f32::INFINITY);
+
+        // Less than a tenth fewer triangles isn't worth a level.
+        if lod.indices.len() / 3 > triangles * 9 / 10 {
+            break;
+        }
+
+        let mut cache_ordered = MeshData {
+            vertices: data.vertices.clone(),
+            indices: lod.indices,
+            morph: MorphTargets::default(),
+        };
+
+        mesh_processing::optimize_vertex_cache(&mut cache_ordered);
+
+        lod.indices = cache_ordered.indices;
+        lod.error = lod.error.max(error);
+
+        lods.push(lod);
+    }
+
+    lods
+}
+
@@ -0,0 +1,254 @@
+use cgmath::InnerSpace;
+
+use crate::{assets::source::AssetResolver, graphics::vertex_input::Vertex};
+
+use super::import::{triangulate, ImportedModel, MeshAssembler};
+
+
+const BINARY_HEADER_SIZE: usize = 80;
+const BINARY_TRIANGLE_SIZE: usize = 50;
+
+/// Imports a binary or ASCII STL file. Facets are flat shaded with the normal
+/// of their winding rather than the stored one, which many exporters leave
+/// zero and which would disagree with culling where it's wrong. STL has no
+/// UVs or materials.
+pub fn import_stl(assets: &AssetResolver, path: &str) -> Result<ImportedModel, String> {
+    let bytes = assets.read(path)?;
+
+    let facets = if is_binary(&bytes) {
+        parse_binary(&bytes)
+    } else if bytes.trim_ascii_start().starts_with(b"solid") && !bytes.contains(&0) {
+        let source = std::str::from_utf8(&bytes).map_err(|_| format!("{} is neither binary nor ASCII STL, or is truncated", path))?;
+
+        parse_ascii(source)
+    } else {
+        Err("A binary STL file must be 84 bytes plus 50 for each of the triangles its header counts, it may be truncated".to_string())
+    }.map_err(|message| format!("{}: {}", path, message))?;
+
+    let mut assembler = MeshAssembler::default();
+
+    facets.iter().for_each(|facet| {
+        let points: Vec<cgmath::Point3<f32>> = facet.vertices.iter().map(|vertex| (*vertex).into()).collect();
+
+        triangulate(&points).into_iter().for_each(|triangle| {
+            let [a, b, c] = triangle.map(|corner| points[corner]);
+
+            let normal = (b - a).cross(c - a);
+
+            // Degenerate, so invisible.
+            if normal.magnitude2() <= 0.0 {
+                return;
+            }
+
+            let normal = normal.normalize();
+
+            assembler.push_triangle(triangle.map(|corner| Vertex {
+                position: facet.vertices[corner],
+                normal: normal.into(),
+                uv: [0.0; 2],
+                tangent: [0.0; 4],
+                joints: [0; 4],
+                weights: [0.0; 4],
+            }));
+        });
+    });
+
+    Ok(ImportedModel {
+        meshes: assembler.finish(),
+        material: None,
+        skeleton: None,
+        animations: Vec::new(),
+        dependencies: Vec::new(),
+    })
+}
+
+struct Facet {
+    /// Three in binary files, though ASCII loops can have more.
+    vertices: Vec<[f32; 3]>,
+}
+
+/// Binary files can start with "solid" too, so the size, which binary files
+/// state exactly, decides. Truncated ones are told apart from ASCII by their
+/// zero bytes.
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() < BINARY_HEADER_SIZE + 4 {
+        return false;
+    }
+
+    let count = u32::from_le_bytes(bytes[BINARY_HEADER_SIZE..BINARY_HEADER_SIZE + 4].try_into().expect("The slice is 4 bytes"));
+
+    bytes.len() == BINARY_HEADER_SIZE + 4 + count as usize * BINARY_TRIANGLE_SIZE
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<Facet>, String> {
+    let triangles = &bytes[BINARY_HEADER_SIZE + 4..];
+
+    let vector = |bytes: &[u8]| -> [f32; 3] {
+        [0, 1, 2].map(|axis| f32::from_le_bytes(bytes[axis * 4..axis * 4 + 4].try_into().expect("The slice is 4 bytes")))
+    };
+
+    triangles.chunks_exact(BINARY_TRIANGLE_SIZE).enumerate().map(|(index, triangle)| {
+        let facet = Facet {
+            vertices: (0..3).map(|corner| vector(&triangle[12 + corner * 12..24 + corner * 12])).collect(),
+        };
+
+        if facet.vertices.iter().flatten().all(|value| value.is_finite()) {
+            Ok(facet)
+        } else {
+            Err(format!("Triangle {} has a value that is not a number", index))
+        }
+    }).collect()
+}
+
+fn parse_ascii(source: &str) -> Result<Vec<Facet>, String> {
+    let mut facets = Vec::new();
+    let mut current: Option<Facet> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", line_index + 1, message);
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        let numbers = |tokens: &[&str]| -> Result<[f32; 3], String> {
+            let values: Vec<f32> = tokens.iter().map(|token| token.parse::<f32>()).collect::<Result<_, _>>().map_err(|_| error("Expected a number"))?;
+
+            values.try_into().map_err(|_| error("Expected 3 numbers"))
+        };
+
+        match tokens.as_slice() {
+            ["facet", "normal", rest @ ..] => {
+                if current.is_some() {
+                    return Err(error("facet inside a facet"));
+                }
+
+                numbers(rest)?;
+
+                current = Some(Facet {
+                    vertices: Vec::with_capacity(3),
+                });
+            },
+            ["vertex", rest @ ..] => {
+                let vertex = numbers(rest)?;
+
+                current.as_mut().ok_or_else(|| error("vertex outside a facet"))?.vertices.push(vertex);
+            },
+            ["endfacet", ..] => {
+                let facet = current.take().ok_or_else(|| error("endfacet without a facet"))?;
+
+                if facet.vertices.len() < 3 {
+                    return Err(error("A facet needs at least 3 vertices"));
+                }
+
+                facets.push(facet);
+            },
+            // "solid", "outer loop", "endloop", "endsolid", and blank lines.
+            _ => {},
+        }
+    }
+
+    if current.is_some() {
+        return Err("The last facet has no endfacet".to_string());
+    }
+
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+
+    fn fixtures() -> AssetResolver {
+        AssetResolver::embedded().with_root_directory(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures"))
+    }
+
+    /// Imports `bytes` from a file in a directory of its own.
+    fn import_bytes(name: &str, bytes: &[u8]) -> Result<ImportedModel, String> {
+        let directory: PathBuf = std::env::temp_dir().join(format!("phoenix_stl_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&directory).expect("Failed to create the test directory");
+        std::fs::write(directory.join("model.stl"), bytes).expect("Failed to write the test file");
+
+        let result = import_stl(&AssetResolver::embedded().with_root_directory(&directory), "model.stl");
+
+        std::fs::remove_dir_all(&directory).ok();
+
+        result
+    }
+
+    fn assert_flat_up(model: &ImportedModel) {
+        assert!(model.meshes.iter().flat_map(|mesh| &mesh.vertices).all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn binary_with_solid_header() {
+        let model = import_stl(&fixtures(), "square_binary.stl").expect("The fixture imports");
+
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].indices.len(), 6);
+        // The corners on the diagonal are shared.
+        assert_eq!(model.meshes[0].vertices.len(), 4);
+        // The stored normals are zero, so they come from the winding.
+        assert_flat_up(&model);
+    }
+
+    #[test]
+    fn ascii_polygons_and_degenerate_facets() {
+        let model = import_stl(&fixtures(), "square_ascii.stl").expect("The fixture imports");
+
+        // A triangle and a quad, the collinear facet is dropped.
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].indices.len(), 9);
+        assert_eq!(model.meshes[0].vertices.len(), 5);
+        // Including the facet stored as facing down.
+        assert_flat_up(&model);
+    }
+
+    #[test]
+    fn truncated_binary_is_an_error() {
+        let bytes = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/square_binary.stl")).expect("The fixture exists");
+
+        for length in 0..bytes.len() {
+            let result = import_bytes(&format!("binary_{}", length), &bytes[..length]);
+
+            if length > BINARY_HEADER_SIZE + 4 {
+                assert!(result.is_err(), "{} bytes imported", length);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_ascii_does_not_panic() {
+        let bytes = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/square_ascii.stl")).expect("The fixture exists");
+
+        for length in 0..bytes.len() {
+            let _ = import_bytes(&format!("ascii_{}", length), &bytes[..length]);
+        }
+    }
+
+    #[test]
+    fn malformed_ascii() {
+        let cases = [
+            ("no_endfacet", "solid a\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\n"),
+            ("two_vertices", "solid a\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\nendfacet\n"),
+            ("short_vertex", "solid a\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n"),
+            ("bad_number", "solid a\nfacet normal 0 0 1\nouter loop\nvertex 0 x 0\n"),
+            ("nested_facet", "solid a\nfacet normal 0 0 1\nfacet normal 0 0 1\n"),
+            ("stray_vertex", "solid a\nvertex 0 0 0\n"),
+            ("stray_endfacet", "solid a\nendfacet\n"),
+            ("not_stl", "hello"),
+        ];
+
+        for (name, source) in cases {
+            assert!(import_bytes(name, source.as_bytes()).is_err(), "{} imported", name);
+        }
+    }
+
+    #[test]
+    fn binary_with_nan_is_an_error() {
+        let mut bytes = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/square_binary.stl")).expect("The fixture exists");
+        bytes[BINARY_HEADER_SIZE + 4 + 12..BINARY_HEADER_SIZE + 4 + 16].copy_from_slice(&f32::NAN.to_le_bytes());
+
+        assert!(import_bytes("nan", &bytes).is_err());
+    }
+}
@@ -1,10 +1,10 @@
-use std::rc::Rc;
+use std::{collections::HashMap, rc::Rc};
 
 use winit::event::*;
 
-use crate::graphics::context::{Buffer, Context};
+use crate::{assets::server::{AssetServer, LoadState}, graphics::{context::{Buffer, Context}, vertex_input::{MorphDelta, Vertex}}};
 
-use super::model::{Model, ModelBuilder};
+use super::{bounds::Aabb, light::PointLight, material::{Material, MaterialBuilder}, model::{Model, ModelBuilder}, primitives, mesh_processing, raycast::{closest_hit, Bvh, Hit, Ray, RaycastMesh, RaycastModel}, simplify::{self, LodData}};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Transform {
@@ -23,10 +23,231 @@ impl Default for Transform {
     }
 }
 
+#[derive(Clone)]
 pub struct Mesh {
     pub vertex_buffer: Rc<Buffer>,
     pub index_buffer: Rc<Buffer>,
     pub num_elements: u32,
+    /// CPU copies of the geometry, for GPU-driven rendering and picking.
+    pub vertices: Rc<[Vertex]>,
+    pub indices: Rc<[u16]>,
+    /// Object-space bounds of the vertices, computed when the mesh is loaded,
+    /// including wherever morph targets with weights from 0 to 1 move them.
+    pub bounds: Aabb,
+    /// Over `vertices` and `indices`, for ray casts.
+    pub bvh: Rc<Bvh>,
+    /// Coarser levels of detail, from finest to coarsest. Level 0 is the mesh
+    /// itself.
+    pub lods: Vec<MeshLod>,
+    pub morph: Option<MeshMorphTargets>,
+}
+
+/// The morph targets of a mesh on the GPU, blended in by the pipelines built
+/// with `MORPHED`. The weights and node are those of its `MorphTargets`.
+#[derive(Clone)]
+pub struct MeshMorphTargets {
+    /// Every vertex's `MorphDelta` for the first target, then for the second,
+    /// and so on.
+    pub delta_buffer: Rc<Buffer>,
+    pub count: u32,
+    pub weights: Vec<f32>,
+    pub node: Option<usize>,
+}
+
+/// A simplified version of a mesh, drawn with the mesh's vertex buffer.
+#[derive(Clone)]
+pub struct MeshLod {
+    pub index_buffer: Rc<Buffer>,
+    pub num_elements: u32,
+    /// Roughly how far the surface moved from the full-detail mesh, in the
+    /// mesh's units.
+    pub error: f32,
+}
+
+impl Mesh {
+    /// Uploads the geometry as it is and builds what the CPU needs to query it.
+    pub fn new(ctx: &mut Context, data: &MeshData) -> Self {
+        Self::from_prepared(ctx, PreparedMesh {
+            bvh: Bvh::build(&data.vertices, &data.indices),
+            data: data.clone(),
+            lods: Vec::new(),
+        })
+    }
+
+    /// Uploads a mesh whose CPU side is already built.
+    pub fn from_prepared(ctx: &mut Context, prepared: PreparedMesh) -> Self {
+        let PreparedMesh { data, lods, bvh } = prepared;
+
+        let vertex_buffer = ctx.create_buffer("mesh_vertex_buffer", bytemuck::cast_slice(&data.vertices), wgpu::BufferUsages::VERTEX);
+
+        let index_buffer = ctx.create_buffer("mesh_index_buffer", bytemuck::cast_slice(&data.indices), wgpu::BufferUsages::INDEX);
+
+        let lods = lods.iter().map(|lod| MeshLod {
+            index_buffer: ctx.create_buffer("mesh_lod_index_buffer", bytemuck::cast_slice(&lod.indices), wgpu::BufferUsages::INDEX),
+            num_elements: lod.indices.len() as u32,
+            error: lod.error,
+        }).collect();
+
+        let mut bounds = Mesh::compute_bounds(&data.vertices);
+
+        if let Some(morph_bounds) = data.morph.bounds(&data.vertices) {
+            bounds = bounds.union(&morph_bounds);
+        }
+
+        let morph = (!data.morph.targets.is_empty() && !data.vertices.is_empty()).then(|| {
+            let deltas: Vec<MorphDelta> = data.morph.targets.concat();
+
+            MeshMorphTargets {
+                delta_buffer: ctx.create_storage_buffer("mesh_morph_delta_buffer", bytemuck::cast_slice(&deltas), wgpu::BufferUsages::empty()),
+                count: data.morph.targets.len() as u32,
+                weights: data.morph.weights.clone(),
+                node: data.morph.node,
+            }
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_elements: data.indices.len() as u32,
+            vertices: data.vertices.as_slice().into(),
+            indices: data.indices.as_slice().into(),
+            bounds,
+            bvh: Rc::new(bvh),
+            lods,
+            morph,
+        }
+    }
+
+    pub fn lod_count(&self) -> usize {
+        self.lods.len() + 1
+    }
+
+    /// The index buffer and index count of level `level`, or of the coarsest
+    /// level if the mesh has fewer.
+    pub fn lod(&self, level: usize) -> (&Buffer, u32) {
+        match level.min(self.lods.len()) {
+            0 => (&self.index_buffer, self.num_elements),
+            level => (&self.lods[level - 1].index_buffer, self.lods[level - 1].num_elements),
+        }
+    }
+
+    pub fn compute_bounds(vertices: &[Vertex]) -> Aabb {
+        Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into()))
+            .unwrap_or(Aabb::new(cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Point3::new(0.0, 0.0, 0.0)))
+    }
+}
+
+/// Everything about a mesh that is computed on the CPU, which takes long
+/// enough for large meshes to be worth doing off the main thread, as the
+/// `AssetServer` does. Only the upload is left for `Mesh::from_prepared`.
+pub struct PreparedMesh {
+    pub data: MeshData,
+    pub lods: Vec<LodData>,
+    pub bvh: Bvh,
+}
+
+impl PreparedMesh {
+    /// Fills in missing attributes and optimises the mesh with
+    /// `mesh_processing::process`, then builds up to `lod_levels` levels of
+    /// detail and the BVH.
+    pub fn new(mut data: MeshData, lod_levels: usize) -> Self {
+        mesh_processing::process(&mut data);
+
+        Self {
+            lods: simplify::lod_chain(&data, lod_levels),
+            bvh: Bvh::build(&data.vertices, &data.indices),
+            data,
+        }
+    }
+}
+
+/// Triangle list geometry on the CPU, before it becomes a `Mesh`.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    pub morph: MorphTargets,
+}
+
+impl MeshData {
+    /// Appends the geometry of `other`, offsetting its indices. Vertices get
+    /// zero deltas for the targets only the other mesh has, and the weights
+    /// and node of `self` are kept unless it had no targets.
+    pub fn extend(&mut self, other: &MeshData) {
+        let offset = self.vertices.len();
+
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|index| vertex_index(offset + *index as usize)));
+
+        if other.morph.targets.is_empty() && self.morph.targets.is_empty() {
+            return;
+        }
+
+        if self.morph.targets.is_empty() {
+            self.morph.weights = other.morph.weights.clone();
+            self.morph.node = other.morph.node;
+        }
+
+        let count = self.morph.targets.len().max(other.morph.targets.len());
+
+        self.morph.targets.resize(count, vec![MorphDelta::default(); offset]);
+        self.morph.weights.resize(count, 0.0);
+
+        self.morph.targets.iter_mut().enumerate().for_each(|(target, deltas)| match other.morph.targets.get(target) {
+            Some(other_deltas) => deltas.extend_from_slice(other_deltas),
+            None => deltas.resize(self.vertices.len(), MorphDelta::default()),
+        });
+    }
+}
+
+/// glTF meshes can't have more targets than this, as the weights of a mesh
+/// are a fixed size uniform. It matches the `MorphWeights` of `morph.wgsl`.
+pub const MAX_MORPH_TARGETS: usize = 64;
+
+/// Alternative shapes of a mesh, blended in by weights as blend shapes.
+#[derive(Debug, Clone, Default)]
+pub struct MorphTargets {
+    /// A delta for every vertex of the mesh, for each target.
+    pub targets: Vec<Vec<MorphDelta>>,
+    /// Weight of each target when not animated.
+    pub weights: Vec<f32>,
+    /// Node of the model's skeleton whose animated weights drive the targets.
+    pub node: Option<usize>,
+}
+
+impl MorphTargets {
+    /// The targets of the vertices at `sources`, in that order, for meshes
+    /// whose vertices were rebuilt from them.
+    pub fn gather(&self, sources: &[usize]) -> Self {
+        Self {
+            targets: self.targets.iter().map(|deltas| sources.iter().map(|source| deltas[*source]).collect()).collect(),
+            weights: self.weights.clone(),
+            node: self.node,
+        }
+    }
+
+    /// The box the vertices stay within for any weights from 0 to 1, or
+    /// `None` without targets.
+    pub fn bounds(&self, vertices: &[Vertex]) -> Option<Aabb> {
+        if self.targets.is_empty() {
+            return None;
+        }
+
+        Aabb::from_points(vertices.iter().enumerate().flat_map(|(index, vertex)| {
+            let (low, high) = self.targets.iter().fold((vertex.position, vertex.position), |(low, high), deltas| {
+                let delta = deltas[index].position;
+
+                ([0, 1, 2].map(|axis| low[axis] + delta[axis].min(0.0)), [0, 1, 2].map(|axis| high[axis] + delta[axis].max(0.0)))
+            });
+
+            [low.into(), high.into()]
+        }))
+    }
+}
+
+/// Meshes use 16-bit indices, so a mesh can't have more than 65536 vertices.
+pub fn vertex_index(index: usize) -> u16 {
+    u16::try_from(index).expect("Meshes can't have more than 65536 vertices")
 }
 
 
@@ -34,23 +255,183 @@ pub struct Mesh {
 
 // World
 pub struct World {
-    pub models: Vec<Model>
+    pub models: Vec<Model>,
+    pub lights: Vec<PointLight>,
+    /// Indices into `models` of the selected models.
+    pub selection: Vec<usize>,
 }
 
 impl World {
     pub fn new(ctx: &mut Context) -> Self {
         let mut models = vec![];
 
+        // Editing textures/checker.png swaps the cube's texture while running.
+        let checker = MaterialBuilder::new(ctx, "checker")
+            .with_base_color_texture("textures/checker.png")
+            .build();
+
         let model = ModelBuilder::new(ctx, "")
+            .with_material(checker)
             .build();
 
         models.push(model);
 
+        // A ring of generated shapes around the cube.
+        let shapes = [
+            primitives::uv_sphere(1.0, 32, 16),
+            primitives::icosphere(1.0, 3),
+            primitives::cylinder(0.8, 2.0, 32),
+            primitives::cone(1.0, 2.0, 32),
+            primitives::torus(0.8, 0.3, 48, 16),
+            primitives::capsule(0.6, 1.0, 32, 8),
+        ];
+
+        let count = shapes.len();
+
+        shapes.into_iter().enumerate().for_each(|(index, shape)| {
+            let (sin, cos) = (index as f32 / count as f32 * std::f32::consts::TAU).sin_cos();
+
+            models.push(ModelBuilder::new(ctx, "")
+                .with_mesh(shape)
+                .with_position((5.0 * sin, 0.0, 5.0 * cos).into())
+                .build());
+        });
+
+        let lights = vec![
+            PointLight::new((4.0, 4.0, 4.0).into(), (1.0, 0.95, 0.9).into(), 40.0, 20.0),
+            PointLight::new((-4.0, 2.0, -3.0).into(), (0.4, 0.5, 1.0).into(), 20.0, 15.0),
+        ];
+
         Self {
-            models
+            models,
+            lights,
+            selection: Vec::new(),
         }
     }
 
+    /// Advances the animations of every animated model by `delta` seconds.
+    pub fn animate(&mut self, ctx: &Context, delta: f32) {
+        self.models.iter_mut().for_each(|model| model.animate(ctx, delta));
+    }
+
+    pub fn refresh_pipelines(&mut self, ctx: &Context) {
+        self.models.iter_mut().for_each(|model| model.refresh_pipelines(ctx));
+    }
+
+    /// Swaps newly loaded or reloaded assets into the models built from them,
+    /// and hands models imported by `ModelBuilder::build` to the server once
+    /// their file changes. Models whose asset failed to load stay
+    /// placeholders, drawn magenta unless they were given a material, and a
+    /// failed reload keeps the previous version.
+    ///
+    /// Material files and material textures are applied the same way, as
+    /// they load and reload.
+    pub fn update_assets(&mut self, ctx: &mut Context, server: &mut AssetServer) {
+        self.models.iter_mut().for_each(|model| {
+            let Some(source) = model.source.as_mut() else {
+                return;
+            };
+
+            if source.handle.is_none() {
+                if !source.watched {
+                    server.watch(&source.dependencies);
+                    source.watched = true;
+                }
+
+                if !server.changed(&source.dependencies) {
+                    return;
+                }
+
+                log::info!("Reloading {}", source.file_path);
+
+                source.handle = Some(server.load_model(&source.file_path));
+            }
+
+            let Some(handle) = source.handle.clone() else {
+                return;
+            };
+
+            let version = server.version(&handle);
+
+            if source.version == Some(version) {
+                return;
+            }
+
+            match server.state(&handle) {
+                LoadState::Loading => {},
+                LoadState::Loaded => {
+                    if let Some(asset) = server.get(&handle) {
+                        model.apply_asset(ctx, &asset, version);
+                    }
+                },
+                LoadState::Failed(_) => {
+                    if source.use_material {
+                        model.material = MaterialBuilder::new(ctx, "asset_failed")
+                            .with_base_color([1.0, 0.0, 1.0, 1.0])
+                            .build();
+                    }
+
+                    if let Some(source) = model.source.as_mut() {
+                        source.version = Some(version);
+                    }
+                },
+            }
+        });
+
+        self.models.iter_mut().for_each(|model| {
+            let Some(source) = model.material_source.as_mut() else {
+                return;
+            };
+
+            let version = server.version(&source.handle);
+
+            if source.version == Some(version) || server.state(&source.handle) != LoadState::Loaded {
+                return;
+            }
+
+            if let Some(material) = server.get(&source.handle) {
+                model.material = material;
+                source.version = Some(version);
+            }
+        });
+
+        // Models sharing a material keep sharing it once it is rebound.
+        let mut rebound: HashMap<*const Material, Rc<Material>> = HashMap::new();
+
+        self.models.iter_mut().for_each(|model| {
+            let key = Rc::as_ptr(&model.material);
+
+            if let Some(material) = rebound.get(&key) {
+                model.material = material.clone();
+                return;
+            }
+
+            if let Some(material) = model.material.bind_texture(ctx, server) {
+                rebound.insert(key, material.clone());
+                model.material = material;
+            }
+        });
+    }
+
+    /// The closest triangle of any model the ray hits, tested on the CPU
+    /// against each mesh's BVH. The BVHs are built from the imported
+    /// vertices, so skinned and morphed meshes are hit in their rest pose,
+    /// not as they are drawn.
+    pub fn raycast(&self, ray: Ray) -> Option<Hit> {
+        closest_hit(ray, self.models.iter().map(|model| RaycastModel {
+            model_matrix: model.model_matrix(),
+            bounds: model.world_bounds(),
+            meshes: model.meshes
+                .iter()
+                .map(|mesh| RaycastMesh {
+                    vertices: &mesh.vertices,
+                    indices: &mesh.indices,
+                    bvh: &mesh.bvh,
+                })
+                .collect(),
+        }))
+    }
+
     pub fn update(&mut self) {
         todo!()
     }   